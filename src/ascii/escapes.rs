@@ -1,8 +1,13 @@
 use std::io::{self, Write, Read};
 use crate::logger::log_message;
+use crate::screen::modes::{KeyboardFlags, Modes, MouseTracking};
+use super::input::{InputEvent, Key, KeyEvent, Modifiers, MouseAction, MouseButton, MouseEvent};
 
 const ESC_CHAR: char= '\x1b';
 const CSI_CHAR: char= '[';
+const OSC_CHAR: char= ']';
+const BEL_CHAR: char= '\x07';
+const OSC_MAX_LEN: usize = 4096;
 macro_rules! esc {
     ($name:literal) => {
         {format!("{}{}",ESC_CHAR, $name)}
@@ -19,12 +24,20 @@ macro_rules! escC {
         {format!("{}{}{}", ESC_CHAR, CSI_CHAR, $name)}
     };
 }
+macro_rules! escO {
+    ($name:literal) => {
+        {format!("{}{}{}{}", ESC_CHAR, OSC_CHAR, $name, BEL_CHAR)}
+    };
+    ($name:expr) => {
+        {format!("{}{}{}{}", ESC_CHAR, OSC_CHAR, $name, BEL_CHAR)}
+    };
+}
 pub trait ParsableSequence<T> {
     fn parse_sequence<I>(chars: &mut std::iter::Peekable<I>) -> Vec<T> where I: Iterator<Item = char>;
     fn parse_writer(reader: &mut Box<dyn Read + Send>) -> Vec<T>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Sequence {
     Text(char),
     Escape(Vec<Escape>),
@@ -35,15 +48,15 @@ impl ParsableSequence<Sequence> for Sequence{
         let mut parsed_seq = Vec::new();
 
         while let Some(&c) = chars.peek() {
-            if c as char == ESC_CHAR {
+            if c == ESC_CHAR {
                 let escapes = Escape::parse_sequence(chars);
-                if escapes.len()>0 {
+                if !escapes.is_empty() {
                     parsed_seq.push(Sequence::Escape(escapes));
                 }
             } else {
                 parsed_seq.push(Sequence::Text(c));
+                chars.next();
             }
-            chars.next();
         }
 
         parsed_seq
@@ -53,19 +66,18 @@ impl ParsableSequence<Sequence> for Sequence{
         let mut parsed_seq = Vec::new();
         let mut s: [u8; 1] = [0;1];
 
-        match reader.read_exact(&mut s) {
-            Ok(_) => {
-                let c = s[0] as char;
-                if c as char == ESC_CHAR {
-                    let escapes = Escape::parse_writer(reader);
-                    if escapes.len()>0 {
-                        parsed_seq.push(Sequence::Escape(escapes));
-                    }
-                } else {
-                    parsed_seq.push(Sequence::Text(c));
+        if reader.read_exact(&mut s).is_ok() {
+            let c = s[0] as char;
+            if c == ESC_CHAR {
+                let escapes = Escape::parse_writer(reader);
+                if !escapes.is_empty() {
+                    parsed_seq.push(Sequence::Escape(escapes));
                 }
-            },
-            Err(_) => {}
+            } else if s[0] >= 0x80 {
+                parsed_seq.push(Sequence::Text(read_utf8_char(s[0], reader)));
+            } else {
+                parsed_seq.push(Sequence::Text(c));
+            }
         }
         log_message(&format!("{:?}", parsed_seq));
 
@@ -73,6 +85,105 @@ impl ParsableSequence<Sequence> for Sequence{
     }
 }
 
+impl Sequence {
+    /// Parses the sequence at the start of `bytes` and says how many bytes
+    /// it used. `None` means the bytes stop partway through a sequence and
+    /// more input is needed. Sequences that parse to nothing, like a
+    /// cancelled CSI, give `Some((None, used))`.
+    pub fn parse_prefix(bytes: &[u8]) -> Option<(Option<Sequence>, usize)> {
+        if *bytes.first()? != ESC_CHAR as u8 {
+            let (c, len) = decode_utf8(bytes)?;
+            return Some((Some(Sequence::Text(c)), len));
+        }
+
+        let mut used = 1;
+        let mut incomplete = false;
+        let escapes = EscapeReader::new(|| {
+            match decode_utf8(&bytes[used..]) {
+                Some((c, len)) => {
+                    used += len;
+                    Some(c)
+                },
+                None => {
+                    incomplete = true;
+                    None
+                }
+            }
+        }).parse();
+        if incomplete {
+            return None;
+        }
+
+        if escapes.is_empty() {
+            Some((None, used))
+        } else {
+            Some((Some(Sequence::Escape(escapes)), used))
+        }
+    }
+}
+
+/// Decodes the char at the start of `bytes`. `None` when the bytes end
+/// before the char does, malformed input decodes to U+FFFD.
+pub(crate) fn decode_utf8(bytes: &[u8]) -> Option<(char, usize)> {
+    let len = match *bytes.first()? {
+        0x00..=0x7F => {1},
+        0xC0..=0xDF => {2},
+        0xE0..=0xEF => {3},
+        0xF0..=0xF7 => {4},
+        _ => {return Some((char::REPLACEMENT_CHARACTER, 1))}
+    };
+    let available = &bytes[1..len.min(bytes.len())];
+    if available.iter().any(|b| b & 0xC0 != 0x80) {
+        return Some((char::REPLACEMENT_CHARACTER, 1));
+    }
+    if bytes.len() < len {
+        return None;
+    }
+
+    match std::str::from_utf8(&bytes[..len]) {
+        Ok(s) => {s.chars().next().map(|c| (c, len))},
+        Err(_) => {Some((char::REPLACEMENT_CHARACTER, 1))}
+    }
+}
+
+/// Reads the continuation bytes of a multi-byte utf8 char whose leading
+/// byte has already been consumed. Malformed input decodes to U+FFFD.
+fn read_utf8_char(lead: u8, reader: &mut Box<dyn Read + Send>) -> char {
+    let len = match lead {
+        0xC0..=0xDF => {2},
+        0xE0..=0xEF => {3},
+        0xF0..=0xF7 => {4},
+        _ => {return char::REPLACEMENT_CHARACTER}
+    };
+    let mut bytes = [lead, 0, 0, 0];
+    if reader.read_exact(&mut bytes[1..len]).is_err() {
+        return char::REPLACEMENT_CHARACTER;
+    }
+
+    match std::str::from_utf8(&bytes[..len]) {
+        Ok(s) => {s.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER)},
+        Err(_) => {char::REPLACEMENT_CHARACTER}
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
 pub struct EscapeWriter<'a, T: Write> {
     escapes: Vec<Escape>,
     writer: &'a mut T,
@@ -100,7 +211,7 @@ impl<'a, T: Write> EscapeWriter<'a, T> {
 
     pub fn send_all(&mut self) -> io::Result<()>{
         while let Some(escape) = self.escapes.pop() {
-            self.writer.write(&escape.into_bytes())?;
+            self.writer.write_all(&escape.into_bytes())?;
         }
 
         Ok(())
@@ -108,7 +219,7 @@ impl<'a, T: Write> EscapeWriter<'a, T> {
 
     pub fn send_all_and_flush(&mut self) -> io::Result<()>{
         while let Some(escape) = self.escapes.pop() {
-            self.writer.write(&escape.into_bytes())?;
+            self.writer.write_all(&escape.into_bytes())?;
         }
         self.writer.flush()?;
 
@@ -116,7 +227,7 @@ impl<'a, T: Write> EscapeWriter<'a, T> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Escape {
     ResetAllModes,                // 0m
     ZeroCursor,                   // H
@@ -182,6 +293,8 @@ pub enum Escape {
     SetBackgroundBriWhite,        // 107m
     SetForgroundCustomColor(u8),  // 38;5;{id}m
     SetBackgroundCustomColor(u8), // 48;5;{id}m
+    SetForgroundRgbColor(u8, u8, u8),  // 38;2;{r};{g};{b}m
+    SetBackgroundRgbColor(u8, u8, u8), // 48;2;{r};{g};{b}m
     ResetBold,                    // 22m
     ResetDim,                     // 22m
     ResetItalic,                  // 23m
@@ -211,10 +324,56 @@ pub enum Escape {
     ResetScreenSet(u8),           //={val}l
     SetCursorVisible,             //?25h
     SetCursorInvisible,           //?25l
-    RestoreScreen,                //?47h
-    SaveScreen,                   //?47l
+    SaveScreen,                   //?47h
+    RestoreScreen,                //?47l
     EnterAltScreen,               //?1049h
     ExitAltScreen,                //?1049l
+    PromptStart,                  //]133;A
+    CommandStart,                 //]133;B
+    CommandExecuted,              //]133;C
+    CommandFinished(Option<i32>), //]133;D;{exit}
+    CopyToClipboard(String),      //]52;c;{base64}
+    CurrentDirectory(String),     //]7;file://{host}{path}
+    SetTitle(String),             //]2;{title}
+    PushTitle,                    //22;0t
+    PopTitle,                     //23;0t
+    EnableAppCursor,              //?1h
+    DisableAppCursor,             //?1l
+    EnableAutoWrap,               //?7h
+    DisableAutoWrap,              //?7l
+    EnableBracketedPaste,         //?2004h
+    DisableBracketedPaste,        //?2004l
+    EnableMouseClicks,            //?1000h
+    DisableMouseClicks,           //?1000l
+    EnableMouseDrag,              //?1002h
+    DisableMouseDrag,             //?1002l
+    EnableMouseMotion,            //?1003h
+    DisableMouseMotion,           //?1003l
+    EnableSgrMouse,               //?1006h
+    DisableSgrMouse,              //?1006l
+    EnableFocusReports,           //?1004h
+    DisableFocusReports,          //?1004l
+    EnableAppKeypad,              // =
+    DisableAppKeypad,             // >
+    SetModifyOtherKeys(u32),      //>4;{level}m
+    PushKeyboardFlags(u32),       //>{flags}u
+    PopKeyboardFlags(u32),        //<{count}u
+    SetKeyboardFlags((u32, u32)), //={flags};{mode}u
+    QueryKeyboardFlags,           //?u
+    Index,                        // D
+    NextLine,                     // E
+    ResetTerminal,                // c
+    ScreenAlignment,              // #8
+    SetScrollRegion((u32, u32)),  // top;bottomr
+    InsertLines(u32),             // #L
+    DeleteLines(u32),             // #M
+    InsertChars(u32),             // #@
+    DeleteChars(u32),             // #P
+    EraseChars(u32),              // #X
+    ScrollUp(u32),                // #S
+    ScrollDown(u32),              // #T
+    CursorToLine(u32),            // #d
+    Unhandled(String),            // anything else, kept as received
 }
 
 impl Escape {
@@ -305,10 +464,38 @@ impl Escape {
             Escape::Set320_200ColorScreen256     => {Some(b"\x1b[=19h")},  
             Escape::SetCursorVisible             => {Some(b"\x1b[?25h")},          
             Escape::SetCursorInvisible           => {Some(b"\x1b[?25l")},        
-            Escape::RestoreScreen                => {Some(b"\x1b[?47h")},             
-            Escape::SaveScreen                   => {Some(b"\x1b[?47l")},                
+            Escape::SaveScreen                   => {Some(b"\x1b[?47h")},
+            Escape::RestoreScreen                => {Some(b"\x1b[?47l")},
             Escape::EnterAltScreen               => {Some(b"\x1b[?1049h")},           
             Escape::ExitAltScreen                => {Some(b"\x1b[?1049l")},          
+            Escape::PromptStart                  => {Some(b"\x1b]133;A\x07")},
+            Escape::CommandStart                 => {Some(b"\x1b]133;B\x07")},
+            Escape::CommandExecuted              => {Some(b"\x1b]133;C\x07")},
+            Escape::EnableAppCursor              => {Some(b"\x1b[?1h")},
+            Escape::DisableAppCursor             => {Some(b"\x1b[?1l")},
+            Escape::EnableAutoWrap               => {Some(b"\x1b[?7h")},
+            Escape::DisableAutoWrap              => {Some(b"\x1b[?7l")},
+            Escape::EnableBracketedPaste         => {Some(b"\x1b[?2004h")},
+            Escape::DisableBracketedPaste        => {Some(b"\x1b[?2004l")},
+            Escape::EnableMouseClicks            => {Some(b"\x1b[?1000h")},
+            Escape::DisableMouseClicks           => {Some(b"\x1b[?1000l")},
+            Escape::EnableMouseDrag              => {Some(b"\x1b[?1002h")},
+            Escape::DisableMouseDrag             => {Some(b"\x1b[?1002l")},
+            Escape::EnableMouseMotion            => {Some(b"\x1b[?1003h")},
+            Escape::DisableMouseMotion           => {Some(b"\x1b[?1003l")},
+            Escape::EnableSgrMouse               => {Some(b"\x1b[?1006h")},
+            Escape::DisableSgrMouse              => {Some(b"\x1b[?1006l")},
+            Escape::EnableFocusReports           => {Some(b"\x1b[?1004h")},
+            Escape::DisableFocusReports          => {Some(b"\x1b[?1004l")},
+            Escape::PushTitle                    => {Some(b"\x1b[22;0t")},
+            Escape::PopTitle                     => {Some(b"\x1b[23;0t")},
+            Escape::EnableAppKeypad              => {Some(b"\x1b=")},
+            Escape::DisableAppKeypad             => {Some(b"\x1b>")},
+            Escape::QueryKeyboardFlags           => {Some(b"\x1b[?u")},
+            Escape::Index                        => {Some(b"\x1bD")},
+            Escape::NextLine                     => {Some(b"\x1bE")},
+            Escape::ResetTerminal                => {Some(b"\x1bc")},
+            Escape::ScreenAlignment              => {Some(b"\x1b#8")},
             _ => {None}
         }
     }
@@ -379,6 +566,8 @@ impl Escape {
             Escape::SetBackgroundBriWhite        => {escC!("107m")},  
             Escape::SetForgroundCustomColor(i)   => {escC!(format!("38;5;{}m",i))}, 
             Escape::SetBackgroundCustomColor(i)  => {escC!(format!("48;5;{}m",i))},
+            Escape::SetForgroundRgbColor(r, g, b)  => {escC!(format!("38;2;{};{};{}m", r, g, b))},
+            Escape::SetBackgroundRgbColor(r, g, b) => {escC!(format!("48;2;{};{};{}m", r, g, b))},
             Escape::ResetBold                    => {escC!("22m")},         
             Escape::ResetDim                     => {escC!("22m")},          
             Escape::ResetItalic                  => {escC!("23m")},       
@@ -408,625 +597,861 @@ impl Escape {
             Escape::ResetScreenSet(i)            => {escC!(format!("={}l",i))},        
             Escape::SetCursorVisible             => {escC!("?25h")},          
             Escape::SetCursorInvisible           => {escC!("?25l")},        
-            Escape::RestoreScreen                => {escC!("?47h")},             
-            Escape::SaveScreen                   => {escC!("?47l")},                
+            Escape::SaveScreen                   => {escC!("?47h")},
+            Escape::RestoreScreen                => {escC!("?47l")},
             Escape::EnterAltScreen               => {escC!("?1049h")},           
             Escape::ExitAltScreen                => {escC!("?1049l")},          
+            Escape::PromptStart                  => {escO!("133;A")},
+            Escape::CommandStart                 => {escO!("133;B")},
+            Escape::CommandExecuted              => {escO!("133;C")},
+            Escape::CommandFinished(None)        => {escO!("133;D")},
+            Escape::CommandFinished(Some(i))     => {escO!(format!("133;D;{}", i))},
+            Escape::CopyToClipboard(text)        => {escO!(format!("52;c;{}", base64(text.as_bytes())))},
+            Escape::CurrentDirectory(path)       => {escO!(format!("7;file://{}", percent_encode(path)))},
+            Escape::SetTitle(title)              => {escO!(format!("2;{}", title))},
+            Escape::EnableAppCursor              => {escC!("?1h")},
+            Escape::DisableAppCursor             => {escC!("?1l")},
+            Escape::EnableAutoWrap               => {escC!("?7h")},
+            Escape::DisableAutoWrap              => {escC!("?7l")},
+            Escape::EnableBracketedPaste         => {escC!("?2004h")},
+            Escape::DisableBracketedPaste        => {escC!("?2004l")},
+            Escape::EnableMouseClicks            => {escC!("?1000h")},
+            Escape::DisableMouseClicks           => {escC!("?1000l")},
+            Escape::EnableMouseDrag              => {escC!("?1002h")},
+            Escape::DisableMouseDrag             => {escC!("?1002l")},
+            Escape::EnableMouseMotion            => {escC!("?1003h")},
+            Escape::DisableMouseMotion           => {escC!("?1003l")},
+            Escape::EnableSgrMouse               => {escC!("?1006h")},
+            Escape::DisableSgrMouse              => {escC!("?1006l")},
+            Escape::EnableFocusReports           => {escC!("?1004h")},
+            Escape::DisableFocusReports          => {escC!("?1004l")},
+            Escape::PushTitle                    => {escC!("22;0t")},
+            Escape::PopTitle                     => {escC!("23;0t")},
+            Escape::EnableAppKeypad              => {esc!("=")},
+            Escape::DisableAppKeypad             => {esc!(">")},
+            Escape::SetModifyOtherKeys(0)        => {escC!(">4m")},
+            Escape::SetModifyOtherKeys(level)    => {escC!(format!(">4;{}m", level))},
+            Escape::PushKeyboardFlags(flags)     => {escC!(format!(">{}u", flags))},
+            Escape::PopKeyboardFlags(count)      => {escC!(format!("<{}u", count))},
+            Escape::SetKeyboardFlags((flags, mode)) => {escC!(format!("={};{}u", flags, mode))},
+            Escape::QueryKeyboardFlags           => {escC!("?u")},
+            Escape::Index                        => {esc!("D")},
+            Escape::NextLine                     => {esc!("E")},
+            Escape::ResetTerminal                => {esc!("c")},
+            Escape::ScreenAlignment              => {esc!("#8")},
+            Escape::SetScrollRegion((0, 0))      => {escC!("r")},
+            Escape::SetScrollRegion((top, 0))    => {escC!(format!("{}r", top))},
+            Escape::SetScrollRegion((top, bot))  => {escC!(format!("{};{}r", top, bot))},
+            Escape::InsertLines(i)               => {escC!(format!("{}L", i))},
+            Escape::DeleteLines(i)               => {escC!(format!("{}M", i))},
+            Escape::InsertChars(i)               => {escC!(format!("{}@", i))},
+            Escape::DeleteChars(i)               => {escC!(format!("{}P", i))},
+            Escape::EraseChars(i)                => {escC!(format!("{}X", i))},
+            Escape::ScrollUp(i)                  => {escC!(format!("{}S", i))},
+            Escape::ScrollDown(i)                => {escC!(format!("{}T", i))},
+            Escape::CursorToLine(i)              => {escC!(format!("{}d", i))},
+            Escape::Unhandled(raw)               => {raw.clone()},
         };
 
         strs.into_bytes()
     }
 }
 
+/// Bytes the program in the pane gets for `event`, encoded the way the
+/// modes it switched on ask for.
+pub fn encode_input(event: &InputEvent, modes: &Modes) -> Vec<u8> {
+    match event {
+        InputEvent::Key(key) => {encode_key(key, modes)},
+        InputEvent::Mouse(mouse) => {encode_mouse(mouse, modes)},
+        InputEvent::FocusIn if modes.focus_reports => {escC!("I").into_bytes()},
+        InputEvent::FocusOut if modes.focus_reports => {escC!("O").into_bytes()},
+        InputEvent::FocusIn | InputEvent::FocusOut => {Vec::new()},
+        InputEvent::Paste(text) if modes.bracketed_paste => {
+            let mut bytes = escC!("200~").into_bytes();
+            bytes.append(&mut strip_escapes(text));
+            bytes.extend_from_slice(escC!("201~").as_bytes());
+            bytes
+        },
+        InputEvent::Paste(text) => {strip_escapes(text)},
+        InputEvent::Unknown(bytes) => {bytes.clone()}
+    }
+}
+
+/// Pasted text without its escape sequences, so a paste can not end the
+/// bracketed paste early or drive the program it is pasted into.
+pub fn strip_escapes(text: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(text.len());
+    let mut bytes = text.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        if byte != 0x1b {
+            stripped.push(byte);
+            continue;
+        }
+        match bytes.next_if(|byte| (0x20..0x7f).contains(byte)) {
+            // CSI, up to its final byte
+            Some(b'[') => {
+                for byte in bytes.by_ref() {
+                    if (0x40..=0x7e).contains(&byte) {
+                        break;
+                    }
+                }
+            },
+            // OSC and the other strings, up to BEL or ST
+            Some(b']' | b'P' | b'X' | b'^' | b'_') => {
+                while let Some(byte) = bytes.next() {
+                    if byte == 0x07 || (byte == 0x1b && bytes.next_if_eq(&b'\\').is_some()) {
+                        break;
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    stripped
+}
+
+/// Encodes a key press. The kitty keyboard protocol wins over
+/// modifyOtherKeys, which wins over the legacy xterm encoding, for the
+/// keys each of them covers.
+pub fn encode_key(event: &KeyEvent, modes: &Modes) -> Vec<u8> {
+    let flags = modes.keyboard.current();
+    if flags != 0 {
+        if let Some(bytes) = encode_kitty_key(event, flags) {
+            return bytes;
+        }
+    }
+    if modes.modify_other_keys > 0 {
+        if let Some(bytes) = encode_modified_key(event, modes.modify_other_keys) {
+            return bytes;
+        }
+    }
+    encode_legacy_key(event, modes)
+}
+
+/// Mouse report for the program, empty when the tracking mode it chose
+/// does not report this kind of event. SGR encoded when it turned on 1006,
+/// else in the legacy encoding which can not reach past column 223.
+pub fn encode_mouse(event: &MouseEvent, modes: &Modes) -> Vec<u8> {
+    let wanted = match event.action {
+        MouseAction::Press | MouseAction::Release => {modes.mouse >= MouseTracking::Clicks},
+        MouseAction::Drag => {modes.mouse >= MouseTracking::Drag},
+        MouseAction::Move => {modes.mouse >= MouseTracking::Motion}
+    };
+    if !wanted {
+        return Vec::new();
+    }
+
+    let mut code = match event.button {
+        Some(MouseButton::Left) => {0},
+        Some(MouseButton::Middle) => {1},
+        Some(MouseButton::Right) => {2},
+        None => {3},
+        Some(MouseButton::WheelUp) => {64},
+        Some(MouseButton::WheelDown) => {65},
+        Some(MouseButton::WheelLeft) => {66},
+        Some(MouseButton::WheelRight) => {67},
+        Some(MouseButton::Other(n)) => {128 + (n.saturating_sub(8) & 3) as u32}
+    };
+    code += 4 * event.modifiers.shift as u32 + 8 * event.modifiers.alt as u32 + 16 * event.modifiers.ctrl as u32;
+    if matches!(event.action, MouseAction::Drag | MouseAction::Move) {
+        code += 32;
+    }
+    if !modes.sgr_mouse {
+        // no button numbers on release, and no release at all for wheels
+        if event.action == MouseAction::Release {
+            code = (code & !0b1100_0011) | 3;
+            if matches!(event.button, Some(MouseButton::WheelUp | MouseButton::WheelDown | MouseButton::WheelLeft | MouseButton::WheelRight)) {
+                return Vec::new();
+            }
+        }
+        let (col, row) = (event.col as u32 + 33, event.row as u32 + 33);
+        if code + 32 > 255 || col > 255 || row > 255 {
+            return Vec::new();
+        }
+        let mut bytes = escC!("M").into_bytes();
+        bytes.extend_from_slice(&[(code + 32) as u8, col as u8, row as u8]);
+        return bytes;
+    }
+
+    let action = match event.action {
+        MouseAction::Release => {'m'},
+        _ => {'M'}
+    };
+    escC!(format!("<{};{};{}{}", code, event.col as u32 + 1, event.row as u32 + 1, action)).into_bytes()
+}
+
+/// The C0 control code Ctrl turns `c` into, xterm's table.
+fn ctrl_byte(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => {Some(c as u8 - b'a' + 1)},
+        'A'..='Z' => {Some(c as u8 - b'A' + 1)},
+        '@' | ' ' | '2' => {Some(0)},
+        '[' | '3' => {Some(0x1b)},
+        '\\' | '4' => {Some(0x1c)},
+        ']' | '5' => {Some(0x1d)},
+        '^' | '6' | '~' => {Some(0x1e)},
+        '_' | '7' | '/' => {Some(0x1f)},
+        '8' | '?' => {Some(0x7f)},
+        _ => {None}
+    }
+}
+
+/// Key code the kitty protocol and modifyOtherKeys use for keys that are
+/// text or C0 controls.
+fn key_code(key: Key) -> Option<u32> {
+    let code = match key {
+        Key::Char(c) => {c as u32},
+        Key::Enter => {13},
+        Key::Tab => {9},
+        Key::Backspace => {127},
+        Key::Escape => {27},
+        _ => {return None}
+    };
+    Some(code)
+}
+
+fn encode_kitty_key(event: &KeyEvent, flags: u32) -> Option<Vec<u8>> {
+    let mut modifiers = event.modifiers;
+    let code = match event.key {
+        // the code is always of the unshifted key
+        Key::Char(c) if c.is_ascii_uppercase() => {
+            modifiers.shift = true;
+            c.to_ascii_lowercase() as u32
+        },
+        Key::Keypad(c) => {
+            match c {
+                '0'..='9' => {57399 + c as u32 - '0' as u32},
+                '.' => {57409},
+                '/' => {57410},
+                '*' => {57411},
+                '-' => {57412},
+                '+' => {57413},
+                '=' => {57415},
+                _ => {57416}
+            }
+        },
+        Key::KeypadEnter => {57414},
+        // cursor and function keys keep their legacy sequences
+        key => {key_code(key)?}
+    };
+
+    if flags & KeyboardFlags::REPORT_ALL_KEYS == 0 {
+        let text_key = matches!(event.key, Key::Char(_));
+        let control_key = matches!(event.key, Key::Enter | Key::Tab | Key::Backspace);
+        let only_shift = !(modifiers.ctrl || modifiers.alt || modifiers.meta);
+        if (text_key && only_shift) || (control_key && event.modifiers.is_empty()) {
+            return None;
+        }
+    }
+
+    let text = match modifiers.param() {
+        1 => {format!("{}u", code)},
+        param => {format!("{};{}u", code, param)}
+    };
+    Some(escC!(text).into_bytes())
+}
+
+/// xterm's modifyOtherKeys, CSI 27 ; modifiers ; code ~ for modified keys.
+/// Level 1 only uses it for keys with no legacy encoding of their own.
+fn encode_modified_key(event: &KeyEvent, level: u32) -> Option<Vec<u8>> {
+    let modifiers = event.modifiers;
+    let code = key_code(event.key)?;
+    if modifiers.is_empty() || (modifiers == Modifiers::SHIFT && matches!(event.key, Key::Char(_))) {
+        return None;
+    }
+    if level == 1 {
+        let legacy = match event.key {
+            Key::Char(c) if modifiers.ctrl => {ctrl_byte(c).is_some() && !modifiers.shift && !modifiers.meta},
+            Key::Tab => {modifiers == Modifiers::SHIFT},
+            _ => {!modifiers.ctrl && !modifiers.shift && !modifiers.meta}
+        };
+        if legacy {
+            return None;
+        }
+    }
+
+    Some(escC!(format!("27;{};{}~", modifiers.param(), code)).into_bytes())
+}
+
+fn encode_legacy_key(event: &KeyEvent, modes: &Modes) -> Vec<u8> {
+    let modifiers = event.modifiers;
+    // keys with a sequence of their own carry the modifiers in it, text
+    // keys get ESC in front for Alt
+    let cursor = |letter: char, ss3: bool| -> String {
+        if !modifiers.is_empty() {
+            escC!(format!("1;{}{}", modifiers.param(), letter))
+        } else if ss3 {
+            esc!(format!("O{}", letter))
+        } else {
+            escC!(letter)
+        }
+    };
+    let tilde = |number: u32| -> String {
+        match modifiers.param() {
+            1 => {escC!(format!("{}~", number))},
+            param => {escC!(format!("{};{}~", number, param))}
+        }
+    };
+
+    let text = match event.key {
+        Key::Up => {cursor('A', modes.app_cursor)},
+        Key::Down => {cursor('B', modes.app_cursor)},
+        Key::Right => {cursor('C', modes.app_cursor)},
+        Key::Left => {cursor('D', modes.app_cursor)},
+        Key::Home => {cursor('H', modes.app_cursor)},
+        Key::End => {cursor('F', modes.app_cursor)},
+        Key::F(n @ 1..=4) => {cursor((b'P' + n - 1) as char, true)},
+        Key::Insert => {tilde(2)},
+        Key::Delete => {tilde(3)},
+        Key::PageUp => {tilde(5)},
+        Key::PageDown => {tilde(6)},
+        Key::F(n @ 5..=20) => {
+            let number = [15, 17, 18, 19, 20, 21, 23, 24, 25, 26, 28, 29, 31, 32, 33, 34][n as usize - 5];
+            tilde(number)
+        },
+        Key::F(_) => {return Vec::new()},
+        Key::Tab if modifiers.shift => {escC!("Z")},
+        Key::Keypad(c) if modes.app_keypad && modifiers.is_empty() => {
+            let letter = match c {
+                '0'..='9' => {(b'p' + c as u8 - b'0') as char},
+                '*' => {'j'},
+                '+' => {'k'},
+                ',' => {'l'},
+                '-' => {'m'},
+                '.' => {'n'},
+                '/' => {'o'},
+                _ => {'X'}
+            };
+            esc!(format!("O{}", letter))
+        },
+        Key::KeypadEnter if modes.app_keypad && modifiers.is_empty() => {esc!("OM")},
+        key => {
+            let mut text = match modifiers.alt {
+                true => {String::from(ESC_CHAR)},
+                false => {String::new()}
+            };
+            match key {
+                Key::Char(c) => {
+                    match ctrl_byte(c).filter(|_| modifiers.ctrl) {
+                        Some(byte) => {text.push(byte as char)},
+                        None if modifiers.shift => {text.extend(c.to_uppercase())},
+                        None => {text.push(c)}
+                    }
+                },
+                Key::Keypad(c) => {text.push(c)},
+                Key::Enter | Key::KeypadEnter => {text.push('\r')},
+                Key::Tab => {text.push('\t')},
+                Key::Backspace if modifiers.ctrl => {text.push('\x08')},
+                Key::Backspace => {text.push('\x7f')},
+                _ => {text.push(ESC_CHAR)}
+            }
+            text
+        }
+    };
+
+    text.into_bytes()
+}
+
 impl ParsableSequence<Escape> for Escape{
     fn parse_sequence<I>(chars: &mut std::iter::Peekable<I>) -> Vec<Escape> where I: Iterator<Item = char> {
-        let mut start_long_esc = false;
-        let mut escapes = Vec::new();
-        while let Some(&c) = chars.peek() {
-            let c = c as char;
-            if c=='[' {
-                start_long_esc = true;
-                break;
+        if chars.peek() == Some(&ESC_CHAR) {
+            chars.next();
+        }
+
+        EscapeReader::new(|| chars.next()).parse()
+    }
+
+    fn parse_writer(reader: &mut Box<dyn Read + Send>) -> Vec<Escape>{
+        EscapeReader::new(|| {
+            let mut s: [u8; 1] = [0;1];
+            reader.read_exact(&mut s).ok()?;
+            if s[0] >= 0x80 {
+                Some(read_utf8_char(s[0], reader))
             } else {
-                match c {
-                    '7' => {escapes.push(Escape::SaveCursorPos)},
-                    '8' => {escapes.push(Escape::RestoreCursorPos)},
-                    'M' => {escapes.push(Escape::CursorMoveOneLineUp)},
-                    _ => {}
-                }
+                Some(s[0] as char)
             }
-            chars.next();
+        }).parse()
+    }
+}
+
+/// Reads one escape sequence, with the leading ESC already consumed, from
+/// any char source. The raw text is kept so sequences that are not modeled
+/// can still be passed through as `Escape::Unhandled`.
+struct EscapeReader<F: FnMut() -> Option<char>> {
+    next_char: F,
+    raw: String,
+}
+
+impl<F: FnMut() -> Option<char>> EscapeReader<F> {
+    fn new(next_char: F) -> Self {
+        EscapeReader {
+            next_char,
+            raw: String::from(ESC_CHAR),
         }
+    }
 
-        if start_long_esc {
-            escapes = parse_long_seq(escapes, chars);
+    fn next(&mut self) -> Option<char> {
+        let c = (self.next_char)()?;
+        if self.raw.len() < OSC_MAX_LEN {
+            self.raw.push(c);
         }
+        Some(c)
+    }
 
-        escapes
+    fn unhandled(self) -> Vec<Escape> {
+        vec![Escape::Unhandled(self.raw)]
     }
 
-    fn parse_writer(reader: &mut Box<dyn Read + Send>) -> Vec<Escape>{
-        let mut escapes = Vec::new();
-        let mut s: [u8; 1] = [0;1];
-        match reader.read_exact(&mut s) {
-            Ok(_) => {
-                let c = s[0] as char;
-                match c {
-                    '[' => {escapes = parse_long_write(escapes, reader);},
-                    '7' => {escapes.push(Escape::SaveCursorPos)},
-                    '8' => {escapes.push(Escape::RestoreCursorPos)},
-                    'M' => {escapes.push(Escape::CursorMoveOneLineUp)},
-                    _ => {}
+    /// Parses the rest of the sequence. Input that ends halfway through or a
+    /// cancelled sequence gives nothing.
+    fn parse(mut self) -> Vec<Escape> {
+        let c = match self.next() {
+            Some(c) => {c},
+            None => {return Vec::new()}
+        };
+        match c {
+            CSI_CHAR => {self.parse_csi()},
+            OSC_CHAR => {self.parse_osc()},
+            'P' | 'X' | '^' | '_' => {
+                match self.read_string() {
+                    Some(_) => {self.unhandled()},
+                    None => {Vec::new()}
+                }
+            },
+            '7' => {vec![Escape::SaveCursorPos]},
+            '8' => {vec![Escape::RestoreCursorPos]},
+            'M' => {vec![Escape::CursorMoveOneLineUp]},
+            'D' => {vec![Escape::Index]},
+            'E' => {vec![Escape::NextLine]},
+            '=' => {vec![Escape::EnableAppKeypad]},
+            '>' => {vec![Escape::DisableAppKeypad]},
+            'c' => {vec![Escape::ResetTerminal]},
+            '#' => {
+                match self.next() {
+                    Some('8') => {vec![Escape::ScreenAlignment]},
+                    Some(_) => {self.unhandled()},
+                    None => {Vec::new()}
                 }
             },
-            Err(_) => {}
+            ' '..='/' => {
+                // charset selection and friends, one more byte to go
+                match self.next() {
+                    Some(_) => {self.unhandled()},
+                    None => {Vec::new()}
+                }
+            },
+            _ => {self.unhandled()}
+        }
+    }
+
+    /// Reads a string up to its BEL or ST terminator.
+    fn read_string(&mut self) -> Option<String> {
+        let mut payload = String::new();
+        loop {
+            let c = self.next()?;
+            if c == BEL_CHAR {
+                break;
+            } else if c == ESC_CHAR {
+                // ST is ESC \, anything else after ESC aborts the string too
+                self.next()?;
+                break;
+            } else if payload.len() < OSC_MAX_LEN {
+                payload.push(c);
+            }
         }
 
-        escapes
-        
+        Some(payload)
+    }
+
+    fn parse_osc(mut self) -> Vec<Escape> {
+        let escapes = match self.read_string() {
+            Some(payload) => {parse_osc(&payload)},
+            None => {return Vec::new()}
+        };
+        if escapes.is_empty() {
+            self.unhandled()
+        } else {
+            escapes
+        }
+    }
+
+    fn parse_csi(mut self) -> Vec<Escape> {
+        let mut marker = None;
+        let mut params = String::new();
+        let mut intermediate = false;
+        loop {
+            let c = match self.next() {
+                Some(c) => {c},
+                None => {return Vec::new()}
+            };
+            match c {
+                '<' | '=' | '>' | '?' if marker.is_none() && params.is_empty() => {marker = Some(c)},
+                '0'..='9' | ';' | ':' => {params.push(c)},
+                ' '..='/' => {intermediate = true},
+                '@'..='~' => {
+                    let escapes = if intermediate {
+                        Vec::new()
+                    } else {
+                        parse_csi(marker, &params, c)
+                    };
+                    return if escapes.is_empty() {
+                        self.unhandled()
+                    } else {
+                        escapes
+                    };
+                },
+                '\x18' | '\x1a' => {return Vec::new()},
+                _ => {}
+            }
+        }
     }
 }
 
-enum SpecialLongCase {
-    NoSpecial,
-    ScreenMode,
-    PrivateMode
+fn parse_osc(payload: &str) -> Vec<Escape> {
+    let mut escapes = Vec::new();
+    let (code, rest) = payload.split_once(';').unwrap_or((payload, ""));
+    match code {
+        "133" => {
+            let mut params = rest.split(';');
+            match params.next() {
+                Some("A") => {escapes.push(Escape::PromptStart)},
+                Some("B") => {escapes.push(Escape::CommandStart)},
+                Some("C") => {escapes.push(Escape::CommandExecuted)},
+                Some("D") => {
+                    let exit = params.next().and_then(|exit| exit.trim().parse().ok());
+                    escapes.push(Escape::CommandFinished(exit));
+                },
+                _ => {}
+            }
+        },
+        // 0 also sets the icon name, which nothing shows any more
+        "0" | "2" => {escapes.push(Escape::SetTitle(rest.to_owned()))},
+        "7" => {
+            if let Some(path) = parse_file_url(rest) {
+                escapes.push(Escape::CurrentDirectory(path));
+            }
+        },
+        _ => {}
+    }
+
+    escapes
+}
+
+/// Path of a `file://host/path` url as sent with OSC 7. The host is
+/// dropped, panes always run on the local machine.
+fn parse_file_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://").or_else(|| url.strip_prefix("kitty-shell-cwd://"))?;
+    let path = &rest[rest.find('/')?..];
+
+    Some(percent_decode(path))
 }
-fn parse_long_write(mut escapes: Vec<Escape>, reader: &mut Box<dyn Read + Send>) -> Vec<Escape>  {
-    let mut number = String::new();
-    let mut special_esc_flag = SpecialLongCase::NoSpecial;
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
     let mut i = 0;
-    let mut s: [u8; 1] = [0;1];
-    loop {
-        match reader.read_exact(&mut s) {
-            Ok(_) => {
-                let c = s[0] as char;
-                log_message(&format!("{}",c));
-                if i<1 {
-                    if c=='=' {
-                        special_esc_flag = SpecialLongCase::ScreenMode;
-                    } else if c=='?'{
-                        special_esc_flag = SpecialLongCase::PrivateMode;
-                    }
-                } else {
-                    match c {
-                        'l' => {
-                            match special_esc_flag {
-                                SpecialLongCase::ScreenMode => {
-                                    if let Ok(number) = number.parse() {
-                                        escapes.push(Escape::ResetScreenSet(number));
-                                    }                                
-                                },
-                                SpecialLongCase::PrivateMode => {
-                                    match number.as_str() {
-                                        "25" => {escapes.push(Escape::SetCursorInvisible); },
-                                        "47" => {escapes.push(Escape::RestoreScreen); },
-                                        "1049" => {escapes.push(Escape::ExitAltScreen); },
-                                        _ => {}
-                                    }
-                                },
-                                _ => {}
-                            }
-                            break;
-                        }
-                        'h' => {
-                            match special_esc_flag {
-                                SpecialLongCase::ScreenMode => {
-                                    match number.as_str() {
-                                        "0" => { escapes.push( Escape::Set40_25MonoScreen ) ; },        //=0h
-                                        "1" => { escapes.push( Escape::Set40_25ColorScreen ) ; },       //=1h
-                                        "2" => { escapes.push( Escape::Set80_25MonoScreen ) ; },        //=2h
-                                        "3" => { escapes.push( Escape::Set80_25ColorScreen ) ; },       //=3h
-                                        "4" => { escapes.push( Escape::Set320_2004ColorScreen ) ; },    //=4h
-                                        "5" => { escapes.push( Escape::Set320_200MonoScreen ) ; },      //=5h
-                                        "6" => { escapes.push( Escape::Set640_200MonoScreen ) ; },      //=6h
-                                        "7" => { escapes.push( Escape::EnableLineWrap) ; },            //=7h
-                                        "13" => { escapes.push( Escape::Set320_200ColorScreen ) ; },     //=13h
-                                        "14" => { escapes.push( Escape::Set640_200ColorScreen ) ; },     //=14h
-                                        "15" => { escapes.push( Escape::Set640_350MonoScreen ) ; },      //=15h
-                                        "16" => { escapes.push( Escape::Set640_350ColorScreen ) ; },     //=16h
-                                        "17" => { escapes.push( Escape::Set640_480MonoScreen ) ; },      //=17h
-                                        "18" => { escapes.push( Escape::Set640_480ColorScreen ) ; },     //=18h
-                                        "19" => { escapes.push( Escape::Set320_200ColorScreen256 ) ; },     //=19h
-                                        _ => {}
-                                    }
-                                },
-                                SpecialLongCase::PrivateMode => {
-                                    match number.as_str() {
-                                        "25" => {escapes.push(Escape::SetCursorVisible); },
-                                        "47" => {escapes.push(Escape::SaveScreen); },
-                                        "1049" => {escapes.push(Escape::EnterAltScreen); },
-                                        _ => {}
-                                    }
-                                },
-                                _ => {}
-                            }
-                            break;
-                        },
-                        'n' => {
-                            if number=="6" {
-                                escapes.push(Escape::RequestCursorPos);
-                            }
-                            break;
-                        },
-                        'm' => {
-                            if number.len()>0 {
-                                let numbers = number.split(';');
-                                let mut is_custom_color: u8 = 0;
-                                let mut rgb = (-1,-1 ,-1);
-                                for number in numbers {
-                                    if is_custom_color>0 && number=="5"{
-                                        if let Ok(number) = number.parse() {
-                                            if is_custom_color==1 {
-                                                escapes.push(Escape::SetForgroundCustomColor(number));
-                                            }else if is_custom_color==2 {
-                                                escapes.push(Escape::SetBackgroundCustomColor(number));
-                                            } else {
-                                                is_custom_color = 0;
-                                            }
-                                        } else {
-                                            is_custom_color = 0;
-                                        }
-                                    }                       
-                                    if is_custom_color>0 && number=="2"{
-                                        unimplemented!("Truecolor escapes have not been implemented");
-                                    }                       
-                                    match number {
-                                        "0" => { escapes.push(Escape::ResetAllModes) },
-                                        "1" => { escapes.push(Escape::SetBold) },
-                                        "2" => { escapes.push(Escape::SetDim) },
-                                        "3" => { escapes.push(Escape::SetItalic) },
-                                        "4" => { escapes.push(Escape::SetUnderline) },
-                                        "5" => { escapes.push(Escape::SetBlinking) },
-                                        "7" => { escapes.push(Escape::SetInverse) },
-                                        "8" => { escapes.push(Escape::SetHidden) },
-                                        "9" => { escapes.push(Escape::SetStrikethrough) },
-                                        "22" => { 
-                                            escapes.push(Escape::ResetBold);
-                                            escapes.push(Escape::ResetDim);
-                                        },
-                                        "23" => { escapes.push(Escape::ResetItalic) },
-                                        "24" => { escapes.push(Escape::ResetUnderline) },
-                                        "25" => { escapes.push(Escape::ResetBlinking) },
-                                        "27" => { escapes.push(Escape::ResetInverse) },
-                                        "28" => { escapes.push(Escape::ResetHidden) },
-                                        "29" => { escapes.push(Escape::ResetStrikethrough) },
-                                        "30" => { escapes.push(Escape::SetForgroundBlack) },
-                                        "40" => { escapes.push(Escape::SetBackgroundBlack) },
-                                        "31" => { escapes.push(Escape::SetForgroundRed) },
-                                        "41" => { escapes.push(Escape::SetBackgroundRed) },
-                                        "32" => { escapes.push(Escape::SetForgroundGreen) },
-                                        "42" => { escapes.push(Escape::SetBackgroundGreen) },
-                                        "33" => { escapes.push(Escape::SetForgroundYellow) },
-                                        "43" => { escapes.push(Escape::SetBackgroundYellow) },
-                                        "34" => { escapes.push(Escape::SetForgroundBlue) },
-                                        "44" => { escapes.push(Escape::SetBackgroundBlue) },
-                                        "35" => { escapes.push(Escape::SetForgroundMagenta) },
-                                        "45" => { escapes.push(Escape::SetBackgroundMagenta) },
-                                        "36" => { escapes.push(Escape::SetForgroundCyan) },
-                                        "46" => { escapes.push(Escape::SetBackgroundCyan) },
-                                        "37" => { escapes.push(Escape::SetForgroundWhite) },
-                                        "38" => { is_custom_color = 1;},
-                                        "48" => { is_custom_color = 2;},
-                                        "47" => { escapes.push(Escape::SetBackgroundWhite) },
-                                        "39" => { escapes.push(Escape::SetForgroundDefault) },
-                                        "49" => { escapes.push(Escape::SetBackgroundDefault) },
-                                        "90" => { escapes.push(Escape::SetForgroundBriBlack) },
-                                        "100" => { escapes.push(Escape::SetBackgroundBriBlack) },
-                                        "91" => { escapes.push(Escape::SetForgroundBriRed) },
-                                        "101" => { escapes.push(Escape::SetBackgroundBriRed) },
-                                        "92" => { escapes.push(Escape::SetForgroundBriGreen) },
-                                        "102" => { escapes.push(Escape::SetBackgroundBriGreen) },
-                                        "93" => { escapes.push(Escape::SetForgroundBriYellow) },
-                                        "103" => { escapes.push(Escape::SetBackgroundBriYellow) },
-                                        "94" => { escapes.push(Escape::SetForgroundBriBlue) },
-                                        "104" => { escapes.push(Escape::SetBackgroundBriBlue) },
-                                        "95" => { escapes.push(Escape::SetForgroundBriMagenta) },
-                                        "105" => { escapes.push(Escape::SetBackgroundBriMagenta) },
-                                        "96" => { escapes.push(Escape::SetForgroundBriCyan) },
-                                        "106" => { escapes.push(Escape::SetBackgroundBriCyan) },
-                                        "97" => { escapes.push(Escape::SetForgroundBriWhite) },
-                                        "107" => { escapes.push(Escape::SetBackgroundBriWhite) },
-                                        _ => {}
-                                    }
-                                }
-                            }
-                            break;
-                        },
-                        'A' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorUp(number));
-                                }
-                            }
-                            break;
-                        },
-                        'B' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorDown(number));
-                                }
-                            }
-                            break;
-                        },
-                        'C' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorRight(number));
-                                }
-                            }
-                            break;
-                        },
-                        'D' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorLeft(number));
-                                }
-                            }
-                            break;
-                        },
-                        'E' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorToNextLineStart(number));
-                                }
-                            }
-                            break;
-                        },
-                        'F' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorToPastLineStart(number));
-                                }
-                            }
-                            break;
-                        },
-                        'G' => {
-                            if number.len()>0 {
-                                if let Ok(number) = number.parse() {
-                                    escapes.push(Escape::CursorToCol(number));
-                                }
-                            }
-                            break;
-                        },
-                        'H' => {
-                            if number.len()==2 {
-                                let numbers = number.split_once(';');
-                                if let Some((line, col)) = numbers {
-                                    if let (Ok(line), Ok(col)) = (line.trim().parse(), col.trim().parse()) {
-                                        escapes.push(Escape::MoveCursorTo((line, col)));
-                                    }
-                                }
-                            } else if number.len()==0 {
-                                escapes.push(Escape::ZeroCursor);
-                            }
-                            break;
-                        },
-                        'J' => {
-                            if number.len()==0 {
-                                escapes.push(Escape::ClearInDisplay);
-                            } else if number=="0" {
-                                escapes.push(Escape::ClearDisplayUntilScreenEnd);
-                            } else if number=="1" {
-                                escapes.push(Escape::ClearDisplayUntilScreenStart);
-                            } else if number=="2" {
-                                escapes.push(Escape::ClearAll);
-                            } else if number=="3" {
-                                escapes.push(Escape::EraseSavedLine);
-                            }
-                            break;
-                        },
-                        'K' => {
-                            if number.len()==0 {
-                                escapes.push(Escape::EraseInLine);
-                            } else if number=="0" {
-                                escapes.push(Escape::EraseFromCursorToEnd);
-                            } else if number=="1" {
-                                escapes.push(Escape::EraseFromCursorToStart);
-                            } else if number=="2" {
-                                escapes.push(Escape::EraseLine);
-                            }
-                            break;
-                        },
-                        's' => {
-                            if number.len()==0 {
-                                escapes.push(Escape::SaveCursorPos);
-                            }
-                            break;
-                        },
-                        'u' => {
-                            if number.len()==0 {
-                                escapes.push(Escape::RestoreCursorPos);
-                            }
-                            break;
-                        },
-                        _ => {
-                            if c.is_numeric() || c==';' {
-                                number.push(c);
-                            }
-                        }
-                    }
-                }
-                i += 1;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
             },
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
 
-            Err(e) => {
-                println!("{:?}",e);
-                break;}
+fn percent_encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {out.push(byte as char)},
+            _ => {out.push_str(&format!("%{:02X}", byte))}
         }
     }
 
+    out
+}
+
+fn parse_csi(marker: Option<char>, params: &str, final_char: char) -> Vec<Escape> {
+    let mut escapes = Vec::new();
+    if let Some(escape) = parse_key_modes(marker, params, final_char) {
+        return vec![escape];
+    }
+    match marker {
+        Some('?') => {return parse_private_modes(params, final_char)},
+        Some('=') => {
+            parse_screen_modes(params, final_char, &mut escapes);
+            return escapes;
+        },
+        Some(_) => {return escapes},
+        None => {}
+    }
+
+    let arg = |i: usize| -> u32 {
+        params.split(';').nth(i).and_then(|param| param.parse().ok()).unwrap_or(0)
+    };
+    let count = |i: usize| arg(i).max(1);
+    match final_char {
+        'A' => {escapes.push(Escape::CursorUp(count(0)))},
+        'B' => {escapes.push(Escape::CursorDown(count(0)))},
+        'C' => {escapes.push(Escape::CursorRight(count(0)))},
+        'D' => {escapes.push(Escape::CursorLeft(count(0)))},
+        'E' => {escapes.push(Escape::CursorToNextLineStart(count(0)))},
+        'F' => {escapes.push(Escape::CursorToPastLineStart(count(0)))},
+        'G' | '`' => {escapes.push(Escape::CursorToCol(count(0)))},
+        'd' => {escapes.push(Escape::CursorToLine(count(0)))},
+        'H' | 'f' => {
+            if params.is_empty() {
+                escapes.push(Escape::ZeroCursor);
+            } else {
+                escapes.push(Escape::MoveCursorTo((count(0), count(1))));
+            }
+        },
+        'J' => {
+            match params {
+                "" => {escapes.push(Escape::ClearInDisplay)},
+                "0" => {escapes.push(Escape::ClearDisplayUntilScreenEnd)},
+                "1" => {escapes.push(Escape::ClearDisplayUntilScreenStart)},
+                "2" => {escapes.push(Escape::ClearAll)},
+                "3" => {escapes.push(Escape::EraseSavedLine)},
+                _ => {}
+            }
+        },
+        'K' => {
+            match params {
+                "" => {escapes.push(Escape::EraseInLine)},
+                "0" => {escapes.push(Escape::EraseFromCursorToEnd)},
+                "1" => {escapes.push(Escape::EraseFromCursorToStart)},
+                "2" => {escapes.push(Escape::EraseLine)},
+                _ => {}
+            }
+        },
+        'L' => {escapes.push(Escape::InsertLines(count(0)))},
+        'M' => {escapes.push(Escape::DeleteLines(count(0)))},
+        '@' => {escapes.push(Escape::InsertChars(count(0)))},
+        'P' => {escapes.push(Escape::DeleteChars(count(0)))},
+        'X' => {escapes.push(Escape::EraseChars(count(0)))},
+        'S' => {escapes.push(Escape::ScrollUp(count(0)))},
+        'T' => {escapes.push(Escape::ScrollDown(count(0)))},
+        'r' => {escapes.push(Escape::SetScrollRegion((arg(0), arg(1))))},
+        'm' => {parse_sgr(params, &mut escapes)},
+        'n' if params == "6" => {escapes.push(Escape::RequestCursorPos)},
+        's' if params.is_empty() => {escapes.push(Escape::SaveCursorPos)},
+        'u' if params.is_empty() => {escapes.push(Escape::RestoreCursorPos)},
+        // only the window title's stack, icon names are not kept
+        't' if arg(0) == 22 && arg(1) != 1 => {escapes.push(Escape::PushTitle)},
+        't' if arg(0) == 23 && arg(1) != 1 => {escapes.push(Escape::PopTitle)},
+        _ => {}
+    }
+
     escapes
 }
 
-fn parse_long_seq<I>(mut escapes: Vec<Escape>, chars: &mut std::iter::Peekable<I>) -> Vec<Escape> where I: Iterator<Item = char> {
-    let mut number = String::new();
-    let mut special_esc_flag = SpecialLongCase::NoSpecial;
-    let mut i = 0;
-    while let Some(&c) = chars.peek() {
-        if i<1 {
-            if c=='=' {
-                special_esc_flag = SpecialLongCase::ScreenMode;
-            } else if c=='?'{
-                special_esc_flag = SpecialLongCase::PrivateMode;
+/// How the program wants keys sent, xterm's modifyOtherKeys and the kitty
+/// keyboard protocol.
+fn parse_key_modes(marker: Option<char>, params: &str, final_char: char) -> Option<Escape> {
+    let arg = |i: usize, default: u32| -> u32 {
+        params.split(';').nth(i).and_then(|param| param.parse().ok()).unwrap_or(default)
+    };
+    let escape = match (marker?, final_char) {
+        ('>', 'm') if arg(0, 0) == 4 => {Escape::SetModifyOtherKeys(arg(1, 0))},
+        ('>', 'u') => {Escape::PushKeyboardFlags(arg(0, 0))},
+        ('<', 'u') => {Escape::PopKeyboardFlags(arg(0, 1))},
+        ('=', 'u') => {Escape::SetKeyboardFlags((arg(0, 0), arg(1, 1)))},
+        ('?', 'u') if params.is_empty() => {Escape::QueryKeyboardFlags},
+        _ => {return None}
+    };
+
+    Some(escape)
+}
+
+/// DEC private modes. Modes without a variant are kept as their own raw
+/// sequence so a combined set like `?1;1000h` still reaches the host.
+fn parse_private_modes(params: &str, final_char: char) -> Vec<Escape> {
+    let set = match final_char {
+        'h' => {true},
+        'l' => {false},
+        _ => {return Vec::new()}
+    };
+    let mut escapes = Vec::new();
+    for mode in params.split(';') {
+        let escape = match (mode, set) {
+            ("1", true) => {Escape::EnableAppCursor},
+            ("1", false) => {Escape::DisableAppCursor},
+            ("7", true) => {Escape::EnableAutoWrap},
+            ("7", false) => {Escape::DisableAutoWrap},
+            ("25", true) => {Escape::SetCursorVisible},
+            ("25", false) => {Escape::SetCursorInvisible},
+            ("47", true) => {Escape::SaveScreen},
+            ("47", false) => {Escape::RestoreScreen},
+            ("1049", true) => {Escape::EnterAltScreen},
+            ("1049", false) => {Escape::ExitAltScreen},
+            ("2004", true) => {Escape::EnableBracketedPaste},
+            ("2004", false) => {Escape::DisableBracketedPaste},
+            ("1000", true) => {Escape::EnableMouseClicks},
+            ("1000", false) => {Escape::DisableMouseClicks},
+            ("1002", true) => {Escape::EnableMouseDrag},
+            ("1002", false) => {Escape::DisableMouseDrag},
+            ("1003", true) => {Escape::EnableMouseMotion},
+            ("1003", false) => {Escape::DisableMouseMotion},
+            ("1006", true) => {Escape::EnableSgrMouse},
+            ("1006", false) => {Escape::DisableSgrMouse},
+            ("1004", true) => {Escape::EnableFocusReports},
+            ("1004", false) => {Escape::DisableFocusReports},
+            _ => {Escape::Unhandled(escC!(format!("?{}{}", mode, final_char)))}
+        };
+        escapes.push(escape);
+    }
+
+    escapes
+}
+
+fn parse_screen_modes(params: &str, final_char: char, escapes: &mut Vec<Escape>) {
+    match final_char {
+        'l' => {
+            if let Ok(number) = params.parse() {
+                escapes.push(Escape::ResetScreenSet(number));
             }
-        } else {
-            match c {
-                'l' => {
-                    match special_esc_flag {
-                        SpecialLongCase::ScreenMode => {
-                            if let Ok(number) = number.parse() {
-                                escapes.push(Escape::ResetScreenSet(number));
-                            }
-                        },
-                        SpecialLongCase::PrivateMode => {
-                            match number.as_str() {
-                                "25" => {escapes.push(Escape::SetCursorInvisible)},
-                                "47" => {escapes.push(Escape::RestoreScreen)},
-                                "1049" => {escapes.push(Escape::ExitAltScreen)},
-                                _ => {}
-                            }
-                        },
-                        _ => {}
-                    }
-                }
-                'h' => {
-                    match special_esc_flag {
-                        SpecialLongCase::ScreenMode => {
-                            match number.as_str() {
-                                "0" => { escapes.push( Escape::Set40_25MonoScreen ) },        //=0h
-                                "1" => { escapes.push( Escape::Set40_25ColorScreen ) },       //=1h
-                                "2" => { escapes.push( Escape::Set80_25MonoScreen ) },        //=2h
-                                "3" => { escapes.push( Escape::Set80_25ColorScreen ) },       //=3h
-                                "4" => { escapes.push( Escape::Set320_2004ColorScreen ) },    //=4h
-                                "5" => { escapes.push( Escape::Set320_200MonoScreen ) },      //=5h
-                                "6" => { escapes.push( Escape::Set640_200MonoScreen ) },      //=6h
-                                "7" => { escapes.push( Escape::EnableLineWrap) },            //=7h
-                                "13" => { escapes.push( Escape::Set320_200ColorScreen ) },     //=13h
-                                "14" => { escapes.push( Escape::Set640_200ColorScreen ) },     //=14h
-                                "15" => { escapes.push( Escape::Set640_350MonoScreen ) },      //=15h
-                                "16" => { escapes.push( Escape::Set640_350ColorScreen ) },     //=16h
-                                "17" => { escapes.push( Escape::Set640_480MonoScreen ) },      //=17h
-                                "18" => { escapes.push( Escape::Set640_480ColorScreen ) },     //=18h
-                                "19" => { escapes.push( Escape::Set320_200ColorScreen256 ) },     //=19h
-                                _ => {}
-                            }
-                        },
-                        SpecialLongCase::PrivateMode => {
-                            match number.as_str() {
-                                "25" => {escapes.push(Escape::SetCursorVisible)},
-                                "47" => {escapes.push(Escape::SaveScreen)},
-                                "1049" => {escapes.push(Escape::EnterAltScreen)},
-                                _ => {}
-                            }
-                        },
-                        _ => {}
-                    }
-                },
-                'n' => {
-                    if number=="6" {
-                        escapes.push(Escape::RequestCursorPos);
-                    }
-                },
-                'm' => {
-                    if number.len()>0 {
-                        let numbers = number.split(';');
-                        let mut is_custom_color: u8 = 0;
-                        let mut rgb = (-1,-1 ,-1);
-                        for number in numbers {
-                            if is_custom_color>0 && number=="5"{
-                                if let Ok(number) = number.parse() {
-                                    if is_custom_color==1 {
-                                        escapes.push(Escape::SetForgroundCustomColor(number));
-                                        break;                            
-                                    }else if is_custom_color==2 {
-                                        escapes.push(Escape::SetBackgroundCustomColor(number));
-                                        break;                            
-                                    } else {
-                                        is_custom_color = 0;
-                                    }
-                                } else {
-                                    is_custom_color = 0;
-                                }
-                            }                       
-                            if is_custom_color>0 && number=="2"{
-                                unimplemented!("Truecolor escapes have not been implemented");
-                            }                       
-                            match number {
-                                "0" => { escapes.push(Escape::ResetAllModes) },
-                                "1" => { escapes.push(Escape::SetBold) },
-                                "2" => { escapes.push(Escape::SetDim) },
-                                "3" => { escapes.push(Escape::SetItalic) },
-                                "4" => { escapes.push(Escape::SetUnderline) },
-                                "5" => { escapes.push(Escape::SetBlinking) },
-                                "7" => { escapes.push(Escape::SetInverse) },
-                                "8" => { escapes.push(Escape::SetHidden) },
-                                "9" => { escapes.push(Escape::SetStrikethrough) },
-                                "22" => { 
-                                    escapes.push(Escape::ResetBold);
-                                    escapes.push(Escape::ResetDim);
-                                },
-                                "23" => { escapes.push(Escape::ResetItalic) },
-                                "24" => { escapes.push(Escape::ResetUnderline) },
-                                "25" => { escapes.push(Escape::ResetBlinking) },
-                                "27" => { escapes.push(Escape::ResetInverse) },
-                                "28" => { escapes.push(Escape::ResetHidden) },
-                                "29" => { escapes.push(Escape::ResetStrikethrough) },
-                                "30" => { escapes.push(Escape::SetForgroundBlack) },
-                                "40" => { escapes.push(Escape::SetBackgroundBlack) },
-                                "31" => { escapes.push(Escape::SetForgroundRed) },
-                                "41" => { escapes.push(Escape::SetBackgroundRed) },
-                                "32" => { escapes.push(Escape::SetForgroundGreen) },
-                                "42" => { escapes.push(Escape::SetBackgroundGreen) },
-                                "33" => { escapes.push(Escape::SetForgroundYellow) },
-                                "43" => { escapes.push(Escape::SetBackgroundYellow) },
-                                "34" => { escapes.push(Escape::SetForgroundBlue) },
-                                "44" => { escapes.push(Escape::SetBackgroundBlue) },
-                                "35" => { escapes.push(Escape::SetForgroundMagenta) },
-                                "45" => { escapes.push(Escape::SetBackgroundMagenta) },
-                                "36" => { escapes.push(Escape::SetForgroundCyan) },
-                                "46" => { escapes.push(Escape::SetBackgroundCyan) },
-                                "37" => { escapes.push(Escape::SetForgroundWhite) },
-                                "38" => { is_custom_color = 1;},
-                                "48" => { is_custom_color = 2;},
-                                "47" => { escapes.push(Escape::SetBackgroundWhite) },
-                                "39" => { escapes.push(Escape::SetForgroundDefault) },
-                                "49" => { escapes.push(Escape::SetBackgroundDefault) },
-                                "90" => { escapes.push(Escape::SetForgroundBriBlack) },
-                                "100" => { escapes.push(Escape::SetBackgroundBriBlack) },
-                                "91" => { escapes.push(Escape::SetForgroundBriRed) },
-                                "101" => { escapes.push(Escape::SetBackgroundBriRed) },
-                                "92" => { escapes.push(Escape::SetForgroundBriGreen) },
-                                "102" => { escapes.push(Escape::SetBackgroundBriGreen) },
-                                "93" => { escapes.push(Escape::SetForgroundBriYellow) },
-                                "103" => { escapes.push(Escape::SetBackgroundBriYellow) },
-                                "94" => { escapes.push(Escape::SetForgroundBriBlue) },
-                                "104" => { escapes.push(Escape::SetBackgroundBriBlue) },
-                                "95" => { escapes.push(Escape::SetForgroundBriMagenta) },
-                                "105" => { escapes.push(Escape::SetBackgroundBriMagenta) },
-                                "96" => { escapes.push(Escape::SetForgroundBriCyan) },
-                                "106" => { escapes.push(Escape::SetBackgroundBriCyan) },
-                                "97" => { escapes.push(Escape::SetForgroundBriWhite) },
-                                "107" => { escapes.push(Escape::SetBackgroundBriWhite) },
-                                _ => {}
-                            }
-                        }
-                        break;
-                    }
-                },
-                'A' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorUp(number));
-                        }
-                        break;
-                    }
-                },
-                'B' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorDown(number));
-                        }
-                        break;
-                    }
-                },
-                'C' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorRight(number));
-                        }
-                        break;
-                    }
-                },
-                'D' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorLeft(number));
-                        }
-                        break;
-                    }
-                },
-                'E' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorToNextLineStart(number));
-                        }
-                        break;
-                    }
-                },
-                'F' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorToPastLineStart(number));
-                        }
-                        break;
-                    }
-                },
-                'G' => {
-                    if number.len()>0 {
-                        if let Ok(number) = number.parse() {
-                            escapes.push(Escape::CursorToCol(number));
-                        }
-                        break;
-                    }
-                },
-                'H' => {
-                    if number.len()==2 {
-                        let numbers = number.split_once(';');
-                        if let Some((line, col)) = numbers {
-                            if let (Ok(line), Ok(col)) = (line.trim().parse(), col.trim().parse()) {
-                                escapes.push(Escape::MoveCursorTo((line, col)));
-                            }
-                        }
-                        break;
-                    } else if number.len()==0 {
-                        escapes.push(Escape::ZeroCursor);
-                    }
-                },
-                'J' => {
-                    if number.len()==0 {
-                        escapes.push(Escape::ClearInDisplay);
-                    } else if number=="0" {
-                        escapes.push(Escape::ClearDisplayUntilScreenEnd);
-                    } else if number=="1" {
-                        escapes.push(Escape::ClearDisplayUntilScreenStart);
-                    } else if number=="2" {
-                        escapes.push(Escape::ClearAll);
-                    } else if number=="3" {
-                        escapes.push(Escape::EraseSavedLine);
-                    }
-                },
-                'K' => {
-                    if number.len()==0 {
-                        escapes.push(Escape::EraseInLine);
-                    } else if number=="0" {
-                        escapes.push(Escape::EraseFromCursorToEnd);
-                    } else if number=="1" {
-                        escapes.push(Escape::EraseFromCursorToStart);
-                    } else if number=="2" {
-                        escapes.push(Escape::EraseLine);
-                    }
-                },
-                's' => {
-                    if number.len()==0 {
-                        escapes.push(Escape::SaveCursorPos);
-                    }
-                },
-                'u' => {
-                    if number.len()==0 {
-                        escapes.push(Escape::RestoreCursorPos);
-                    }
-                },
-                _ => {
-                    if c.is_numeric() || c==';' {
-                        number.push(c);
-                    }
-                }
+        },
+        'h' => {
+            match params {
+                "0" => { escapes.push( Escape::Set40_25MonoScreen ) ; },        //=0h
+                "1" => { escapes.push( Escape::Set40_25ColorScreen ) ; },       //=1h
+                "2" => { escapes.push( Escape::Set80_25MonoScreen ) ; },        //=2h
+                "3" => { escapes.push( Escape::Set80_25ColorScreen ) ; },       //=3h
+                "4" => { escapes.push( Escape::Set320_2004ColorScreen ) ; },    //=4h
+                "5" => { escapes.push( Escape::Set320_200MonoScreen ) ; },      //=5h
+                "6" => { escapes.push( Escape::Set640_200MonoScreen ) ; },      //=6h
+                "7" => { escapes.push( Escape::EnableLineWrap) ; },            //=7h
+                "13" => { escapes.push( Escape::Set320_200ColorScreen ) ; },     //=13h
+                "14" => { escapes.push( Escape::Set640_200ColorScreen ) ; },     //=14h
+                "15" => { escapes.push( Escape::Set640_350MonoScreen ) ; },      //=15h
+                "16" => { escapes.push( Escape::Set640_350ColorScreen ) ; },     //=16h
+                "17" => { escapes.push( Escape::Set640_480MonoScreen ) ; },      //=17h
+                "18" => { escapes.push( Escape::Set640_480ColorScreen ) ; },     //=18h
+                "19" => { escapes.push( Escape::Set320_200ColorScreen256 ) ; },     //=19h
+                _ => {}
             }
+        },
+        _ => {}
+    }
+}
+
+fn parse_sgr(params: &str, escapes: &mut Vec<Escape>) {
+    if params.is_empty() {
+        escapes.push(Escape::ResetAllModes);
+        return;
+    }
+    let mut params = params.split(';');
+    while let Some(param) = params.next() {
+        let (number, colon) = match param.split_once(':') {
+            Some((number, rest)) => {(number, Some(rest))},
+            None => {(param, None)}
+        };
+        if number == "38" || number == "48" {
+            let color = match colon {
+                Some(rest) => {parse_sgr_color(number == "38", &mut rest.split(':'), true)},
+                None => {parse_sgr_color(number == "38", &mut params, false)}
+            };
+            escapes.extend(color);
+            continue;
+        }
+        match number {
+            "0" | "" => { escapes.push(Escape::ResetAllModes) },
+            "1" => { escapes.push(Escape::SetBold) },
+            "2" => { escapes.push(Escape::SetDim) },
+            "3" => { escapes.push(Escape::SetItalic) },
+            "4" => { escapes.push(Escape::SetUnderline) },
+            "5" => { escapes.push(Escape::SetBlinking) },
+            "7" => { escapes.push(Escape::SetInverse) },
+            "8" => { escapes.push(Escape::SetHidden) },
+            "9" => { escapes.push(Escape::SetStrikethrough) },
+            "22" => {
+                escapes.push(Escape::ResetBold);
+                escapes.push(Escape::ResetDim);
+            },
+            "23" => { escapes.push(Escape::ResetItalic) },
+            "24" => { escapes.push(Escape::ResetUnderline) },
+            "25" => { escapes.push(Escape::ResetBlinking) },
+            "27" => { escapes.push(Escape::ResetInverse) },
+            "28" => { escapes.push(Escape::ResetHidden) },
+            "29" => { escapes.push(Escape::ResetStrikethrough) },
+            "30" => { escapes.push(Escape::SetForgroundBlack) },
+            "40" => { escapes.push(Escape::SetBackgroundBlack) },
+            "31" => { escapes.push(Escape::SetForgroundRed) },
+            "41" => { escapes.push(Escape::SetBackgroundRed) },
+            "32" => { escapes.push(Escape::SetForgroundGreen) },
+            "42" => { escapes.push(Escape::SetBackgroundGreen) },
+            "33" => { escapes.push(Escape::SetForgroundYellow) },
+            "43" => { escapes.push(Escape::SetBackgroundYellow) },
+            "34" => { escapes.push(Escape::SetForgroundBlue) },
+            "44" => { escapes.push(Escape::SetBackgroundBlue) },
+            "35" => { escapes.push(Escape::SetForgroundMagenta) },
+            "45" => { escapes.push(Escape::SetBackgroundMagenta) },
+            "36" => { escapes.push(Escape::SetForgroundCyan) },
+            "46" => { escapes.push(Escape::SetBackgroundCyan) },
+            "37" => { escapes.push(Escape::SetForgroundWhite) },
+            "47" => { escapes.push(Escape::SetBackgroundWhite) },
+            "39" => { escapes.push(Escape::SetForgroundDefault) },
+            "49" => { escapes.push(Escape::SetBackgroundDefault) },
+            "90" => { escapes.push(Escape::SetForgroundBriBlack) },
+            "100" => { escapes.push(Escape::SetBackgroundBriBlack) },
+            "91" => { escapes.push(Escape::SetForgroundBriRed) },
+            "101" => { escapes.push(Escape::SetBackgroundBriRed) },
+            "92" => { escapes.push(Escape::SetForgroundBriGreen) },
+            "102" => { escapes.push(Escape::SetBackgroundBriGreen) },
+            "93" => { escapes.push(Escape::SetForgroundBriYellow) },
+            "103" => { escapes.push(Escape::SetBackgroundBriYellow) },
+            "94" => { escapes.push(Escape::SetForgroundBriBlue) },
+            "104" => { escapes.push(Escape::SetBackgroundBriBlue) },
+            "95" => { escapes.push(Escape::SetForgroundBriMagenta) },
+            "105" => { escapes.push(Escape::SetBackgroundBriMagenta) },
+            "96" => { escapes.push(Escape::SetForgroundBriCyan) },
+            "106" => { escapes.push(Escape::SetBackgroundBriCyan) },
+            "97" => { escapes.push(Escape::SetForgroundBriWhite) },
+            "107" => { escapes.push(Escape::SetBackgroundBriWhite) },
+            _ => {}
         }
-        i += 1;
-        chars.next();
     }
+}
 
-    escapes
+/// Reads the colour after a 38 or 48 from `args`, `5;i` for a palette
+/// index or `2;r;g;b` for RGB. The colon form, `38:2::r:g:b`, can have a
+/// colour space id before the components.
+fn parse_sgr_color(foreground: bool, args: &mut std::str::Split<'_, char>, colon: bool) -> Option<Escape> {
+    match args.next()? {
+        "5" => {
+            let i = args.next()?.parse().ok()?;
+            match foreground {
+                true => {Some(Escape::SetForgroundCustomColor(i))},
+                false => {Some(Escape::SetBackgroundCustomColor(i))}
+            }
+        },
+        "2" => {
+            let components: Vec<&str> = match colon {
+                true => {args.collect()},
+                false => {args.take(3).collect()}
+            };
+            let skip = match components.len() {
+                3 => {0},
+                n if colon && n > 3 => {1},
+                _ => {return None}
+            };
+            let mut rgb = components[skip..skip + 3].iter().map(|c| c.parse::<u8>().ok());
+            let (r, g, b) = (rgb.next()??, rgb.next()??, rgb.next()??);
+            match foreground {
+                true => {Some(Escape::SetForgroundRgbColor(r, g, b))},
+                false => {Some(Escape::SetBackgroundRgbColor(r, g, b))}
+            }
+        },
+        _ => {None}
+    }
 }
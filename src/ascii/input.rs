@@ -0,0 +1,610 @@
+use std::time::{Duration, Instant};
+
+use super::escapes::decode_utf8;
+
+/// How long a lone ESC waits for the rest of a sequence before it is taken
+/// as the Escape key. Terminals send a whole sequence in one write, so
+/// this only has to cover the time it spends in flight.
+pub const ESC_TIMEOUT: Duration = Duration::from_millis(25);
+
+/// How soon after an eager ESC the rest of its sequence has to arrive to
+/// count as one split in two. Far shorter than anyone types `<Esc>O`.
+const SPLIT_WINDOW: Duration = Duration::from_millis(10);
+
+/// Reads in a row that come whole before adaptive ESC is eager again.
+const RECOVER_READS: u32 = 32;
+
+const ESC: u8 = 0x1b;
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Modifier keys held with a key or mouse event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+    /// Super, or Meta where the terminal reports that instead.
+    pub meta: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, alt: false, ctrl: false, meta: false };
+    pub const SHIFT: Modifiers = Modifiers { shift: true, ..Modifiers::NONE };
+    pub const ALT: Modifiers = Modifiers { alt: true, ..Modifiers::NONE };
+    pub const CTRL: Modifiers = Modifiers { ctrl: true, ..Modifiers::NONE };
+
+    /// From the xterm and kitty modifier parameter, one more than a bit
+    /// mask of shift, alt, ctrl and super. Lock keys are left out.
+    pub fn from_param(param: u32) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        Modifiers {
+            shift: bits & 1 != 0,
+            alt: bits & 2 != 0,
+            ctrl: bits & 4 != 0,
+            meta: bits & 8 != 0,
+        }
+    }
+
+    /// The xterm modifier parameter, 1 for no modifiers.
+    pub fn param(&self) -> u32 {
+        1 + self.shift as u32 + 2 * self.alt as u32 + 4 * self.ctrl as u32 + 8 * self.meta as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Modifiers::NONE
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// F1 to F24.
+    F(u8),
+    /// A keypad key told apart from the main keys, `0`-`9`, `+`, `-`,
+    /// `*`, `/`, `.`, `,` and `=`.
+    Keypad(char),
+    KeypadEnter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    pub fn new(key: Key, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent { key, modifiers }
+    }
+
+    pub fn plain(key: Key) -> KeyEvent {
+        KeyEvent::new(key, Modifiers::NONE)
+    }
+
+    /// Ctrl held with a letter or symbol key, like Ctrl-] for `ctrl(']')`.
+    pub fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(Key::Char(c), Modifiers::CTRL)
+    }
+
+    fn with_alt(mut self) -> KeyEvent {
+        self.modifiers.alt = true;
+        self
+    }
+
+    /// The key a single byte from a terminal in its default mode stands
+    /// for. Ctrl-letter arrives as the C0 control code of the letter.
+    fn from_char(c: char) -> KeyEvent {
+        match c {
+            '\r' => {KeyEvent::plain(Key::Enter)},
+            '\t' => {KeyEvent::plain(Key::Tab)},
+            '\x7f' => {KeyEvent::plain(Key::Backspace)},
+            '\x1b' => {KeyEvent::plain(Key::Escape)},
+            '\0' => {KeyEvent::ctrl(' ')},
+            '\x01'..='\x1a' => {KeyEvent::ctrl((c as u8 + 0x60) as char)},
+            '\x1c'..='\x1f' => {KeyEvent::ctrl((c as u8 + 0x40) as char)},
+            _ => {KeyEvent::plain(Key::Char(c))}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    /// Buttons 8 to 11, back and forward on most mice.
+    Other(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press,
+    Release,
+    /// Moved with a button held.
+    Drag,
+    /// Moved with no button held, only reported in any-event tracking.
+    Move,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// `None` for moves, and for releases in the legacy encoding which
+    /// does not say which button went up.
+    pub button: Option<MouseButton>,
+    /// Cell on the host terminal, from 0.
+    pub col: u16,
+    pub row: u16,
+    pub modifiers: Modifiers,
+}
+
+impl MouseEvent {
+    /// Decodes the button code of an xterm mouse report.
+    fn from_code(code: u32, col: u16, row: u16, release: bool) -> MouseEvent {
+        let modifiers = Modifiers {
+            shift: code & 4 != 0,
+            alt: code & 8 != 0,
+            ctrl: code & 16 != 0,
+            meta: false,
+        };
+        let low = (code & 3) as u8;
+        let button = if code & 64 != 0 {
+            Some([MouseButton::WheelUp, MouseButton::WheelDown, MouseButton::WheelLeft, MouseButton::WheelRight][low as usize])
+        } else if code & 128 != 0 {
+            Some(MouseButton::Other(8 + low))
+        } else {
+            [Some(MouseButton::Left), Some(MouseButton::Middle), Some(MouseButton::Right), None][low as usize]
+        };
+        let action = if release {
+            MouseAction::Release
+        } else if code & 32 != 0 && button.is_none() {
+            MouseAction::Move
+        } else if code & 32 != 0 {
+            MouseAction::Drag
+        } else if button.is_none() {
+            // the legacy encoding's release, button 3
+            MouseAction::Release
+        } else {
+            MouseAction::Press
+        };
+
+        MouseEvent {
+            action,
+            button,
+            col,
+            row,
+            modifiers,
+        }
+    }
+}
+
+/// One thing the user did on the host terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    FocusIn,
+    FocusOut,
+    /// Everything between the bracketed paste markers, as is.
+    Paste(Vec<u8>),
+    /// Bytes that decode to none of the above, like replies to queries or
+    /// malformed utf8.
+    Unknown(Vec<u8>),
+}
+
+/// Decodes what the host terminal sends on stdin. A sequence split across
+/// two reads is held back until the rest shows up. A lone ESC could be the
+/// Escape key or the start of a sequence, it is held until `expire` finds
+/// the escape timeout passed without anything following it.
+///
+/// Adaptive, a lone ESC at the end of a read is the Escape key right away,
+/// since terminals write a sequence in one go. Once the host is seen
+/// splitting sequences across reads, over a slow link say, it goes back to
+/// waiting until a run of reads comes whole again.
+#[derive(Debug)]
+pub struct InputParser {
+    pending: Vec<u8>,
+    /// When what is in `pending` arrived.
+    since: Option<Instant>,
+    escape_timeout: Duration,
+    adaptive: bool,
+    /// The host sent a sequence in more than one read.
+    splits: bool,
+    /// Reads without a split since the last one.
+    clean_reads: u32,
+    /// When an ESC ending a read was taken as the Escape key without
+    /// waiting.
+    eager_escape: Option<Instant>,
+}
+
+impl Default for InputParser {
+    fn default() -> Self {
+        InputParser {
+            pending: Vec::new(),
+            since: None,
+            escape_timeout: ESC_TIMEOUT,
+            adaptive: false,
+            splits: false,
+            clean_reads: 0,
+            eager_escape: None,
+        }
+    }
+}
+
+impl InputParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long a lone ESC waits for the rest of a sequence.
+    pub fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.escape_timeout = timeout;
+    }
+
+    pub fn escape_timeout(&self) -> Duration {
+        self.escape_timeout
+    }
+
+    pub fn set_adaptive(&mut self, adaptive: bool) {
+        self.adaptive = adaptive;
+        self.splits = false;
+        self.clean_reads = 0;
+    }
+
+    /// Adaptive and the host has not split a sequence yet, a lone ESC is
+    /// the Escape key without waiting.
+    pub fn is_eager(&self) -> bool {
+        self.adaptive && !self.splits
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<InputEvent> {
+        let now = Instant::now();
+        // the rest of a sequence whose ESC was taken as the Escape key, it
+        // still decodes as the key it is rather than as text
+        let eager = self.eager_escape.take().filter(|at| now.duration_since(*at) < SPLIT_WINDOW);
+        let mut split = eager.is_some() && self.pending.is_empty() && completes_sequence(bytes);
+        if split {
+            self.pending.push(ESC);
+        }
+        self.pending.extend_from_slice(bytes);
+        let mut events = self.parse(false);
+        if self.adaptive && !self.in_paste() {
+            split |= is_cut_sequence(&self.pending);
+            self.note_read(split);
+            // an Escape, or Alt-Escape, ends the read, nothing follows
+            if !self.splits && matches!(self.pending.as_slice(), [ESC] | [ESC, ESC]) {
+                self.eager_escape = Some(now);
+                events.append(&mut self.parse(true));
+            }
+        }
+        self.since = match self.pending.is_empty() {
+            true => {None},
+            false => {Some(Instant::now())}
+        };
+
+        events
+    }
+
+    /// Takes whatever is held back as complete, a lone ESC becomes the
+    /// Escape key and ESC followed by a key becomes that key with Alt.
+    pub fn flush(&mut self) -> Vec<InputEvent> {
+        self.since = None;
+        self.parse(true)
+    }
+
+    /// Flushes what is held back once it has waited long enough.
+    pub fn expire(&mut self, now: Instant) -> Vec<InputEvent> {
+        match self.timeout(now) {
+            Some(timeout) if timeout.is_zero() => {self.flush()},
+            _ => {Vec::new()}
+        }
+    }
+
+    /// How long until `expire` has something to flush. `None` when nothing
+    /// is held back or when it is a paste, which waits for its end marker
+    /// however long it takes.
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        if self.in_paste() {
+            return None;
+        }
+        let since = self.since?;
+        Some((since + self.escape_timeout).saturating_duration_since(now))
+    }
+
+    /// True while part of a sequence is waiting for more input.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Counts a read towards adaptive ESC being eager again, or starts over
+    /// on a split.
+    fn note_read(&mut self, split: bool) {
+        if split {
+            self.splits = true;
+            self.clean_reads = 0;
+        } else if self.splits {
+            self.clean_reads += 1;
+            if self.clean_reads >= RECOVER_READS {
+                self.splits = false;
+                self.clean_reads = 0;
+            }
+        }
+    }
+
+    fn in_paste(&self) -> bool {
+        self.pending.starts_with(b"\x1b[200~")
+    }
+
+    fn parse(&mut self, eof: bool) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        let mut pos = 0;
+        while let Some((event, used)) = parse_event(&self.pending[pos..], eof) {
+            if let Some(event) = event {
+                events.push(event);
+            }
+            pos += used;
+        }
+        self.pending.drain(..pos);
+
+        events
+    }
+}
+
+/// Whether ESC followed by `bytes` is one whole CSI or SS3 key, which is
+/// what a sequence split after its ESC looks like.
+fn completes_sequence(bytes: &[u8]) -> bool {
+    if !matches!(bytes.first(), Some(b'[' | b'O')) {
+        return false;
+    }
+    let mut joined = vec![ESC];
+    joined.extend_from_slice(bytes);
+    matches!(parse_event(&joined, false), Some((Some(InputEvent::Key(_)), used)) if used > 2)
+}
+
+/// Whether a read ended partway into a CSI or SS3 sequence. A bare `ESC [`
+/// or `ESC O` is Alt with the key more likely, and split utf8 or `ESC ESC`
+/// say nothing about how the host writes sequences.
+fn is_cut_sequence(pending: &[u8]) -> bool {
+    matches!(pending, [ESC, b'[' | b'O', _, ..] | [ESC, ESC, b'[' | b'O', ..])
+}
+
+/// Decodes the event at the start of `bytes` and says how many bytes it
+/// used, `None` when more input is needed. With `eof` nothing more is
+/// coming and whatever is there gets decoded. Events that decode to
+/// nothing, like kitty key releases, give `Some((None, used))`.
+fn parse_event(bytes: &[u8], eof: bool) -> Option<(Option<InputEvent>, usize)> {
+    let first = *bytes.first()?;
+    if first != ESC {
+        return match decode_utf8(bytes) {
+            Some((char::REPLACEMENT_CHARACTER, 1)) if first >= 0x80 => {Some((Some(InputEvent::Unknown(vec![first])), 1))},
+            Some((c, len)) => {Some((Some(InputEvent::Key(KeyEvent::from_char(c))), len))},
+            None if eof => {Some((Some(InputEvent::Unknown(bytes.to_vec())), bytes.len()))},
+            None => {None}
+        };
+    }
+
+    match bytes.get(1) {
+        None if eof => {Some((Some(InputEvent::Key(KeyEvent::plain(Key::Escape))), 1))},
+        None => {None},
+        Some(b'[') => {parse_csi(bytes, eof)},
+        Some(b'O') => {parse_ss3(bytes, eof)},
+        Some(&ESC) => {
+            // some terminals send Alt with a cursor or function key as
+            // ESC in front of its sequence
+            match bytes.get(2) {
+                Some(b'[') | Some(b'O') => {with_alt(bytes, eof)},
+                None if !eof => {None},
+                _ => {Some((Some(InputEvent::Key(KeyEvent::plain(Key::Escape))), 1))}
+            }
+        },
+        Some(_) => {with_alt(bytes, eof)}
+    }
+}
+
+/// ESC followed by a key is that key with Alt held.
+fn with_alt(bytes: &[u8], eof: bool) -> Option<(Option<InputEvent>, usize)> {
+    match parse_event(&bytes[1..], eof)? {
+        (Some(InputEvent::Key(key)), used) => {Some((Some(InputEvent::Key(key.with_alt())), used + 1))},
+        _ => {Some((Some(InputEvent::Key(KeyEvent::plain(Key::Escape))), 1))}
+    }
+}
+
+fn parse_ss3(bytes: &[u8], eof: bool) -> Option<(Option<InputEvent>, usize)> {
+    let mut pos = 2;
+    let mut modifiers = Modifiers::NONE;
+    // a few terminals put the modifier parameter right after the O
+    if let Some(digit @ b'1'..=b'9') = bytes.get(pos) {
+        modifiers = Modifiers::from_param((digit - b'0') as u32);
+        pos += 1;
+    }
+    let final_byte = match bytes.get(pos) {
+        Some(b) => {*b},
+        None if eof && pos == 2 => {return Some((Some(InputEvent::Key(KeyEvent::new(Key::Char('O'), Modifiers::ALT))), 2))},
+        None if eof => {return Some((Some(InputEvent::Unknown(bytes.to_vec())), bytes.len()))},
+        None => {return None}
+    };
+
+    let key = match final_byte {
+        b'A' => {Key::Up},
+        b'B' => {Key::Down},
+        b'C' => {Key::Right},
+        b'D' => {Key::Left},
+        b'H' => {Key::Home},
+        b'F' => {Key::End},
+        b'P'..=b'S' => {Key::F(final_byte - b'P' + 1)},
+        b'M' => {Key::KeypadEnter},
+        b'p'..=b'y' => {Key::Keypad((final_byte - b'p' + b'0') as char)},
+        b'j' => {Key::Keypad('*')},
+        b'k' => {Key::Keypad('+')},
+        b'l' => {Key::Keypad(',')},
+        b'm' => {Key::Keypad('-')},
+        b'n' => {Key::Keypad('.')},
+        b'o' => {Key::Keypad('/')},
+        b'X' => {Key::Keypad('=')},
+        _ => {return Some((Some(InputEvent::Unknown(bytes[..=pos].to_vec())), pos + 1))}
+    };
+    Some((Some(InputEvent::Key(KeyEvent::new(key, modifiers))), pos + 1))
+}
+
+fn parse_csi(bytes: &[u8], eof: bool) -> Option<(Option<InputEvent>, usize)> {
+    let mut marker = None;
+    let mut pos = 2;
+    let final_byte = loop {
+        match bytes.get(pos) {
+            Some(b @ (b'<' | b'=' | b'>' | b'?')) if pos == 2 => {marker = Some(*b)},
+            Some(b'0'..=b'9' | b';' | b':' | b' '..=b'/') => {},
+            Some(b @ b'@'..=b'~') => {break *b},
+            Some(_) => {
+                // a control char cuts the sequence short, it is a key of
+                // its own
+                return Some((Some(InputEvent::Unknown(bytes[..pos].to_vec())), pos));
+            },
+            None if eof && pos == 2 => {return Some((Some(InputEvent::Key(KeyEvent::new(Key::Char('['), Modifiers::ALT))), 2))},
+            None if eof => {return Some((Some(InputEvent::Unknown(bytes.to_vec())), bytes.len()))},
+            None => {return None}
+        }
+        pos += 1;
+    };
+    let used = pos + 1;
+    let params = std::str::from_utf8(&bytes[2 + marker.is_some() as usize..pos]).unwrap_or_default();
+
+    if marker.is_none() && params == "200" && final_byte == b'~' {
+        return parse_paste(bytes, used, eof);
+    }
+    if marker.is_none() && params.is_empty() && final_byte == b'M' {
+        // legacy mouse report, three more bytes each offset by 32
+        return match bytes.get(used..used + 3) {
+            Some(&[code, col, row]) => {
+                let code = code.saturating_sub(32) as u32;
+                let event = MouseEvent::from_code(code, col.saturating_sub(33) as u16, row.saturating_sub(33) as u16, false);
+                Some((Some(InputEvent::Mouse(event)), used + 3))
+            },
+            _ if eof => {Some((Some(InputEvent::Unknown(bytes.to_vec())), bytes.len()))},
+            _ => {None}
+        };
+    }
+
+    match csi_event(marker, params, final_byte) {
+        Some(event) => {Some((event, used))},
+        None => {Some((Some(InputEvent::Unknown(bytes[..used].to_vec())), used))}
+    }
+}
+
+/// Reads a bracketed paste up to its end marker, `start` is where its
+/// content begins.
+fn parse_paste(bytes: &[u8], start: usize, eof: bool) -> Option<(Option<InputEvent>, usize)> {
+    let content = &bytes[start..];
+    match content.windows(PASTE_END.len()).position(|window| window == PASTE_END) {
+        Some(end) => {Some((Some(InputEvent::Paste(content[..end].to_vec())), start + end + PASTE_END.len()))},
+        None if eof => {Some((Some(InputEvent::Paste(content.to_vec())), bytes.len()))},
+        None => {None}
+    }
+}
+
+/// Numbers of each `;` separated parameter, split further on `:`. Missing
+/// numbers are `None`.
+fn split_params(params: &str) -> Vec<Vec<Option<u32>>> {
+    params.split(';')
+        .map(|param| param.split(':').map(|n| n.parse().ok()).collect())
+        .collect()
+}
+
+/// The event of a complete CSI sequence, `None` when it is not one.
+fn csi_event(marker: Option<u8>, params: &str, final_byte: u8) -> Option<Option<InputEvent>> {
+    let fields = split_params(params);
+    let param = |i: usize, sub: usize| fields.get(i).and_then(|field| field.get(sub).copied().flatten());
+    let modifiers = Modifiers::from_param(param(1, 0).unwrap_or(1));
+    let key = |key: Key| Some(Some(InputEvent::Key(KeyEvent::new(key, modifiers))));
+
+    match (marker, final_byte) {
+        (Some(b'<'), b'M' | b'm') => {
+            let (code, col, row) = (param(0, 0)?, param(1, 0)?, param(2, 0)?);
+            let event = MouseEvent::from_code(code, col.saturating_sub(1) as u16, row.saturating_sub(1) as u16, final_byte == b'm');
+            Some(Some(InputEvent::Mouse(event)))
+        },
+        (Some(_), _) => {None},
+        (None, b'I') if params.is_empty() => {Some(Some(InputEvent::FocusIn))},
+        (None, b'O') if params.is_empty() => {Some(Some(InputEvent::FocusOut))},
+        // with no modifiers the first parameter is left out, with some it
+        // is 1
+        (None, b'A'..=b'D' | b'H' | b'F' | b'P'..=b'S' | b'Z') if param(0, 0).unwrap_or(1) == 1 => {
+            match final_byte {
+                b'A' => {key(Key::Up)},
+                b'B' => {key(Key::Down)},
+                b'C' => {key(Key::Right)},
+                b'D' => {key(Key::Left)},
+                b'H' => {key(Key::Home)},
+                b'F' => {key(Key::End)},
+                b'Z' => {Some(Some(InputEvent::Key(KeyEvent::new(Key::Tab, Modifiers { shift: true, ..modifiers }))))},
+                _ => {key(Key::F(final_byte - b'P' + 1))}
+            }
+        },
+        (None, b'~') => {
+            match param(0, 0)? {
+                1 | 7 => {key(Key::Home)},
+                2 => {key(Key::Insert)},
+                3 => {key(Key::Delete)},
+                4 | 8 => {key(Key::End)},
+                5 => {key(Key::PageUp)},
+                6 => {key(Key::PageDown)},
+                n @ 11..=15 => {key(Key::F(n as u8 - 10))},
+                n @ 17..=21 => {key(Key::F(n as u8 - 11))},
+                n @ 23..=26 => {key(Key::F(n as u8 - 12))},
+                n @ 28..=29 => {key(Key::F(n as u8 - 13))},
+                n @ 31..=34 => {key(Key::F(n as u8 - 14))},
+                // xterm modifyOtherKeys, CSI 27 ; modifiers ; code ~
+                27 => {key(code_key(param(2, 0)?)?)},
+                _ => {None}
+            }
+        },
+        // kitty keyboard protocol and fixterms, CSI code ; modifiers u
+        (None, b'u') => {
+            // event types are 1 press, 2 repeat and 3 release
+            if param(1, 1) == Some(3) {
+                return Some(None);
+            }
+            key(code_key(param(0, 0)?)?)
+        },
+        _ => {None}
+    }
+}
+
+/// The key a kitty or modifyOtherKeys key code stands for.
+fn code_key(code: u32) -> Option<Key> {
+    let key = match code {
+        9 => {Key::Tab},
+        13 => {Key::Enter},
+        27 => {Key::Escape},
+        127 => {Key::Backspace},
+        57399..=57408 => {Key::Keypad(char::from_digit(code - 57399, 10)?)},
+        57409 => {Key::Keypad('.')},
+        57410 => {Key::Keypad('/')},
+        57411 => {Key::Keypad('*')},
+        57412 => {Key::Keypad('-')},
+        57413 => {Key::Keypad('+')},
+        57414 => {Key::KeypadEnter},
+        57415 => {Key::Keypad('=')},
+        // the rest of kitty's private use area is keys fi has no use for
+        57344..=63743 => {return None},
+        _ => {Key::Char(char::from_u32(code)?)}
+    };
+    Some(key)
+}
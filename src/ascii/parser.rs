@@ -6,9 +6,42 @@ use super::escapes::*;
 pub fn parse_sequences(input: Cow<str>) -> Vec<Sequence> {
     let mut chars = input.chars().peekable();
 
-    let sequences = Sequence::parse_sequence(&mut chars);
+    Sequence::parse_sequence(&mut chars)
+}
+
+pub fn parse_bytes(bytes: &[u8]) -> Vec<Sequence> {
+    SequenceParser::new().feed(bytes)
+}
 
-    sequences
+/// Parses pty output as it arrives. A sequence split across two reads is
+/// held back until the rest of it shows up.
+#[derive(Debug, Default)]
+pub struct SequenceParser {
+    pending: Vec<u8>,
 }
 
+impl SequenceParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Sequence> {
+        self.pending.extend_from_slice(bytes);
+        let mut sequences = Vec::new();
+        let mut pos = 0;
+        while let Some((sequence, used)) = Sequence::parse_prefix(&self.pending[pos..]) {
+            if let Some(sequence) = sequence {
+                sequences.push(sequence);
+            }
+            pos += used;
+        }
+        self.pending.drain(..pos);
+
+        sequences
+    }
+
+    /// True while part of a sequence is waiting for more input.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
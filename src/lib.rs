@@ -1,4 +1,5 @@
 pub mod ascii;
 pub mod pty;
 pub mod logger;
+pub mod screen;
 
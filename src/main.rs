@@ -1,15 +1,18 @@
 extern crate fi;
 use std::io;
-use std::io::Read;
-use std::thread;
 use std::time::Duration;
 
 use fi::ascii::parser::*;
 use fi::ascii::escapes::*;
+use fi::ascii::input::ESC_TIMEOUT;
+use fi::pty::exit::ExitPolicy;
 use fi::pty::forker::*;
-use portable_pty::CommandBuilder;
+use fi::pty::frame::DEFAULT_FPS;
+use fi::pty::keymap::{Keymap, CHORD_TIMEOUT};
+use fi::pty::raw_mode::window_size;
+use fi::pty::spawn::SpawnSpec;
 fn main() -> io::Result<()>{
-    let mut buffer = Vec::new();
+    let buffer = Vec::new();
     //std::io::stdin().read_to_end(&mut buffer).expect("Failed to read input");
 
     let input = String::from_utf8_lossy(&buffer);
@@ -19,15 +22,100 @@ fn main() -> io::Result<()>{
         match sequence {
             Sequence::Text(text) => println!("Text: {:?}", text),
             Sequence::Escape(esc) => println!("Escape: {:?}", esc),
-            _ => {}
         }
     }
 
-    let (handle,p_term) = PTerminal::new(80, 40, 0, 0)?;
-
-
-    handle.join();
+    // `fi [--on-exit close|hold|respawn] [--fps N] [--keys FILE]
+    // [--visual-bell] [--escape-timeout MS|adaptive] [--chord-timeout MS]
+    // program args...` runs that program instead of the login shell
+    let mut args = std::env::args().skip(1).peekable();
+    let mut exit_policy = ExitPolicy::default();
+    let mut fps = DEFAULT_FPS;
+    let mut keys = Keymap::config_path();
+    let mut visual_bell = false;
+    let mut escape_timeout = (ESC_TIMEOUT, false);
+    let mut chord_timeout = CHORD_TIMEOUT;
+    loop {
+        match args.peek().map(String::as_str) {
+            Some("--on-exit") => {
+                args.next();
+                exit_policy = match args.next().as_deref().and_then(ExitPolicy::from_name) {
+                    Some(policy) => {policy},
+                    None => {
+                        eprintln!("--on-exit takes close, hold or respawn");
+                        std::process::exit(2);
+                    }
+                };
+            },
+            Some("--fps") => {
+                args.next();
+                fps = match args.next().and_then(|fps| fps.parse().ok()) {
+                    Some(fps) => {fps},
+                    None => {
+                        eprintln!("--fps takes a number of frames per second, 0 for no cap");
+                        std::process::exit(2);
+                    }
+                };
+            },
+            Some("--keys") => {
+                args.next();
+                keys = match args.next() {
+                    Some(path) => {Some(path.into())},
+                    None => {
+                        eprintln!("--keys takes the file to read key bindings from");
+                        std::process::exit(2);
+                    }
+                };
+            },
+            Some("--escape-timeout") => {
+                args.next();
+                let arg = args.next();
+                escape_timeout = match (arg.as_deref(), arg.as_deref().and_then(|ms| ms.parse().ok())) {
+                    (Some("adaptive"), _) => {(ESC_TIMEOUT, true)},
+                    (_, Some(ms)) => {(Duration::from_millis(ms), false)},
+                    _ => {
+                        eprintln!("--escape-timeout takes milliseconds or adaptive");
+                        std::process::exit(2);
+                    }
+                };
+            },
+            Some("--chord-timeout") => {
+                args.next();
+                chord_timeout = match args.next().and_then(|ms| ms.parse().ok()) {
+                    Some(ms) => {Duration::from_millis(ms)},
+                    None => {
+                        eprintln!("--chord-timeout takes milliseconds");
+                        std::process::exit(2);
+                    }
+                };
+            },
+            Some("--visual-bell") => {
+                args.next();
+                visual_bell = true;
+            },
+            _ => {break}
+        }
+    }
+    let keymap = match keys.as_deref().map(Keymap::load).unwrap_or_else(|| Ok(Keymap::default())) {
+        Ok(keymap) => {keymap},
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let spawn = match args.next() {
+        Some(program) => {SpawnSpec::program(program, args.collect())},
+        None => {SpawnSpec::shell()}
+    };
 
+    let (size_x, size_y) = window_size().unwrap_or((80, 40));
+    let mut p_term = PTerminal::new(spawn, size_x, size_y, 0, 0)?;
+    p_term.set_exit_policy(exit_policy);
+    p_term.set_frame_rate(fps);
+    p_term.set_keymap(keymap);
+    p_term.set_visual_bell(visual_bell);
+    p_term.set_escape_timeout(escape_timeout.0, escape_timeout.1);
+    p_term.set_chord_timeout(chord_timeout);
 
-    Ok(())
+    p_term.run()
 }
@@ -1,2 +1,16 @@
+pub mod control;
+pub mod exit;
 pub mod forker;
+pub mod frame;
+pub mod guard;
+pub mod keymap;
+#[cfg(feature = "native-pty")]
+pub mod native;
+pub mod poll;
+pub mod process;
 pub mod raw_mode;
+pub mod registers;
+pub mod session;
+pub mod signals;
+pub mod spawn;
+pub mod writer;
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+/// Requests other threads can make of a running pane.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Control {
+    Resize(u16, u16),
+    Respawn,
+    /// Bytes to send to the child as if they were typed.
+    Input(Vec<u8>),
+    Close,
+}
+
+/// Sending half of the control channel. Every send also writes a byte to a
+/// pipe the event loop polls, so it wakes up without spinning.
+#[derive(Clone)]
+pub struct Controller {
+    sender: Sender<Control>,
+    wake: Arc<File>,
+}
+
+impl Controller {
+    pub fn send(&self, control: Control) -> io::Result<()> {
+        if self.sender.send(control).is_err() {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "pane is gone"));
+        }
+        match (&*self.wake).write(&[1]) {
+            // a full pipe already has a wake up pending
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {Ok(())},
+            Err(err) => {Err(err)},
+            Ok(_) => {Ok(())}
+        }
+    }
+}
+
+/// Receiving half, owned by the event loop.
+pub struct ControlReceiver {
+    receiver: Receiver<Control>,
+    wake: File,
+}
+
+impl ControlReceiver {
+    /// Everything sent since the last call, without blocking.
+    pub fn drain(&mut self) -> Vec<Control> {
+        let mut buf = [0u8; 64];
+        while let Ok(n) = self.wake.read(&mut buf) {
+            if n < buf.len() {
+                break;
+            }
+        }
+
+        self.receiver.try_iter().collect()
+    }
+
+    pub fn raw_fd(&self) -> RawFd {
+        self.wake.as_raw_fd()
+    }
+}
+
+pub fn channel() -> io::Result<(Controller, ControlReceiver)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let (sender, receiver) = mpsc::channel();
+
+    Ok((
+        Controller { sender, wake: Arc::new(write) },
+        ControlReceiver { receiver, wake: read },
+    ))
+}
@@ -0,0 +1,57 @@
+use portable_pty::ExitStatus;
+
+/// How a pane's child ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildExit {
+    pub code: u32,
+    /// Description of the signal that killed it, like "Terminated".
+    pub signal: Option<String>,
+}
+
+impl ChildExit {
+    pub fn from_status(status: &ExitStatus) -> Self {
+        // portable_pty keeps the signal private and only shows it here
+        let signal = status.to_string().strip_prefix("Terminated by ").map(str::to_owned);
+
+        ChildExit {
+            code: status.exit_code(),
+            signal,
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.signal.is_none() && self.code == 0
+    }
+
+    /// Shown at the bottom of a held pane.
+    pub fn banner(&self) -> String {
+        match &self.signal {
+            Some(signal) => {format!("[exited: {}]", signal)},
+            None => {format!("[exited {}]", self.code)}
+        }
+    }
+}
+
+/// What a pane does once its child exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExitPolicy {
+    /// Close the pane, and fi with it.
+    #[default]
+    Close,
+    /// Keep the last screen with a banner until the pane is closed or
+    /// respawned by hand.
+    Hold,
+    /// Start the program again in the directory it was last in.
+    Respawn,
+}
+
+impl ExitPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "close" => {Some(ExitPolicy::Close)},
+            "hold" => {Some(ExitPolicy::Hold)},
+            "respawn" => {Some(ExitPolicy::Respawn)},
+            _ => {None}
+        }
+    }
+}
@@ -1,201 +1,1189 @@
-use libc::termios as Termios;
 use std::ffi::CString;
-use std::thread::JoinHandle;
-use libc::{pid_t, TIOCGSID, ioctl, readlink};
-use portable_pty::{native_pty_system, CommandBuilder, PtyPair, PtySize};
-use std::sync::{Arc, Mutex};
-use std::io::{self, Read, Stdout, Write};
-use super::raw_mode::raw_mode;
-use crate::ascii::escapes::{Escape, Sequence, ParsableSequence};
-//use crate::logger::log_message;
+use libc::readlink;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+use super::control::{channel, Control, ControlReceiver, Controller};
+use super::exit::{ChildExit, ExitPolicy};
+use super::frame::{FramePacer, FRAME_BYTES, READ_BUDGET};
+use super::poll::PollSet;
+use super::process::ProcessInfo;
+use super::registers::Registers;
+use super::guard::TerminalGuard;
+use super::keymap::{Action, InputMode, Keymap, Lookup, CHORD_TIMEOUT};
+use super::session::{Session, SessionId, SessionManager};
+use super::signals::SignalPipe;
+use super::spawn::{CwdSource, SpawnSpec};
+use crate::ascii::escapes::{encode_input, encode_mouse, Escape, Sequence};
+use crate::ascii::input::{InputEvent, InputParser, Key, KeyEvent, MouseAction, MouseButton, MouseEvent};
+use crate::screen::grid::Screen;
+use crate::screen::modes::{KeyboardFlags, MouseTracking};
+use crate::screen::render::{render_badge, render_lines, render_screen, render_selection, render_status};
+use crate::screen::selection::Selection;
+use crate::logger::log_message;
 use crate::error_log;
 
+/// Children exiting quicker than this are held instead of respawned, so a
+/// program that fails at startup does not respawn in a tight loop.
+const MIN_RESPAWN_UPTIME: Duration = Duration::from_secs(1);
+/// Mouse events the host always reports, fi selects text with drags.
+const HOST_MOUSE: MouseTracking = MouseTracking::Drag;
+/// Lines of history one step of the mouse wheel scrolls.
+const WHEEL_LINES: isize = 3;
+/// How long the visual bell shows the pane inverted.
+const BELL_FLASH: Duration = Duration::from_millis(100);
+
+/// The pane on the host terminal and the sessions it can show, one at a
+/// time. Everything happens on the thread calling `run`, other threads
+/// talk to it through a `Controller`.
 pub struct PTerminal{
     writer: Stdout,
     to_write: Vec<u8>,
-    buffer_hist: Vec<Sequence>,
-    raw_mode: Option<Termios>,
-    pub join_handler: bool,
+    /// Mode changes, titles and bells for the host. Unlike output in
+    /// `to_write` these are never dropped for a repaint.
+    host_control: Vec<u8>,
+    pacer: FramePacer,
+    input: InputParser,
+    keymap: Keymap,
+    /// The keymap has keys only modifyOtherKeys or the kitty protocol
+    /// tell apart.
+    extended_keys: bool,
+    /// Keys typed so far of a binding that takes more.
+    chord: Vec<KeyEvent>,
+    chord_since: Option<Instant>,
+    chord_timeout: Duration,
+    registers: Registers,
+    /// Record or replay waiting for the key that names its register.
+    pending_register: Option<Action>,
+    /// Keys go to every session of `group` instead of just the shown one.
+    broadcasting: bool,
+    /// Sessions picked for broadcasting, all of them when empty.
+    group: Vec<SessionId>,
+    /// Output was too much to pass through, the next frame is drawn from
+    /// the screen model.
+    stale: bool,
+    sessions: SessionManager,
+    /// The session on screen, always one of `sessions` while open.
+    active: SessionId,
+    view: Option<usize>,
+    selection: Option<Selection>,
+    /// Mouse tracking mode the host is in.
+    host_mouse: MouseTracking,
+    /// The host reports keys with modifyOtherKeys and the kitty protocol.
+    host_keys: bool,
+    /// Title last set on the host.
+    host_title: Option<String>,
+    /// The host terminal has focus, as far as its reports tell.
+    host_focused: bool,
+    /// Put in front of the shown session's title on the host.
+    name: String,
+    /// The bell flashes the pane instead of ringing the host's.
+    visual_bell: bool,
+    /// When the pane, inverted by the visual bell, is drawn again.
+    flash_until: Option<Instant>,
+    guard: TerminalGuard,
+    /// Whether keys go to the child or to fi's commands.
+    mode: InputMode,
+    closed: bool,
+    exit_policy: ExitPolicy,
+    /// 'q' was pressed while a job was running, pressing it again closes.
+    confirm_close: bool,
+    /// A paste of several lines waiting for a yes, the shown program does
+    /// not take bracketed pastes and would run each line as it came in.
+    pending_paste: Option<Vec<u8>>,
     size_x: u16,
     size_y: u16,
     offset_x: u32,
     offset_y: u32,
-    child: Box<dyn portable_pty::Child + Send + Sync>,
-    pty_pair: PtyPair,
-    pty_writer: Box<dyn Write + Send>,
-    pty_reader: Arc<Mutex<Box<dyn Read + Send>>>,
+    signals: SignalPipe,
+    control: ControlReceiver,
+    controller: Controller,
 }
 
 impl PTerminal {
     pub fn new(
+        spawn: SpawnSpec,
         size_x: u16,
         size_y: u16,
         offset_x: u32,
         offset_y: u32,
-    ) -> io::Result<(JoinHandle<()>,Arc<Mutex<PTerminal>>)> {
-        let mut cmd = CommandBuilder::new("bash");
-        cmd.arg("-l");
-        let raw_mode = raw_mode(None)?;
-
-        let pair = native_pty_system().openpty(PtySize {
-            rows: size_y,
-            cols: size_x,
-            pixel_width: 0,
-            pixel_height: 0,
-        });
-
-        let pair = match pair {
-            Ok(pair) => {pair},
-            Err(_) => {return Err(error_log!("failed to make pty pair"))}
-        };
-
+    ) -> io::Result<PTerminal> {
+        let signals = SignalPipe::install(&[
+            libc::SIGWINCH,
+            libc::SIGCHLD,
+            libc::SIGINT,
+            libc::SIGTERM,
+            libc::SIGHUP,
+        ])?;
+        let (controller, control) = channel()?;
 
-        let child = match pair.slave.spawn_command(cmd) {
-            Ok(child) => {child},
-            Err(_) => {return Err(error_log!("failed to spawn process"))}
-        };
-
-
-        let reader = Arc::new(Mutex::new(pair.master.try_clone_reader().expect("OOF")));
-        let writer = pair.master.take_writer().expect("OOF");
-        let to_write = Vec::new();
-        let buffer_hist = Vec::new();
+        let mut sessions = SessionManager::new();
+        let active = sessions.create(spawn, size_x, size_y)?;
+        let guard = TerminalGuard::enter()?;
 
-        let p_term = Arc::new(Mutex::new(PTerminal { 
+        let mut p_term = PTerminal {
             writer: io::stdout(),
-            to_write,
-            buffer_hist,
-            raw_mode,
-            join_handler: false,
+            to_write: Vec::new(),
+            host_control: Vec::new(),
+            pacer: FramePacer::default(),
+            input: InputParser::new(),
+            keymap: Keymap::default(),
+            extended_keys: false,
+            chord: Vec::new(),
+            chord_since: None,
+            chord_timeout: CHORD_TIMEOUT,
+            registers: Registers::new(),
+            pending_register: None,
+            broadcasting: false,
+            group: Vec::new(),
+            stale: false,
+            sessions,
+            active,
+            view: None,
+            selection: None,
+            host_mouse: MouseTracking::Off,
+            host_keys: false,
+            host_title: None,
+            host_focused: true,
+            name: String::from("fi"),
+            visual_bell: false,
+            flash_until: None,
+            guard,
+            mode: InputMode::Normal,
+            closed: false,
+            exit_policy: ExitPolicy::default(),
+            confirm_close: false,
+            pending_paste: None,
             size_x,
             size_y,
             offset_x,
             offset_y,
-            child,
-            pty_pair: pair,
-            pty_writer: writer,
-            pty_reader: reader,
-        }));
-        let p_term_2 = p_term.clone();
-        let p_term_3 = p_term.clone();
-        if let Ok(mut p_term) = p_term.lock() {
-            p_term.queue(Sequence::Escape(vec![
-                Escape::EnterAltScreen,
-                Escape::ClearAll
-            ]))?;
-
-            p_term.flush()?;
-        }
-
-        let pty_handler = std::thread::spawn(move || {
-            loop{
-            if let Ok(p_term) = p_term_2.lock() {
-                if p_term.join_handler {
-                    break;
+            signals,
+            control,
+            controller,
+        };
+        p_term.watch(active);
+        p_term.queue(Sequence::Escape(vec![
+            Escape::EnterAltScreen,
+            Escape::ClearAll,
+            // pastes always come bracketed, they are wrapped again for
+            // the child only if it asks
+            Escape::EnableBracketedPaste,
+            Escape::EnableFocusReports,
+            // the host's own title comes back on restore
+            Escape::PushTitle
+        ]))?;
+        p_term.sync_host_mouse();
+        p_term.sync_host_keys();
+        p_term.flush()?;
+
+        Ok(p_term)
+    }
+
+    /// Handle for sending requests to the pane from other threads.
+    pub fn controller(&self) -> Controller {
+        self.controller.clone()
+    }
+
+    /// Caps how often the host is drawn, 0 draws on every read.
+    pub fn set_frame_rate(&mut self, fps: u32) {
+        self.pacer = FramePacer::new(fps);
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.extended_keys = keymap.needs_extended_keys();
+        self.keymap = keymap;
+        self.chord.clear();
+        self.chord_since = None;
+    }
+
+    /// How long a key that starts a longer binding waits for the next.
+    pub fn set_chord_timeout(&mut self, timeout: Duration) {
+        self.chord_timeout = timeout;
+    }
+
+    /// How long a lone ESC from the host waits to be the start of a
+    /// sequence before it is the Escape key. Adaptive, it does not wait
+    /// unless the host splits sequences across reads.
+    pub fn set_escape_timeout(&mut self, timeout: Duration, adaptive: bool) {
+        self.input.set_escape_timeout(timeout);
+        self.input.set_adaptive(adaptive);
+    }
+
+    /// Name the host's title starts with, `fi` by default.
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn set_visual_bell(&mut self, visual_bell: bool) {
+        self.visual_bell = visual_bell;
+    }
+
+    pub fn set_exit_policy(&mut self, policy: ExitPolicy) {
+        self.exit_policy = policy;
+    }
+
+    /// How the shown session's child ended, while it is held after exiting.
+    pub fn exit_status(&self) -> Option<&ChildExit> {
+        self.session().exit_status()
+    }
+
+    pub fn sessions(&self) -> &SessionManager {
+        &self.sessions
+    }
+
+    pub fn active_session(&self) -> SessionId {
+        self.active
+    }
+
+    /// Starts another session at the pane's size, without showing it.
+    pub fn new_session(&mut self, spawn: SpawnSpec) -> io::Result<SessionId> {
+        let id = self.sessions.create(spawn, self.size_x, self.size_y)?;
+        self.watch(id);
+        Ok(id)
+    }
+
+    /// Shows session `id` in the pane.
+    pub fn switch_session(&mut self, id: SessionId) -> io::Result<()> {
+        if self.sessions.get(id).is_none() {
+            return Err(error_log!("no such session"));
+        }
+        if self.host_focused && id != self.active {
+            self.report_focus(self.active, InputEvent::FocusOut)?;
+            self.report_focus(id, InputEvent::FocusIn)?;
+        }
+        self.active = id;
+        self.view = None;
+        self.selection = None;
+        self.confirm_close = false;
+        self.pending_paste = None;
+        self.pending_register = None;
+        self.repaint()
+    }
+
+    fn session(&self) -> &Session {
+        self.sessions.get(self.active).expect("the active session exists while open")
+    }
+
+    fn session_mut(&mut self) -> &mut Session {
+        self.sessions.get_mut(self.active).expect("the active session exists while open")
+    }
+
+    fn watch(&self, id: SessionId) {
+        if let Some(session) = self.sessions.get(id) {
+            self.guard.watch_child(session.child_pid(), session.pty_fd());
+        }
+    }
+
+    /// Runs the pane until it is closed. Waits on every session's output,
+    /// the host's keys, signals and control requests all at once, so
+    /// nothing spins while the pane is idle. Output is drawn at most once
+    /// a frame, however fast it comes.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 16384];
+        while !self.closed {
+            let mut poll = PollSet::new();
+            // a dead child's pty is never read again, polling it would spin
+            let ptys: Vec<(SessionId, usize)> = self.sessions.iter()
+                .filter(|session| session.is_readable())
+                .map(|session| (session.id(), poll.add(session.pty_fd())))
+                .collect();
+            let writable: Vec<(SessionId, usize)> = self.sessions.iter()
+                .filter(|session| session.wants_write())
+                .map(|session| (session.id(), poll.add_writable(session.pty_fd())))
+                .collect();
+            // while a child is not taking input keys are left with the
+            // host tty, which in turn stops whoever is typing or pasting
+            let input_full = self.input_targets().into_iter()
+                .any(|id| self.sessions.get(id).is_some_and(Session::input_full));
+            let stdin = match input_full {
+                true => {None},
+                false => {Some(poll.add(libc::STDIN_FILENO))}
+            };
+            let signal = poll.add(self.signals.raw_fd());
+            let control = poll.add(self.control.raw_fd());
+            let now = Instant::now();
+            let frame = match self.frame_pending() && self.flash_until.is_none() {
+                true => {Some(self.pacer.wait(now))},
+                false => {None}
+            };
+            let chord = self.chord_since.map(|since| self.chord_timeout.saturating_sub(now - since));
+            let flash = self.flash_until.map(|until| until.saturating_duration_since(now));
+            let timeout = [frame, self.input.timeout(now), chord, flash].into_iter().flatten().min();
+            poll.wait(timeout)?;
+
+            if poll.readable(signal) {
+                self.on_signal()?;
+            }
+            if poll.readable(control) {
+                for control in self.control.drain() {
+                    self.on_control(control)?;
                 }
-                let test = p_term.pty_reader.clone();
-                drop(p_term);
-                if let Ok(mut test) = test.lock() {
-                    let seqs = Sequence::parse_writer(&mut test);
-                    if let Ok(mut p_term) = p_term_2.lock() {
-                        if p_term.join_handler {
-                            break;
-                        }
-                        for seq in seqs {
-                            match seq {
-                                Sequence::Text(t) => {
-                                    p_term.to_write.push(t as u8);
-                                },
-                                Sequence::Escape(escs) => {
-                                    for esc in escs {
-                                        p_term.to_write.append(&mut esc.into_bytes());
-                                    }
-                                }
-                            }
+            }
+            for (id, pty) in writable {
+                if poll.writable(pty) || poll.hung_up(pty) {
+                    if let Some(session) = self.sessions.get_mut(id) {
+                        session.flush_input()?;
+                    }
+                }
+            }
+            if stdin.is_some_and(|stdin| poll.readable(stdin) || poll.hung_up(stdin)) {
+                match read_fd(libc::STDIN_FILENO, &mut buf)? {
+                    0 => {self.close()?},
+                    n => {
+                        let events = self.input.feed(&buf[..n]);
+                        self.on_input(events)?;
+                    }
+                }
+            }
+            // a lone ESC nothing followed is the Escape key
+            let events = self.input.expire(Instant::now());
+            if !events.is_empty() {
+                self.on_input(events)?;
+            }
+            // a key that starts a longer binding and nothing followed
+            if self.chord_since.is_some_and(|since| since.elapsed() >= self.chord_timeout) {
+                let mut passthrough = Vec::new();
+                self.resolve_chord(&mut passthrough, true)?;
+                self.send_events(&passthrough)?;
+            }
+            for (id, pty) in ptys {
+                if self.closed || !(poll.readable(pty) || poll.hung_up(pty)) {
+                    continue;
+                }
+                self.drain_pty(id, &mut buf)?;
+            }
+            if !self.closed {
+                self.present()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads what session `id` has written, up to `READ_BUDGET` bytes so
+    /// a flood of output can not hold up the host's keys, Ctrl-C included.
+    fn drain_pty(&mut self, id: SessionId, buf: &mut [u8]) -> io::Result<()> {
+        let mut total = 0;
+        while total < READ_BUDGET && !self.closed {
+            let read = match self.sessions.get_mut(id) {
+                Some(session) if session.is_readable() => {session.read(buf)},
+                _ => {break}
+            };
+            match read {
+                Ok(0) => {return self.on_hangup(id)},
+                Ok(n) => {
+                    total += n;
+                    self.on_output(id, &buf[..n])?;
+                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {break},
+                Err(err) => {return Err(err)}
+            }
+        }
+        Ok(())
+    }
+
+    /// Output is waiting for the next frame.
+    fn frame_pending(&self) -> bool {
+        self.stale || !self.to_write.is_empty() || !self.host_control.is_empty()
+    }
+
+    /// Draws the output gathered since the last frame, if it is time to.
+    fn present(&mut self) -> io::Result<()> {
+        self.sync_host_mouse();
+        self.sync_host_keys();
+        self.sync_host_title();
+        let now = Instant::now();
+        match self.flash_until {
+            Some(until) if now < until => {return Ok(())},
+            Some(_) => {self.flash_until = None},
+            None => {}
+        }
+        if !self.frame_pending() || !self.pacer.ready(now) {
+            return Ok(());
+        }
+        if self.stale {
+            self.repaint()?;
+        } else {
+            self.flush()?;
+        }
+        self.pacer.drawn(now);
+        Ok(())
+    }
+
+    fn on_signal(&mut self) -> io::Result<()> {
+        match self.signals.wait()? {
+            libc::SIGWINCH => {
+                if let Ok((cols, rows)) = self.guard.window_size() {
+                    let size_x = cols.saturating_sub(self.offset_x as u16);
+                    let size_y = rows.saturating_sub(self.offset_y as u16);
+                    self.resize(size_x, size_y)?;
+                }
+                Ok(())
+            },
+            libc::SIGCHLD => {
+                self.sessions.reap_orphans();
+                for id in self.sessions.ids() {
+                    self.check_child(id)?;
+                }
+                Ok(())
+            },
+            libc::SIGINT | libc::SIGTERM | libc::SIGHUP => {self.close()},
+            _ => {Ok(())}
+        }
+    }
+
+    /// The pty was closed under us. Usually that is the child exiting, but
+    /// the pty can hang up before the child can be reaped. Such a session
+    /// waits for SIGCHLD to apply the exit policy.
+    fn on_hangup(&mut self, id: SessionId) -> io::Result<()> {
+        self.check_child(id)?;
+        if let Some(session) = self.sessions.get_mut(id).filter(|session| session.exit_status().is_none()) {
+            session.set_hung_up();
+        }
+        Ok(())
+    }
+
+    /// Reaps the session's child if it exited and applies the exit policy.
+    fn check_child(&mut self, id: SessionId) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let session = match self.sessions.get_mut(id) {
+            Some(session) => {session},
+            None => {return Ok(())}
+        };
+        let exit = match session.reap()? {
+            Some(exit) => {exit},
+            None => {return Ok(())}
+        };
+        log_message(&format!("session {} exited: {:?}", id, exit));
+        // whatever it printed last still belongs on the screen
+        let seqs = session.drain_output()?;
+        let uptime = session.uptime();
+        self.show_output(id, seqs)?;
+
+        match self.exit_policy {
+            ExitPolicy::Close => {self.remove_session(id)},
+            ExitPolicy::Respawn if uptime >= MIN_RESPAWN_UPTIME => {self.respawn_logged(id)},
+            ExitPolicy::Hold | ExitPolicy::Respawn => {self.hold(id, exit)}
+        }
+    }
+
+    /// Keeps the session on its last screen with the exit banner below.
+    fn hold(&mut self, id: SessionId, exit: ChildExit) -> io::Result<()> {
+        let banner = format!("\r\n\x1b[7m{}\x1b[0m", exit.banner());
+        self.on_output(id, banner.as_bytes())
+    }
+
+    /// Drops a session, showing another in its place. The last one going
+    /// closes the pane.
+    fn remove_session(&mut self, id: SessionId) -> io::Result<()> {
+        let next = self.sessions.next_after(id, true).filter(|next| *next != id);
+        if let Some(session) = self.sessions.get(id) {
+            self.guard.unwatch_child(session.pty_fd());
+        }
+        self.sessions.destroy(id);
+        self.group.retain(|member| *member != id);
+
+        match next {
+            Some(next) if id == self.active => {self.switch_session(next)},
+            Some(_) => {Ok(())},
+            None => {self.close()}
+        }
+    }
+
+    fn on_control(&mut self, control: Control) -> io::Result<()> {
+        match control {
+            Control::Resize(size_x, size_y) => {self.resize(size_x, size_y)},
+            Control::Respawn => {self.respawn_logged(self.active)},
+            Control::Input(bytes) => {self.send_input(&bytes)},
+            Control::Close => {self.close()}
+        }
+    }
+
+    fn on_input(&mut self, events: Vec<InputEvent>) -> io::Result<()> {
+        let mut passthrough = Vec::new();
+        for event in events {
+            if self.closed {
+                return Ok(());
+            }
+            match event {
+                InputEvent::Key(key) if self.pending_paste.is_some() => {
+                    self.send_events(&passthrough)?;
+                    passthrough.clear();
+                    self.confirm_paste(key)?;
+                },
+                InputEvent::Key(key) if self.pending_register.is_some() => {
+                    self.send_events(&passthrough)?;
+                    passthrough.clear();
+                    self.on_register(key)?;
+                },
+                InputEvent::Key(key) => {
+                    self.chord.push(key);
+                    self.resolve_chord(&mut passthrough, false)?;
+                },
+                InputEvent::FocusIn | InputEvent::FocusOut => {
+                    // only the shown session has the focus, broadcast or not
+                    self.send_events(&passthrough)?;
+                    passthrough.clear();
+                    self.host_focused = event == InputEvent::FocusIn;
+                    self.report_focus(self.active, event)?;
+                },
+                InputEvent::Paste(text) => {
+                    self.resolve_chord(&mut passthrough, true)?;
+                    if self.mode == InputMode::Insert {
+                        // each session gets the paste wrapped for its own
+                        // modes, one not taking bracketed pastes is enough
+                        // to ask
+                        let unbracketed = self.input_targets().into_iter()
+                            .any(|id| self.sessions.get(id).is_some_and(|session| !session.screen().modes().bracketed_paste));
+                        let lines = paste_lines(&text);
+                        if unbracketed && lines > 1 {
+                            self.send_events(&passthrough)?;
+                            passthrough.clear();
+                            self.pending_paste = Some(text);
+                            let prompt = format!(" paste {} lines, each runs as it arrives? | y paste  anything else cancels", lines);
+                            self.show_status(&prompt)?;
+                        } else {
+                            passthrough.push(InputEvent::Paste(text));
                         }
-                        let _ = p_term.flush();
                     }
-                };
+                },
+                InputEvent::Mouse(mouse) => {
+                    self.send_events(&passthrough)?;
+                    passthrough.clear();
+                    self.on_mouse(mouse)?;
+                },
+                event => {
+                    // anything but a key ends a chord
+                    self.resolve_chord(&mut passthrough, true)?;
+                    if self.mode == InputMode::Insert {
+                        passthrough.push(event);
+                    }
+                }
             }
+        }
+        self.send_events(&passthrough)
+    }
+
+    /// Works out what the keys in `chord` do. Keys that could still become
+    /// a longer binding wait for more unless `expired`. Keys no binding
+    /// takes go to the child in insert mode and are dropped in normal mode.
+    fn resolve_chord(&mut self, passthrough: &mut Vec<InputEvent>, expired: bool) -> io::Result<()> {
+        while !self.chord.is_empty() && !self.closed {
+            let bound = match self.keymap.lookup(self.mode, &self.chord) {
+                Lookup::Prefix(_) if !expired => {
+                    self.chord_since = Some(Instant::now());
+                    return Ok(());
+                },
+                Lookup::Action(action) | Lookup::Prefix(Some(action)) => {Some((self.chord.len(), action))},
+                // the longest start of the keys that is bound on its own
+                Lookup::Prefix(None) | Lookup::Unbound => {
+                    (1..self.chord.len()).rev()
+                        .find_map(|len| self.keymap.action(self.mode, &self.chord[..len]).map(|action| (len, action)))
+                }
+            };
+            match bound {
+                Some((len, action)) => {
+                    self.chord.drain(..len);
+                    // keys typed before the action still go to the child first
+                    self.send_events(passthrough)?;
+                    passthrough.clear();
+                    self.on_action(action)?;
+                },
+                None => {
+                    let key = self.chord.remove(0);
+                    self.on_unbound_key(key, passthrough)?;
+                }
             }
-            return;
-        });
+        }
+        self.chord_since = None;
+        Ok(())
+    }
 
-        let _key_listener_handler = std::thread::spawn(move || {
-            let mut key_buffer = [0;1];
-            let mut stdin = io::stdin();
-            let mut escaped = true;
-            loop {
-            if let Ok(mut p_term) = p_term_3.try_lock() {
-                if p_term.join_handler {
-                    break;
+    fn on_unbound_key(&mut self, key: KeyEvent, passthrough: &mut Vec<InputEvent>) -> io::Result<()> {
+        if self.mode == InputMode::Normal {
+            return self.cancel_close();
+        }
+        // typing goes back to the live screen
+        if self.view.is_some() || self.selection.is_some() {
+            self.selection = None;
+            self.view = None;
+            self.repaint()?;
+        }
+        passthrough.push(InputEvent::Key(key));
+        Ok(())
+    }
+
+    fn on_action(&mut self, action: Action) -> io::Result<()> {
+        if action != Action::Quit {
+            self.cancel_close()?;
+        }
+        match action {
+            Action::Normal => {self.mode = InputMode::Normal},
+            Action::Insert => {
+                self.leave_view()?;
+                self.mode = InputMode::Insert;
+            },
+            Action::Respawn => {self.respawn_logged(self.active)?},
+            Action::Quit => {
+                if !self.confirm_close {
+                    let job = self.sessions.iter().find_map(Session::running_job);
+                    if let Some(job) = job {
+                        self.confirm_close = true;
+                        let text = format!(" {} is still running | quit again to close anyway, anything else cancels", job.command_line());
+                        return self.show_status(&text);
+                    }
                 }
-                if escaped {
-                    if key_buffer[0] == 29 {
-                        escaped = true;
+                self.close()?;
+            },
+            Action::NewSession => {
+                let mut spawn = SpawnSpec::shell();
+                spawn.cwd = self.session().cwd();
+                let id = self.new_session(spawn)?;
+                self.switch_session(id)?;
+            },
+            Action::NextSession | Action::PreviousSession => {
+                if let Some(id) = self.sessions.next_after(self.active, action == Action::NextSession) {
+                    self.switch_session(id)?;
+                }
+            },
+            Action::PreviousPrompt => {self.jump_prompt(false)?},
+            Action::NextPrompt => {self.jump_prompt(true)?},
+            Action::Yank => {self.yank_output()?},
+            Action::Record => {
+                match self.registers.stop() {
+                    Some(name) => {self.show_status(&format!(" recorded register {}", name))?},
+                    None => {
+                        self.pending_register = Some(action);
+                        self.show_status(" record into register: a-z, A-Z adds to it")?;
                     }
-                    match key_buffer[0] as char {
-                        'r' => {
-                            if let Ok(pwd) = p_term.get_process_pwd() {
-                                let _ = p_term.respawn(&pwd);
-                            }                            
-                        }
-                        'q' => {
-                            let _ = p_term.close();
-                            break;
-                        },
-                        '\n' => {},
-                        'i' => {
-                            escaped = false;
-                        },
-                        _ => {}
-                    }        
-                    drop(p_term);
-                    let _ = stdin.read_exact(&mut key_buffer);
-                } else {
-                    if key_buffer[0] == 29 {
-                        escaped = true;
-                    } else {
-                        let _ = (p_term.pty_writer).write(&key_buffer); 
-                        let _ = p_term.pty_writer.flush();
+                }
+            },
+            Action::Replay => {
+                self.pending_register = Some(action);
+                self.show_status(" replay register: a-z, @ for the last one")?;
+            },
+            Action::Broadcast => {
+                self.broadcasting = !self.broadcasting;
+                // drawn again with or without the badge
+                self.stale = true;
+            },
+            Action::BroadcastGroup => {
+                match self.group.iter().position(|id| *id == self.active) {
+                    Some(index) => {self.group.remove(index);},
+                    None => {self.group.push(self.active)}
+                }
+                let text = match self.group.is_empty() {
+                    true => {String::from(" broadcast group is empty, broadcasting goes to every session")},
+                    false => {
+                        let ids: Vec<String> = self.group.iter().map(SessionId::to_string).collect();
+                        format!(" broadcast group: sessions {}", ids.join(", "))
                     }
-                    drop(p_term);
-                    let _ = stdin.read_exact(&mut key_buffer);
+                };
+                self.show_status(&text)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the paste waiting for a yes if `key` is one, drops it otherwise.
+    fn confirm_paste(&mut self, key: KeyEvent) -> io::Result<()> {
+        let text = match self.pending_paste.take() {
+            Some(text) => {text},
+            None => {return Ok(())}
+        };
+        self.repaint()?;
+        if key == KeyEvent::plain(Key::Char('y')) {
+            self.send_events(&[InputEvent::Paste(text)])?;
+        }
+        Ok(())
+    }
+
+    /// Records into or replays the register `key` names. Any key that is
+    /// not a register's name cancels.
+    fn on_register(&mut self, key: KeyEvent) -> io::Result<()> {
+        let action = match self.pending_register.take() {
+            Some(action) => {action},
+            None => {return Ok(())}
+        };
+        self.repaint()?;
+        let name = match key {
+            KeyEvent { key: Key::Char(c), modifiers } if modifiers.is_empty() => {c},
+            _ => {return Ok(())}
+        };
+        match action {
+            Action::Record if self.registers.start(name) => {
+                // what gets recorded is typed into the pane
+                self.leave_view()?;
+                self.mode = InputMode::Insert;
+                self.show_status(&format!(" recording register {}", name.to_ascii_lowercase()))
+            },
+            Action::Replay => {
+                match self.registers.replay(name) {
+                    Some(events) => {self.send_events(&events)},
+                    None if self.registers.recording() == Some(name.to_ascii_lowercase()) => {
+                        self.show_status(&format!(" register {} is being recorded", name))
+                    },
+                    None => {self.show_status(&format!(" register {} is empty", name))}
                 }
-            }}
+            },
+            _ => {self.show_status(&format!(" no register {}", name))}
+        }
+    }
+
+    /// Types the events recorded in register `name` into session `id`,
+    /// shown or not. False when the register is empty or being recorded.
+    pub fn replay(&mut self, name: char, id: SessionId) -> io::Result<bool> {
+        if self.sessions.get(id).is_none() {
+            return Err(error_log!("no such session"));
+        }
+        let events = match self.registers.replay(name) {
+            Some(events) => {events},
+            None => {return Ok(false)}
+        };
+        for event in &events {
+            self.registers.record(event);
+        }
+        self.write_events(id, &events)?;
+        Ok(true)
+    }
+
+    /// Takes back a quit that was waiting to be confirmed.
+    fn cancel_close(&mut self) -> io::Result<()> {
+        if !self.confirm_close {
+            return Ok(());
+        }
+        self.confirm_close = false;
+        self.repaint()
+    }
+
+    /// Mouse events inside the pane go to the shown program when it turned
+    /// on mouse tracking. With Shift held, in command mode or while
+    /// browsing history fi keeps them to select text and scroll.
+    fn on_mouse(&mut self, mut mouse: MouseEvent) -> io::Result<()> {
+        let col = mouse.col as i64 - self.offset_x as i64;
+        let row = mouse.row as i64 - self.offset_y as i64;
+        if col < 0 || row < 0 || col >= self.size_x as i64 || row >= self.size_y as i64 {
+            return Ok(());
+        }
+        mouse.col = col as u16;
+        mouse.row = row as u16;
+
+        let modes = self.session().screen().modes();
+        if modes.mouse != MouseTracking::Off && !mouse.modifiers.shift && self.mode == InputMode::Insert && self.view.is_none() {
+            let bytes = encode_mouse(&mouse, &modes);
+            return self.send_input(&bytes);
+        }
+
+        let line = self.view_top() + row as usize;
+        let col = col as usize;
+        match (mouse.action, mouse.button) {
+            (MouseAction::Press, Some(MouseButton::WheelUp)) => {self.scroll_view(-WHEEL_LINES)},
+            (MouseAction::Press, Some(MouseButton::WheelDown)) => {self.scroll_view(WHEEL_LINES)},
+            (MouseAction::Press, Some(MouseButton::Left)) => {
+                // clicking the live screen hands it the keyboard
+                if self.view.is_none() {
+                    self.mode = InputMode::Insert;
+                }
+                self.selection = Some(Selection::new(line, col));
+                self.repaint()
+            },
+            (MouseAction::Drag, Some(MouseButton::Left)) => {
+                match self.selection.as_mut() {
+                    Some(selection) => {
+                        selection.extend(line, col);
+                        self.repaint()
+                    },
+                    None => {Ok(())}
+                }
+            },
+            (MouseAction::Release, _) => {
+                match self.selection {
+                    Some(selection) if !selection.is_empty() => {
+                        let text = self.session().screen().selection_text(&selection);
+                        self.queue(Sequence::Escape(vec![Escape::CopyToClipboard(text)]))?;
+                        self.flush()
+                    },
+                    Some(_) => {
+                        self.selection = None;
+                        Ok(())
+                    },
+                    None => {Ok(())}
+                }
+            },
+            _ => {Ok(())}
+        }
+    }
+
+    /// First line of history on screen, the live screen's when not browsing.
+    fn view_top(&self) -> usize {
+        let live = self.session().screen().scrollback_len();
+        self.view.unwrap_or(live).min(live)
+    }
+
+    /// Moves the history view by `lines`, scrolling down past the live
+    /// screen's top leaves the view.
+    fn scroll_view(&mut self, lines: isize) -> io::Result<()> {
+        let live = self.session().screen().scrollback_len();
+        let top = self.view_top().saturating_add_signed(lines).min(live);
+        if top == live {
+            return self.leave_view();
+        }
+        self.view = Some(top);
+        self.draw_view()
+    }
+
+    /// Has the host report the mouse events fi wants, and whatever more
+    /// the shown program asked for.
+    fn sync_host_mouse(&mut self) {
+        let wanted = self.session().screen().modes().mouse.max(HOST_MOUSE);
+        if wanted == self.host_mouse {
+            return;
+        }
+        let mut escapes = match self.host_mouse {
+            MouseTracking::Off => {vec![Escape::EnableSgrMouse]},
+            MouseTracking::Clicks => {vec![Escape::DisableMouseClicks]},
+            MouseTracking::Drag => {vec![Escape::DisableMouseDrag]},
+            MouseTracking::Motion => {vec![Escape::DisableMouseMotion]}
+        };
+        escapes.push(match wanted {
+            MouseTracking::Off | MouseTracking::Clicks => {Escape::EnableMouseClicks},
+            MouseTracking::Drag => {Escape::EnableMouseDrag},
+            MouseTracking::Motion => {Escape::EnableMouseMotion}
         });
+        for escape in escapes {
+            self.host_control.append(&mut escape.into_bytes());
+        }
+        self.host_mouse = wanted;
+    }
 
-        Ok((pty_handler,p_term))
+    /// Tells session `id` it gained or lost focus, if it asked to know.
+    fn report_focus(&mut self, id: SessionId, event: InputEvent) -> io::Result<()> {
+        if let Some(session) = self.sessions.get_mut(id) {
+            let bytes = encode_input(&event, &session.screen().modes());
+            session.write(&bytes)?;
+        }
+        Ok(())
     }
 
+    /// Sets the host's title to the shown session's, after the pane's name
+    /// and the session's id, and says so while input is being recorded or
+    /// broadcast.
+    fn sync_host_title(&mut self) {
+        let mut title = format!("{}[{}] {}", self.name, self.active, self.session().title());
+        if let Some(name) = self.registers.recording() {
+            title.push_str(&format!(" (recording {})", name));
+        }
+        if self.broadcasting {
+            title.push_str(" (broadcasting)");
+        }
+        if self.host_title.as_ref() == Some(&title) {
+            return;
+        }
+        self.host_control.append(&mut Escape::SetTitle(title.clone()).into_bytes());
+        self.host_title = Some(title);
+    }
 
-    pub fn get_process_pwd(&self) -> io::Result<String> {
-        if let Some(process_id) = self.pty_pair.master.process_group_leader() {
-            let mut target = vec![0u8; 4096];
+    /// The shown session rings the host's bell or flashes the pane, the
+    /// others ring it and say which one did.
+    fn on_bell(&mut self, id: SessionId) -> io::Result<()> {
+        if id != self.active {
+            let title = match self.sessions.get(id) {
+                Some(session) => {session.title()},
+                None => {return Ok(())}
+            };
+            self.host_control.push(0x07);
+            return self.show_status(&format!(" bell in {}[{}] {}", self.name, id, title));
+        }
+        if !self.visual_bell {
+            self.host_control.push(0x07);
+            return Ok(());
+        }
+        if self.flash_until.is_some() {
+            return Ok(());
+        }
+        // the whole pane selected is the pane inverted
+        let screen = self.session().screen();
+        let top = self.view_top();
+        let mut pane = Selection::new(top, 0);
+        pane.extend(top + screen.rows() - 1, screen.cols() - 1);
+        let mut bytes = render_selection(screen, &pane, top, self.offset_x, self.offset_y);
+        self.to_write.append(&mut bytes);
+        self.flush()?;
+        self.flash_until = Some(Instant::now() + BELL_FLASH);
+        // drawn again from the model once the flash is over
+        self.stale = true;
+        Ok(())
+    }
 
+    /// Has the host tell keys like Ctrl-Enter apart, with modifyOtherKeys
+    /// and the kitty protocol both since a terminal may know either, when a
+    /// binding or the shown program needs them. Keys are decoded and encoded
+    /// again for the child, so the host reporting more than the child asked
+    /// for changes nothing it sees.
+    fn sync_host_keys(&mut self) {
+        let modes = self.session().screen().modes();
+        let wanted = self.extended_keys || modes.modify_other_keys > 0 || modes.keyboard.current() != 0;
+        if wanted == self.host_keys {
+            return;
+        }
+        let escapes = match wanted {
+            true => {[Escape::SetModifyOtherKeys(2), Escape::PushKeyboardFlags(KeyboardFlags::DISAMBIGUATE)]},
+            false => {[Escape::SetModifyOtherKeys(0), Escape::PopKeyboardFlags(1)]}
+        };
+        for escape in escapes {
+            self.host_control.append(&mut escape.into_bytes());
+        }
+        self.host_keys = wanted;
+    }
+
+    /// Types `events` into the shown session, or into every session of the
+    /// broadcast group while broadcasting, and records them if recording.
+    fn send_events(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        if events.is_empty() || self.closed {
+            return Ok(());
+        }
+        for event in events {
+            self.registers.record(event);
+        }
+        for id in self.input_targets() {
+            self.write_events(id, events)?;
+        }
+        Ok(())
+    }
 
-            let path = format!("/proc/{}/cwd", process_id);
+    /// Sends `events` to session `id` as one unit, encoded for its modes.
+    fn write_events(&mut self, id: SessionId, events: &[InputEvent]) -> io::Result<()> {
+        let session = match self.sessions.get_mut(id) {
+            Some(session) => {session},
+            None => {return Ok(())}
+        };
+        let modes = session.screen().modes();
+        let bytes: Vec<u8> = events.iter().flat_map(|event| encode_input(event, &modes)).collect();
+        if !session.write(&bytes)? {
+            log_message(&format!("input queue full, dropped {} bytes for session {}", bytes.len(), id));
+        }
+        Ok(())
+    }
 
-            match PTerminal::read_link_to_buf(&path, &mut target) {
-                Ok(n) if n > 0 => return Ok(String::from_utf8_lossy(&target[..n]).to_string()),
-                Ok(_) => {},
-                Err(_) => {}
-            }
+    /// Sessions typed keys go to. Broadcasting with no group picked goes to
+    /// all of them, and always to the shown one, but never to one whose
+    /// program exited.
+    fn input_targets(&self) -> Vec<SessionId> {
+        if !self.broadcasting {
+            return vec![self.active];
+        }
+        let mut targets: Vec<SessionId> = self.sessions.iter()
+            .filter(|session| session.exit_status().is_none())
+            .map(|session| session.id())
+            .filter(|id| self.group.is_empty() || self.group.contains(id) || *id == self.active)
+            .collect();
+        targets.sort_unstable();
+        targets
+    }
 
-            let mut sid: pid_t = 0;
-            if unsafe { ioctl(process_id as i32, TIOCGSID, &mut sid) } != -1 {
-                let path = format!("/proc/{}/cwd", sid);
-                match PTerminal::read_link_to_buf(&path, &mut target) {
-                    Ok(n) if n > 0 => return Ok(String::from_utf8_lossy(&target[..n]).to_string()),
-                    Ok(_) => {},
-                    Err(_) => {}
-                }
-            }
+    /// Sends input to the shown session as one unit.
+    fn send_input(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // keys typed after the one that closed the pane have nowhere to go
+        if self.closed {
+            return Ok(());
+        }
+        if !self.session_mut().write(bytes)? {
+            log_message(&format!("input queue full, dropped {} bytes", bytes.len()));
+        }
+        Ok(())
+    }
 
-            return Err(error_log!("process id returned None"));
+    fn on_output(&mut self, id: SessionId, bytes: &[u8]) -> io::Result<()> {
+        let seqs = match self.sessions.get_mut(id) {
+            Some(session) => {session.process(bytes)},
+            None => {return Ok(())}
+        };
+        // the host's key modes are fi's, it answers for the child's own
+        let queried = seqs.iter().any(|seq| matches!(seq, Sequence::Escape(escs) if escs.contains(&Escape::QueryKeyboardFlags)));
+        if let Some(session) = self.sessions.get_mut(id).filter(|_| queried) {
+            let reply = format!("\x1b[?{}u", session.screen().modes().keyboard.current());
+            session.write(reply.as_bytes())?;
+        }
+        if seqs.contains(&Sequence::Text('\x07')) {
+            self.on_bell(id)?;
+        }
+        self.show_output(id, seqs)
+    }
+
+    /// Queues a session's output for the next frame if it is on screen.
+    fn show_output(&mut self, id: SessionId, seqs: Vec<Sequence>) -> io::Result<()> {
+        // browsing history, the view is repainted from the screen model
+        // when it is left
+        if id != self.active || self.view.is_some() || self.stale {
+            return Ok(());
+        }
+        for seq in seqs {
+            // fi keeps the host's mouse, key, paste and focus modes to
+            // itself, the child's are applied when its input is encoded.
+            // Titles and bells are passed on by sync_host_title and on_bell.
+            let seq = match seq {
+                Sequence::Escape(mut escs) => {
+                    escs.retain(|esc| !is_mouse_mode(esc) && !is_input_mode(esc) && !is_title(esc));
+                    Sequence::Escape(escs)
+                },
+                Sequence::Text('\x07') => {continue},
+                text => {text}
+            };
+            self.queue(seq)?;
+        }
+        // the screen model has all of it, drawing that once is cheaper
+        // than passing every byte on. What fi told the host itself is in
+        // host_control and still goes out.
+        if self.to_write.len() > FRAME_BYTES {
+            self.to_write.clear();
+            self.stale = true;
+        }
+        Ok(())
+    }
+
+    fn respawn_logged(&mut self, id: SessionId) -> io::Result<()> {
+        let session = match self.sessions.get_mut(id) {
+            Some(session) => {session},
+            None => {return Ok(())}
+        };
+        let (cwd, source) = session.respawn()?;
+        log_message(&format!("respawned session {} in {:?}, directory from {:?}", id, cwd, source));
+        self.watch(id);
+        // the alt screen it may have left is drawn over
+        self.stale |= id == self.active;
+        Ok(())
+    }
+
+    /// Resizes the pane and every session in it.
+    pub fn resize(&mut self, size_x: u16, size_y: u16) -> io::Result<()> {
+        let size_x = size_x.max(1);
+        let size_y = size_y.max(1);
+        if size_x == self.size_x && size_y == self.size_y {
+            return Ok(());
+        }
+
+        self.size_x = size_x;
+        self.size_y = size_y;
+        for session in self.sessions.iter_mut() {
+            session.resize(size_x, size_y)?;
+        }
+        self.draw_view()
+    }
+
+    /// Moves the history view to the previous or next shell prompt marked
+    /// with OSC 133 and shows the command's exit status on a status line.
+    pub fn jump_prompt(&mut self, forward: bool) -> io::Result<()> {
+        let from = match self.view {
+            Some(line) => {line},
+            None => {self.session().screen().scrollback_len() + self.session().screen().cursor().0}
+        };
+        let target = if forward {
+            self.session().screen().next_prompt(from)
         } else {
-            return Err(error_log!("process id returned None"));
+            self.session().screen().previous_prompt(from)
+        };
+
+        if let Some(line) = target {
+            self.view = Some(line);
+            self.draw_view()?;
+        }
+        Ok(())
+    }
+
+    fn draw_view(&mut self) -> io::Result<()> {
+        let line = match self.view {
+            Some(line) => {line},
+            None => {return Ok(())}
+        };
+        let top = line.min(self.session().screen().scrollback_len());
+        let mut bytes = render_lines(self.session().screen(), top, self.offset_x, self.offset_y);
+        if let Some(selection) = &self.selection {
+            bytes.append(&mut render_selection(self.session().screen(), selection, top, self.offset_x, self.offset_y));
+        }
+
+        let commands = self.session().screen().commands();
+        if let Some(i) = commands.iter().position(|zone| zone.prompt_line == line) {
+            let zone = &commands[i];
+            let status = match (zone.finished, zone.exit_status) {
+                (true, Some(code)) => {format!("exit {}", code)},
+                (true, None) => {String::from("done")},
+                (false, _) if zone.output.is_some() => {
+                    match self.running_job() {
+                        Some(job) => {format!("running {}", job.command_line())},
+                        None => {String::from("running")}
+                    }
+                },
+                (false, _) => {String::from("no command")}
+            };
+            let text = format!(" prompt {}/{} | {} | [ ] jump  y yank  i insert", i + 1, commands.len(), status);
+            bytes.append(&mut render_status(self.session().screen(), &text, self.offset_x, self.offset_y));
+        }
+
+        self.to_write.append(&mut bytes);
+        self.flush()
+    }
+
+    /// Writes `text` on the bottom line until the next repaint.
+    fn show_status(&mut self, text: &str) -> io::Result<()> {
+        let mut bytes = render_status(self.session().screen(), text, self.offset_x, self.offset_y);
+        self.to_write.append(&mut bytes);
+        self.flush()
+    }
+
+    /// Draws the history view or the live screen again from the model.
+    fn repaint(&mut self) -> io::Result<()> {
+        self.stale = false;
+        if self.view.is_some() {
+            return self.draw_view();
+        }
+        let mut bytes = render_screen(self.session().screen(), self.offset_x, self.offset_y);
+        if let Some(selection) = &self.selection {
+            let top = self.session().screen().scrollback_len();
+            bytes.append(&mut render_selection(self.session().screen(), selection, top, self.offset_x, self.offset_y));
+        }
+        self.to_write.append(&mut bytes);
+        self.flush()
+    }
+
+    /// Copies the output of the viewed command, or of the last command when
+    /// not browsing, to the host clipboard with OSC 52.
+    pub fn yank_output(&mut self) -> io::Result<()> {
+        let zone = match self.view {
+            Some(line) => {self.session().screen().commands().into_iter().find(|zone| zone.prompt_line == line)},
+            None => {self.session().screen().last_command()}
+        };
+
+        if let Some(text) = zone.and_then(|zone| self.session().screen().command_output(&zone)) {
+            self.queue(Sequence::Escape(vec![Escape::CopyToClipboard(text)]))?;
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns from browsing history to the live screen.
+    pub fn leave_view(&mut self) -> io::Result<()> {
+        if self.view.take().is_some() {
+            self.repaint()?;
         }
+        Ok(())
+    }
+
+    /// What runs in the foreground of the shown session right now.
+    pub fn foreground_process(&self) -> io::Result<ProcessInfo> {
+        self.session().foreground_process()
+    }
+
+    /// The shown session's foreground job, unless that is its own program
+    /// idling.
+    fn running_job(&self) -> Option<ProcessInfo> {
+        self.session().running_job()
+    }
+
+    pub fn get_process_pwd(&self) -> io::Result<String> {
+        self.session().get_process_pwd()
     }
 
     pub fn read_link_to_buf(path: &str, buf: &mut [u8]) -> io::Result<usize> {
@@ -208,23 +1196,18 @@ impl PTerminal {
         }
     }
 
-    pub fn respawn(&mut self, working_dir: &String) -> io::Result<()>{
-        let mut cmd = CommandBuilder::new("sh");
-        cmd.arg("-c");
-        cmd.arg(format!("cd {}; bash", working_dir));
-
-        self.child.kill()?;
-        self.child = match self.pty_pair.slave.spawn_command(cmd) {
-            Ok(child) => {child},
-            Err(_) => {return Err(error_log!("failed to spawn process"))}
-        };
-        Ok(())
+    /// Restarts the shown session's program, see `Session::respawn`.
+    pub fn respawn(&mut self) -> io::Result<(Option<PathBuf>, CwdSource)> {
+        let result = self.session_mut().respawn()?;
+        self.watch(self.active);
+        Ok(result)
     }
 
     fn queue(&mut self, seq: Sequence) -> io::Result<()>{
         match seq {
             Sequence::Text(text) => {
-                self.to_write.push(text as u8);
+                let mut utf8 = [0; 4];
+                self.to_write.extend_from_slice(text.encode_utf8(&mut utf8).as_bytes());
             },
             Sequence::Escape(escs) => {
                 for esc in escs {
@@ -238,9 +1221,25 @@ impl PTerminal {
         Ok(())
     }
 
+    pub fn spawn_spec(&self) -> &SpawnSpec {
+        self.session().spawn_spec()
+    }
+
+    pub fn screen(&self) -> &Screen {
+        self.session().screen()
+    }
+
     pub fn flush(&mut self) -> io::Result<()>{
+        // last, so nothing drawn in the same frame covers it
+        if self.broadcasting && !self.closed && !self.to_write.is_empty() {
+            let text = format!(" BROADCAST to {} ", self.input_targets().len());
+            let mut badge = render_badge(self.session().screen(), &text, self.offset_x, self.offset_y);
+            self.to_write.append(&mut badge);
+        }
         let mut writer = self.writer.lock();
-        writer.write(&self.to_write)?;
+        writer.write_all(&self.host_control)?;
+        writer.write_all(&self.to_write)?;
+        self.host_control.clear();
         self.to_write.clear();
 
         writer.flush()?;
@@ -249,12 +1248,68 @@ impl PTerminal {
     }
 
     pub fn close(&mut self) -> io::Result<()>{
-        self.child.kill()?;
-        raw_mode(self.raw_mode)?;
-        self.queue(Sequence::Escape(vec![Escape::ExitAltScreen]))?;
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
         self.flush()?;
-        self.join_handler = true;
+        // hangs up the child's process group before leaving raw mode
+        self.guard.restore()?;
+        // the children may be gone already, which is why the pane is closing
+        for id in self.sessions.ids() {
+            self.sessions.destroy(id);
+        }
         Ok(())
     }
 
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+}
+
+fn read_fd(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Mouse modes are set on the host by fi, never by the program.
+fn is_mouse_mode(esc: &Escape) -> bool {
+    matches!(esc,
+        Escape::EnableMouseClicks | Escape::DisableMouseClicks |
+        Escape::EnableMouseDrag | Escape::DisableMouseDrag |
+        Escape::EnableMouseMotion | Escape::DisableMouseMotion |
+        Escape::EnableSgrMouse | Escape::DisableSgrMouse)
+}
+
+/// Key reporting, paste and focus modes are set on the host by fi, never by the
+/// program.
+fn is_input_mode(esc: &Escape) -> bool {
+    matches!(esc,
+        Escape::SetModifyOtherKeys(_) | Escape::PushKeyboardFlags(_) |
+        Escape::PopKeyboardFlags(_) | Escape::SetKeyboardFlags(_) |
+        Escape::QueryKeyboardFlags |
+        Escape::EnableBracketedPaste | Escape::DisableBracketedPaste |
+        Escape::EnableFocusReports | Escape::DisableFocusReports)
+}
+
+/// The host's title is the shown session's, set by fi.
+fn is_title(esc: &Escape) -> bool {
+    matches!(esc, Escape::SetTitle(_) | Escape::PushTitle | Escape::PopTitle)
+}
+
+/// Lines a paste would enter, a line break at the very end does not start
+/// another.
+fn paste_lines(text: &[u8]) -> usize {
+    let end = text.iter().rposition(|byte| *byte != b'\r' && *byte != b'\n').map_or(0, |last| last + 1);
+    let text = String::from_utf8_lossy(&text[..end]).replace("\r\n", "\n");
+    text.split(['\r', '\n']).count()
 }
@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+/// Frames per second the host is drawn at when nothing else is asked for.
+pub const DEFAULT_FPS: u32 = 60;
+/// Child output passed through in one frame past this is dropped, the
+/// frame is drawn from the screen model instead. Keeps a child flooding
+/// output from making the host terminal the bottleneck.
+pub const FRAME_BYTES: usize = 64 * 1024;
+/// Most a single session's pty is read before the other inputs, the
+/// host's keys in particular, get their turn.
+pub const READ_BUDGET: usize = 64 * 1024;
+
+/// Decides when output gathered since the last frame goes to the host.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl FramePacer {
+    /// `fps` of 0 draws every time there is something to draw.
+    pub fn new(fps: u32) -> FramePacer {
+        let interval = match fps {
+            0 => {Duration::ZERO},
+            fps => {Duration::from_secs(1) / fps}
+        };
+
+        FramePacer {
+            interval,
+            last: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// How long until the next frame may be drawn, zero when it may now.
+    pub fn wait(&self, now: Instant) -> Duration {
+        match self.last {
+            Some(last) => {(last + self.interval).saturating_duration_since(now)},
+            None => {Duration::ZERO}
+        }
+    }
+
+    pub fn ready(&self, now: Instant) -> bool {
+        self.wait(now).is_zero()
+    }
+
+    /// A frame went out at `now`.
+    pub fn drawn(&mut self, now: Instant) {
+        self.last = Some(now);
+    }
+}
+
+impl Default for FramePacer {
+    fn default() -> FramePacer {
+        FramePacer::new(DEFAULT_FPS)
+    }
+}
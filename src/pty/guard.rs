@@ -0,0 +1,160 @@
+use libc::pid_t;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::panic;
+use std::sync::{Mutex, Once, TryLockError};
+use super::raw_mode::TtyGuard;
+use crate::error_log;
+
+/// Turns off everything a pane may have switched on in the host terminal:
+/// colours, mouse reporting, focus events, bracketed paste, modifyOtherKeys
+/// and kitty keyboard flags, the hidden cursor and the alt screen, and
+/// brings back the title saved when the pane opened.
+const RESTORE: &[u8] = b"\x1b[0m\x1b[?1000l\x1b[?1002l\x1b[?1003l\x1b[?1006l\x1b[?1004l\x1b[?2004l\x1b[>4m\x1b[=0;1u\x1b[?25h\x1b[?1049l\x1b[23;0t";
+
+/// Kept global so the panic hook can get at it.
+static STATE: Mutex<GuardState> = Mutex::new(GuardState {
+    active: false,
+    tty: None,
+    children: Vec::new(),
+});
+static PANIC_HOOK: Once = Once::new();
+
+struct GuardState {
+    active: bool,
+    tty: Option<TtyGuard>,
+    /// Each watched child with the pty it runs in.
+    children: Vec<(Option<pid_t>, RawFd)>,
+}
+
+/// Holds the host terminal in raw mode and puts it back however fi goes
+/// away: dropped, closed, killed by a signal the event loop handles or a
+/// panic anywhere.
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    /// Fails while another guard holds the terminal, entering twice would
+    /// save the raw settings over the ones to restore.
+    pub fn enter() -> io::Result<TerminalGuard> {
+        {
+            let mut state = lock_state()?;
+            if state.active {
+                return Err(error_log!("terminal guard is already entered"));
+            }
+            let mut tty = TtyGuard::open()?;
+            tty.enter_raw()?;
+            state.active = true;
+            state.tty = Some(tty);
+        }
+        PANIC_HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                // restore first so the message lands on a sane screen
+                let _ = restore_terminal();
+                previous(info);
+            }));
+        });
+
+        Ok(TerminalGuard { _private: () })
+    }
+
+    /// A child whose process group gets hung up on restore, and the pty
+    /// it runs in for finding its foreground job. Replaces the child
+    /// watched for that pty before.
+    pub fn watch_child(&self, pid: Option<u32>, pty_fd: RawFd) {
+        if let Ok(mut state) = lock_state() {
+            state.children.retain(|(_, fd)| *fd != pty_fd);
+            state.children.push((pid.map(|pid| pid as pid_t), pty_fd));
+        }
+    }
+
+    /// Forgets the child in `pty_fd` once it has been dealt with.
+    pub fn unwatch_child(&self, pty_fd: RawFd) {
+        if let Ok(mut state) = lock_state() {
+            state.children.retain(|(_, fd)| *fd != pty_fd);
+        }
+    }
+
+    /// Size of the host terminal as (cols, rows).
+    pub fn window_size(&self) -> io::Result<(u16, u16)> {
+        match lock_state()?.tty.as_ref() {
+            Some(tty) => {tty.window_size()},
+            None => {Err(error_log!("terminal was already restored"))}
+        }
+    }
+
+    /// Puts the terminal back now. Later calls, including the one on drop,
+    /// do nothing.
+    pub fn restore(&mut self) -> io::Result<()> {
+        restore_terminal()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+    }
+}
+
+/// Sends SIGHUP to the child's process group and to the pty's foreground
+/// job, the way a terminal closing would, with a SIGCONT so stopped jobs
+/// get to handle it.
+pub fn hang_up(child: Option<u32>, pty_fd: Option<RawFd>) {
+    let own = unsafe { libc::getpgrp() };
+    let foreground = pty_fd.map(|fd| unsafe { libc::tcgetpgrp(fd) });
+    // the child called setsid, so its pid is its process group
+    let groups = [child.map(|pid| pid as pid_t), foreground];
+
+    for pgid in groups.iter().flatten() {
+        if *pgid <= 0 || *pgid == own {
+            continue;
+        }
+        unsafe {
+            libc::kill(-*pgid, libc::SIGHUP);
+            libc::kill(-*pgid, libc::SIGCONT);
+        }
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    let mut state = lock_state()?;
+    if !state.active {
+        return Ok(());
+    }
+    state.active = false;
+
+    for (pid, pty_fd) in std::mem::take(&mut state.children) {
+        hang_up(pid.map(|pid| pid as u32), Some(pty_fd));
+    }
+    write_stdout(RESTORE);
+    if let Some(mut tty) = state.tty.take() {
+        tty.restore()?;
+    }
+    Ok(())
+}
+
+/// A panic while the lock is held must not deadlock the panic hook, and a
+/// poisoned lock still holds the tty to restore.
+fn lock_state() -> io::Result<std::sync::MutexGuard<'static, GuardState>> {
+    match STATE.try_lock() {
+        Ok(state) => {Ok(state)},
+        Err(TryLockError::Poisoned(poisoned)) => {Ok(poisoned.into_inner())},
+        Err(TryLockError::WouldBlock) => {Err(error_log!("terminal guard is busy"))}
+    }
+}
+
+/// Straight to the fd, std's stdout lock may be held by whoever panicked.
+fn write_stdout(mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        let n = unsafe { libc::write(libc::STDOUT_FILENO, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+        if n > 0 {
+            bytes = &bytes[n as usize..];
+        } else if n == -1 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        } else {
+            return;
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::ascii::escapes::encode_key;
+use crate::ascii::input::{InputEvent, InputParser, Key, KeyEvent, Modifiers};
+use crate::screen::modes::Modes;
+
+/// How long a key that starts a longer binding waits for the next one,
+/// unless set otherwise.
+pub const CHORD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Which keys fi is taking. In insert mode keys go to the shown program
+/// unless they are bound, in normal mode they are fi's commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    Insert,
+    Normal,
+}
+
+impl InputMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "insert" => {Some(InputMode::Insert)},
+            "normal" => {Some(InputMode::Normal)},
+            _ => {None}
+        }
+    }
+}
+
+/// What a binding does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Switch to normal mode, the global escape.
+    Normal,
+    /// Back to typing into the pane, leaving the history view.
+    Insert,
+    Respawn,
+    /// Close fi, asking first when a job is still running.
+    Quit,
+    /// New shell in the shown session's directory.
+    NewSession,
+    NextSession,
+    PreviousSession,
+    /// Browse to the previous shell prompt in the history.
+    PreviousPrompt,
+    NextPrompt,
+    /// Copy the viewed or last command's output to the host clipboard.
+    Yank,
+    /// Start recording what is typed into the next key's register, or stop
+    /// when already recording. On `Q` rather than vim's `q`, which quits,
+    /// `map normal q record` gets vim's back.
+    Record,
+    /// Type the next key's register into the shown session.
+    Replay,
+    /// Turn typing into every session of the broadcast group on or off.
+    Broadcast,
+    /// Add the shown session to the broadcast group, or take it out.
+    BroadcastGroup,
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let action = match name {
+            "normal" => {Action::Normal},
+            "insert" => {Action::Insert},
+            "respawn" => {Action::Respawn},
+            "quit" => {Action::Quit},
+            "new-session" => {Action::NewSession},
+            "next-session" => {Action::NextSession},
+            "previous-session" => {Action::PreviousSession},
+            "previous-prompt" => {Action::PreviousPrompt},
+            "next-prompt" => {Action::NextPrompt},
+            "yank" => {Action::Yank},
+            "record" => {Action::Record},
+            "replay" => {Action::Replay},
+            "broadcast" => {Action::Broadcast},
+            "broadcast-group" => {Action::BroadcastGroup},
+            _ => {return None}
+        };
+        Some(action)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub mode: InputMode,
+    pub keys: Vec<KeyEvent>,
+    pub action: Action,
+}
+
+/// What the keys typed so far add up to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    /// Bound, and nothing longer starts with them.
+    Action(Action),
+    /// Longer bindings start with these keys, wait for more. The action
+    /// is what the keys do on their own, if anything, once the wait is over.
+    Prefix(Option<Action>),
+    Unbound,
+}
+
+/// Key bindings for each input mode. Bindings can be chords of several
+/// keys, written like `<C-]> g t`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap::empty();
+        let defaults = [
+            (InputMode::Insert, "<C-]>", Action::Normal),
+            (InputMode::Normal, "i", Action::Insert),
+            (InputMode::Normal, "r", Action::Respawn),
+            (InputMode::Normal, "q", Action::Quit),
+            (InputMode::Normal, "c", Action::NewSession),
+            (InputMode::Normal, "n", Action::NextSession),
+            (InputMode::Normal, "p", Action::PreviousSession),
+            (InputMode::Normal, "g t", Action::NextSession),
+            (InputMode::Normal, "g T", Action::PreviousSession),
+            (InputMode::Normal, "[", Action::PreviousPrompt),
+            (InputMode::Normal, "]", Action::NextPrompt),
+            (InputMode::Normal, "y", Action::Yank),
+            // q already quits, so recording moves to Q
+            (InputMode::Normal, "Q", Action::Record),
+            (InputMode::Normal, "@", Action::Replay),
+            (InputMode::Normal, "b", Action::Broadcast),
+            (InputMode::Normal, "B", Action::BroadcastGroup),
+        ];
+        for (mode, keys, action) in defaults {
+            keymap.bind(mode, parse_keys(keys).unwrap_or_default(), action);
+        }
+
+        keymap
+    }
+}
+
+impl Keymap {
+    /// A keymap with nothing bound, not even the way out of insert mode.
+    pub fn empty() -> Self {
+        Keymap { bindings: Vec::new() }
+    }
+
+    /// Where the key bindings are read from, `$XDG_CONFIG_HOME/fi/keys` or
+    /// `~/.config/fi/keys`.
+    pub fn config_path() -> Option<PathBuf> {
+        let config = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => {PathBuf::from(dir)},
+            None => {PathBuf::from(env::var_os("HOME")?).join(".config")}
+        };
+        Some(config.join("fi").join("keys"))
+    }
+
+    /// The defaults with the bindings of the file at `path` on top. A
+    /// missing file leaves the defaults.
+    pub fn load(path: &Path) -> io::Result<Keymap> {
+        let mut keymap = Keymap::default();
+        match fs::read_to_string(path) {
+            Ok(text) => {keymap.apply_config(&text)?},
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+            Err(err) => {return Err(err)}
+        }
+        Ok(keymap)
+    }
+
+    /// Applies a config, one command a line, a `#` starting a word starts a
+    /// comment:
+    ///
+    /// ```text
+    /// map <mode> <keys> <action>
+    /// unmap <mode> <keys>
+    /// prefix <keys>
+    /// ```
+    ///
+    /// `prefix` moves every insert mode binding that starts with the
+    /// global escape over to the new keys.
+    pub fn apply_config(&mut self, text: &str) -> io::Result<()> {
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let applied = match words.as_slice() {
+                [] => {Some(())},
+                ["map", mode, keys @ .., action] if !keys.is_empty() => {
+                    InputMode::from_name(mode).zip(parse_keys(&keys.join(" "))).zip(Action::from_name(action))
+                        .map(|((mode, keys), action)| self.bind(mode, keys, action))
+                },
+                ["unmap", mode, keys @ ..] if !keys.is_empty() => {
+                    InputMode::from_name(mode).zip(parse_keys(&keys.join(" ")))
+                        .map(|(mode, keys)| {self.unbind(mode, &keys);})
+                },
+                ["prefix", keys @ ..] if !keys.is_empty() => {
+                    parse_keys(&keys.join(" ")).map(|keys| self.set_prefix(keys))
+                },
+                _ => {None}
+            };
+            if applied.is_none() {
+                let message = format!("key bindings line {}: can not read {:?}", number + 1, line.trim());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds `keys` in `mode`, replacing what they were bound to.
+    pub fn bind(&mut self, mode: InputMode, keys: Vec<KeyEvent>, action: Action) {
+        self.unbind(mode, &keys);
+        self.bindings.push(Binding { mode, keys, action });
+    }
+
+    pub fn unbind(&mut self, mode: InputMode, keys: &[KeyEvent]) -> bool {
+        let before = self.bindings.len();
+        self.bindings.retain(|binding| binding.mode != mode || binding.keys != keys);
+        self.bindings.len() != before
+    }
+
+    /// Keys of the global escape, the insert mode binding to normal mode.
+    pub fn prefix(&self) -> Option<&[KeyEvent]> {
+        self.bindings.iter()
+            .find(|binding| binding.mode == InputMode::Insert && binding.action == Action::Normal)
+            .map(|binding| binding.keys.as_slice())
+    }
+
+    /// Rebinds the global escape, chords that start with it included.
+    pub fn set_prefix(&mut self, keys: Vec<KeyEvent>) {
+        let old = self.prefix().map(<[KeyEvent]>::to_vec);
+        if let Some(old) = old {
+            for binding in self.bindings.iter_mut().filter(|binding| binding.mode == InputMode::Insert) {
+                if binding.keys.starts_with(&old) {
+                    binding.keys.splice(..old.len(), keys.iter().copied());
+                }
+            }
+        }
+        self.bind(InputMode::Insert, keys, Action::Normal);
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// The action bound to exactly `keys`.
+    pub fn action(&self, mode: InputMode, keys: &[KeyEvent]) -> Option<Action> {
+        self.bindings.iter()
+            .find(|binding| binding.mode == mode && binding.keys == keys)
+            .map(|binding| binding.action)
+    }
+
+    pub fn lookup(&self, mode: InputMode, keys: &[KeyEvent]) -> Lookup {
+        let longer = self.bindings.iter()
+            .any(|binding| binding.mode == mode && binding.keys.len() > keys.len() && binding.keys.starts_with(keys));
+        match (self.action(mode, keys), longer) {
+            (action, true) => {Lookup::Prefix(action)},
+            (Some(action), false) => {Lookup::Action(action)},
+            (None, false) => {Lookup::Unbound}
+        }
+    }
+
+    /// Some binding uses a key a terminal only tells apart with
+    /// modifyOtherKeys or the kitty protocol, like Ctrl-Enter.
+    pub fn needs_extended_keys(&self) -> bool {
+        let modes = Modes::default();
+        self.bindings.iter().flat_map(|binding| binding.keys.iter()).any(|key| {
+            let mut parser = InputParser::new();
+            let mut events = parser.feed(&encode_key(key, &modes));
+            events.append(&mut parser.flush());
+            events != [InputEvent::Key(*key)]
+        })
+    }
+}
+
+/// A line of key bindings without its comment. `#` starts one at the start
+/// of the line or after whitespace, anywhere else it is a key.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Reads keys written the way vim writes them, like `<C-]> g t`. Chars
+/// stand for themselves, `<...>` holds a named key with any of the `C-`,
+/// `A-` or `M-`, `S-` and `D-` modifiers. Spaces only separate keys, a
+/// space key is `<Space>` and a `#` on its own, which would start a
+/// comment in a config, is `<Hash>`.
+pub fn parse_keys(text: &str) -> Option<Vec<KeyEvent>> {
+    let mut keys = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {},
+            '<' if chars.peek().is_some_and(|c| *c != ' ' && *c != '>') => {
+                let mut name = String::new();
+                loop {
+                    match chars.next()? {
+                        '>' if !name.is_empty() && !name.ends_with('-') => {break},
+                        c => {name.push(c)}
+                    }
+                }
+                keys.push(parse_named_key(&name)?);
+            },
+            c => {keys.push(KeyEvent::plain(Key::Char(c)))}
+        }
+    }
+
+    match keys.is_empty() {
+        true => {None},
+        false => {Some(keys)}
+    }
+}
+
+fn parse_named_key(name: &str) -> Option<KeyEvent> {
+    let mut modifiers = Modifiers::NONE;
+    let mut rest = name;
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        match rest.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => {modifiers.ctrl = true},
+            b'A' | b'M' => {modifiers.alt = true},
+            b'S' => {modifiers.shift = true},
+            b'D' => {modifiers.meta = true},
+            _ => {return None}
+        }
+        rest = &rest[2..];
+    }
+
+    let key = match rest.to_ascii_lowercase().as_str() {
+        "cr" | "enter" | "return" => {Key::Enter},
+        "tab" => {Key::Tab},
+        "bs" | "backspace" => {Key::Backspace},
+        "esc" => {Key::Escape},
+        "space" => {Key::Char(' ')},
+        "lt" => {Key::Char('<')},
+        "bslash" => {Key::Char('\\')},
+        "bar" => {Key::Char('|')},
+        "hash" => {Key::Char('#')},
+        "up" => {Key::Up},
+        "down" => {Key::Down},
+        "left" => {Key::Left},
+        "right" => {Key::Right},
+        "home" => {Key::Home},
+        "end" => {Key::End},
+        "pageup" => {Key::PageUp},
+        "pagedown" => {Key::PageDown},
+        "insert" => {Key::Insert},
+        "del" | "delete" => {Key::Delete},
+        lower => {
+            let mut chars = rest.chars();
+            match (chars.next()?, chars.next()) {
+                (c, None) => {Key::Char(c)},
+                ('f' | 'F', Some(_)) => {Key::F(lower[1..].parse().ok().filter(|n| (1..=24).contains(n))?)},
+                _ => {return None}
+            }
+        }
+    };
+
+    // written the way terminals send them, Ctrl-letter carries no case
+    // and Shift with a letter is just the capital
+    let key = match key {
+        Key::Char(c) if modifiers.ctrl && c.is_ascii_uppercase() => {Key::Char(c.to_ascii_lowercase())},
+        Key::Char(c) if modifiers == Modifiers::SHIFT && c.is_ascii_lowercase() => {
+            modifiers.shift = false;
+            Key::Char(c.to_ascii_uppercase())
+        },
+        key => {key}
+    };
+    Some(KeyEvent::new(key, modifiers))
+}
@@ -0,0 +1,382 @@
+use libc::{c_char, c_int, pid_t};
+use portable_pty::{Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, PtySystem, SlavePty};
+use std::cell::Cell;
+use std::ffi::{CStr, CString, OsStr};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::ptr;
+use std::sync::Mutex;
+use crate::error_log;
+
+/// Opens ptys and spawns into them with plain syscalls, implementing
+/// portable_pty's traits so sessions do not care which backend they got.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativePtySystem;
+
+impl PtySystem for NativePtySystem {
+    fn openpty(&self, size: PtySize) -> anyhow::Result<PtyPair> {
+        let (master, slave) = open_pair(size)?;
+
+        Ok(PtyPair {
+            slave: Box::new(NativeSlave { fd: slave }),
+            master: Box::new(NativeMaster { fd: master, took_writer: Cell::new(false) }),
+        })
+    }
+}
+
+pub struct NativeMaster {
+    fd: File,
+    took_writer: Cell<bool>,
+}
+
+impl MasterPty for NativeMaster {
+    fn resize(&self, size: PtySize) -> Result<(), anyhow::Error> {
+        Ok(set_size(self.fd.as_raw_fd(), size)?)
+    }
+
+    fn get_size(&self) -> Result<PtySize, anyhow::Error> {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(PtySize {
+            rows: size.ws_row,
+            cols: size.ws_col,
+            pixel_width: size.ws_xpixel,
+            pixel_height: size.ws_ypixel,
+        })
+    }
+
+    fn try_clone_reader(&self) -> Result<Box<dyn Read + Send>, anyhow::Error> {
+        Ok(Box::new(dup_cloexec(self.fd.as_raw_fd())?))
+    }
+
+    fn take_writer(&self) -> Result<Box<dyn io::Write + Send>, anyhow::Error> {
+        if self.took_writer.replace(true) {
+            return Err(error_log!("pty writer was already taken").into());
+        }
+        Ok(Box::new(dup_cloexec(self.fd.as_raw_fd())?))
+    }
+
+    fn process_group_leader(&self) -> Option<pid_t> {
+        match unsafe { libc::tcgetpgrp(self.fd.as_raw_fd()) } {
+            pid if pid > 0 => {Some(pid)},
+            _ => {None}
+        }
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        Some(self.fd.as_raw_fd())
+    }
+}
+
+pub struct NativeSlave {
+    fd: File,
+}
+
+impl SlavePty for NativeSlave {
+    fn spawn_command(&self, cmd: CommandBuilder) -> Result<Box<dyn Child + Send + Sync>, anyhow::Error> {
+        Ok(Box::new(spawn(self.fd.as_raw_fd(), &cmd)?))
+    }
+}
+
+#[derive(Debug)]
+pub struct NativeChild {
+    pid: pid_t,
+    status: Option<ExitStatus>,
+}
+
+impl NativeChild {
+    fn wait_pid(&mut self, flags: c_int) -> io::Result<Option<ExitStatus>> {
+        if let Some(status) = &self.status {
+            return Ok(Some(status.clone()));
+        }
+        let mut raw = 0;
+        loop {
+            match unsafe { libc::waitpid(self.pid, &mut raw, flags) } {
+                0 => {return Ok(None)},
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                },
+                _ => {
+                    let status = exit_status(raw);
+                    self.status = Some(status.clone());
+                    return Ok(Some(status));
+                }
+            }
+        }
+    }
+}
+
+impl Child for NativeChild {
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.wait_pid(libc::WNOHANG)
+    }
+
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        match self.wait_pid(0)? {
+            Some(status) => {Ok(status)},
+            None => {Err(error_log!("waitpid returned without a status"))}
+        }
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.pid as u32)
+    }
+}
+
+impl ChildKiller for NativeChild {
+    /// Hangs up like a closing terminal would. The child is reaped once it
+    /// exits, nothing here waits for that.
+    fn kill(&mut self) -> io::Result<()> {
+        if self.status.is_some() {
+            return Ok(());
+        }
+        if unsafe { libc::kill(self.pid, libc::SIGHUP) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(NativeKiller { pid: self.pid })
+    }
+}
+
+#[derive(Debug)]
+struct NativeKiller {
+    pid: pid_t,
+}
+
+impl ChildKiller for NativeKiller {
+    fn kill(&mut self) -> io::Result<()> {
+        if unsafe { libc::kill(self.pid, libc::SIGHUP) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(NativeKiller { pid: self.pid })
+    }
+}
+
+/// ptsname hands out a static buffer, only one thread may use it at a time.
+static PTSNAME: Mutex<()> = Mutex::new(());
+
+/// A new master and slave, both close-on-exec so children only ever get
+/// the slave they are spawned on, as their stdio.
+fn open_pair(size: PtySize) -> io::Result<(File, File)> {
+    let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    if master == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { File::from_raw_fd(master) };
+    set_cloexec(master.as_raw_fd())?;
+    if unsafe { libc::grantpt(master.as_raw_fd()) } == -1 || unsafe { libc::unlockpt(master.as_raw_fd()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let name = {
+        let _lock = PTSNAME.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let name = unsafe { libc::ptsname(master.as_raw_fd()) };
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { CStr::from_ptr(name) }.to_owned()
+    };
+    let slave = unsafe { libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) };
+    if slave == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let slave = unsafe { File::from_raw_fd(slave) };
+
+    set_size(master.as_raw_fd(), size)?;
+    Ok((master, slave))
+}
+
+fn set_size(fd: RawFd, size: PtySize) -> io::Result<()> {
+    let size = libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: size.pixel_width,
+        ws_ypixel: size.pixel_height,
+    };
+    if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn dup_cloexec(fd: RawFd) -> io::Result<File> {
+    let fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn c_string(text: &OsStr) -> io::Result<CString> {
+    match CString::new(text.as_bytes()) {
+        Ok(text) => {Ok(text)},
+        Err(_) => {Err(error_log!("nul byte in command"))}
+    }
+}
+
+/// Forks a child that becomes a session leader with the slave as its
+/// controlling terminal and stdio, then execs `cmd`. Exec failures come
+/// back over a close-on-exec pipe, so a missing program is an error here
+/// rather than a child exiting with 127.
+fn spawn(slave: RawFd, cmd: &CommandBuilder) -> io::Result<NativeChild> {
+    // everything the child needs is allocated up front, after fork only
+    // async signal safe calls are allowed
+    let env: Vec<(String, String)> = cmd.iter_full_env_as_str().map(|(k, v)| (k.to_owned(), v.to_owned())).collect();
+    let lookup = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+    let argv = match cmd.get_argv().first() {
+        Some(_) if !cmd.is_default_prog() => {cmd.get_argv().clone()},
+        _ => {vec![lookup("SHELL").unwrap_or("/bin/sh").into()]}
+    };
+    let argv = argv.iter().map(|arg| c_string(arg)).collect::<io::Result<Vec<_>>>()?;
+    let envp = env.iter().map(|(k, v)| c_string(OsStr::new(&format!("{}={}", k, v)))).collect::<io::Result<Vec<_>>>()?;
+    let candidates = program_candidates(argv[0].to_bytes(), lookup("PATH"))?;
+    let cwd = match cmd.get_cwd() {
+        Some(cwd) => {Some(c_string(cwd)?)},
+        None => {lookup("HOME").filter(|home| Path::new(home).is_dir()).map(|home| c_string(OsStr::new(home))).transpose()?}
+    };
+
+    let mut argv_ptrs: Vec<*const c_char> = argv.iter().map(|arg| arg.as_ptr()).collect();
+    argv_ptrs.push(ptr::null());
+    let mut envp_ptrs: Vec<*const c_char> = envp.iter().map(|var| var.as_ptr()).collect();
+    envp_ptrs.push(ptr::null());
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let (mut errors, error_write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    set_cloexec(errors.as_raw_fd())?;
+    set_cloexec(error_write.as_raw_fd())?;
+
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid == 0 {
+        unsafe { exec_child(slave, error_write.as_raw_fd(), cwd.as_deref(), &candidates, &argv_ptrs, &envp_ptrs) };
+    }
+    drop(error_write);
+
+    let mut errno = [0u8; 4];
+    let mut child = NativeChild { pid, status: None };
+    match errors.read(&mut errno) {
+        // the pipe closed on a successful exec
+        Ok(0) => {Ok(child)},
+        Ok(_) => {
+            let _ = child.wait();
+            Err(io::Error::from_raw_os_error(i32::from_ne_bytes(errno)))
+        },
+        Err(err) => {Err(err)}
+    }
+}
+
+/// Paths to try exec on, like execvp does with PATH from the child's own
+/// environment rather than ours.
+fn program_candidates(program: &[u8], path: Option<&str>) -> io::Result<Vec<CString>> {
+    if program.contains(&b'/') {
+        return Ok(vec![c_string(OsStr::from_bytes(program))?]);
+    }
+    path.unwrap_or("/usr/local/bin:/usr/bin:/bin")
+        .split(':')
+        .map(|dir| if dir.is_empty() { "." } else { dir })
+        .map(|dir| {
+            let mut candidate = dir.as_bytes().to_vec();
+            candidate.push(b'/');
+            candidate.extend_from_slice(program);
+            c_string(OsStr::from_bytes(&candidate))
+        })
+        .collect()
+}
+
+unsafe fn exec_child(
+    slave: RawFd,
+    error_fd: RawFd,
+    cwd: Option<&CStr>,
+    candidates: &[CString],
+    argv: &[*const c_char],
+    envp: &[*const c_char],
+) -> ! {
+    let fail = |errno: c_int| -> ! {
+        let errno = errno.to_ne_bytes();
+        libc::write(error_fd, errno.as_ptr() as *const libc::c_void, errno.len());
+        libc::_exit(127);
+    };
+    // reading errno this way does not allocate
+    let last_errno = || io::Error::last_os_error().raw_os_error().unwrap_or(libc::EIO);
+
+    if libc::setsid() == -1 || libc::ioctl(slave, libc::TIOCSCTTY as _, 0) == -1 {
+        fail(last_errno());
+    }
+    for fd in 0..3 {
+        // dup2 clears close-on-exec on the copies
+        if libc::dup2(slave, fd) == -1 {
+            fail(last_errno());
+        }
+    }
+
+    // handlers reset on exec by themselves, ignored signals and the mask
+    // do not, and std ignores SIGPIPE
+    let mut mask: libc::sigset_t = std::mem::zeroed();
+    libc::sigemptyset(&mut mask);
+    libc::sigprocmask(libc::SIG_SETMASK, &mask, ptr::null_mut());
+    for signo in [libc::SIGPIPE, libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM, libc::SIGCHLD, libc::SIGWINCH] {
+        libc::signal(signo, libc::SIG_DFL);
+    }
+
+    if let Some(cwd) = cwd {
+        if libc::chdir(cwd.as_ptr()) == -1 {
+            fail(last_errno());
+        }
+    }
+
+    let mut errno = libc::ENOENT;
+    for candidate in candidates {
+        libc::execve(candidate.as_ptr(), argv.as_ptr(), envp.as_ptr());
+        // keep a permission error over later misses, like execvp
+        match last_errno() {
+            libc::ENOENT | libc::ENOTDIR => {},
+            other => {errno = other}
+        }
+    }
+    fail(errno)
+}
+
+fn exit_status(raw: c_int) -> ExitStatus {
+    if libc::WIFSIGNALED(raw) {
+        let signo = libc::WTERMSIG(raw);
+        let name = unsafe { libc::strsignal(signo) };
+        let name = if name.is_null() {
+            format!("Signal {}", signo)
+        } else {
+            unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+        };
+        return ExitStatus::with_signal(&name);
+    }
+
+    ExitStatus::with_exit_code(libc::WEXITSTATUS(raw) as u32)
+}
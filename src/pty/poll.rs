@@ -0,0 +1,67 @@
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/// A set of fds to wait on with poll(2). Each `add` returns the index used
+/// to ask about that fd once `wait` returns.
+#[derive(Default)]
+pub struct PollSet {
+    fds: Vec<libc::pollfd>,
+}
+
+impl PollSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, fd: RawFd) -> usize {
+        self.add_events(fd, libc::POLLIN)
+    }
+
+    /// Waits for `fd` to become writable instead of readable.
+    pub fn add_writable(&mut self, fd: RawFd) -> usize {
+        self.add_events(fd, libc::POLLOUT)
+    }
+
+    fn add_events(&mut self, fd: RawFd, events: libc::c_short) -> usize {
+        self.fds.push(libc::pollfd { fd, events, revents: 0 });
+        self.fds.len() - 1
+    }
+
+    /// Blocks until one of the fds is ready or the timeout runs out. A
+    /// signal interrupting the wait counts as nothing being ready, the
+    /// signal itself shows up on the signal pipe.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<usize> {
+        for fd in &mut self.fds {
+            fd.revents = 0;
+        }
+        let timeout = match timeout {
+            Some(timeout) => {timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int},
+            None => {-1}
+        };
+        let n = unsafe { libc::poll(self.fds.as_mut_ptr(), self.fds.len() as libc::nfds_t, timeout) };
+        if n == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(0);
+            }
+            return Err(err);
+        }
+
+        Ok(n as usize)
+    }
+
+    pub fn readable(&self, i: usize) -> bool {
+        self.fds[i].revents & libc::POLLIN != 0
+    }
+
+    pub fn writable(&self, i: usize) -> bool {
+        self.fds[i].revents & libc::POLLOUT != 0
+    }
+
+    /// The other end went away or the fd is broken. Reading it will give
+    /// EOF or the error.
+    pub fn hung_up(&self, i: usize) -> bool {
+        self.fds[i].revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0
+    }
+}
@@ -0,0 +1,75 @@
+use libc::pid_t;
+use std::fs;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use crate::error_log;
+
+/// What is running in the foreground of a pane, read from /proc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// Leader of the foreground process group.
+    pub pid: pid_t,
+    /// Kernel's name for it, at most 15 bytes.
+    pub name: String,
+    pub argv: Vec<String>,
+    /// `None` when /proc does not let us see it, like for setuid programs.
+    pub cwd: Option<PathBuf>,
+    /// The pane's own program, normally the shell, is in the foreground
+    /// rather than a job started from it.
+    pub is_shell: bool,
+}
+
+impl ProcessInfo {
+    pub fn from_pid(pid: pid_t) -> io::Result<ProcessInfo> {
+        let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+        let name = fs::read_to_string(proc_dir.join("comm"))?.trim_end_matches('\n').to_owned();
+        let argv = fs::read(proc_dir.join("cmdline"))?
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+
+        Ok(ProcessInfo {
+            pid,
+            name,
+            argv,
+            cwd: fs::read_link(proc_dir.join("cwd")).ok(),
+            is_shell: false,
+        })
+    }
+
+    /// The foreground job of the pty `pty_fd` is the master of. `shell` is
+    /// the pid of the program the pane started.
+    pub fn foreground(pty_fd: RawFd, shell: Option<u32>) -> io::Result<ProcessInfo> {
+        let pgid = unsafe { libc::tcgetpgrp(pty_fd) };
+        if pgid <= 0 {
+            return Err(error_log!("pty has no foreground process group"));
+        }
+        let mut info = ProcessInfo::from_pid(pgid)?;
+        info.is_shell = shell == Some(pgid as u32);
+
+        Ok(info)
+    }
+
+    /// Short form for status lines, like "vim src/main.rs".
+    pub fn command_line(&self) -> String {
+        let program = match self.argv.first() {
+            Some(program) => {program},
+            None => {return self.name.clone()}
+        };
+        let program = Path::new(program).file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+
+        let mut line = program.trim_start_matches('-').to_owned();
+        for arg in &self.argv[1..] {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        line
+    }
+
+    /// Closing the pane would kill something the user started.
+    pub fn has_running_job(&self) -> bool {
+        !self.is_shell
+    }
+}
@@ -1,63 +1,186 @@
-use libc::{
-    cfmakeraw, tcgetattr, tcsetattr, termios as Termios, TCSANOW,
-};
+use crate::error_log;
+use libc::{termios as Termios, TCSANOW};
 
 use std::{
-    fmt::Debug, fs, io::{self, Error}, mem, os::unix::{
-        io::{IntoRawFd, RawFd},
-        prelude::AsRawFd,
-    }, 
-    sync::{Arc, Mutex} 
+    fs, io, mem,
+    os::unix::io::{AsRawFd, IntoRawFd, RawFd},
 };
 
-fn get_term_attr(fd: RawFd) -> io::Result<Termios>{
-    unsafe {
-        let mut termios = mem::zeroed();
-        if tcgetattr(fd, &mut termios)==-1 {
-            Err(Error::new(io::ErrorKind::Other, "Could not get terminal attr"))
-        } else {
-            Ok(termios)
+/// How much line handling the tty keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtyMode {
+    /// Bytes as typed, no echo, no line editing, no translation.
+    Raw,
+    /// Keys one at a time without echo, the rest of the tty's handling
+    /// stays as it was.
+    Cbreak,
+}
+
+/// Handling a raw or cbreak tty can keep on top of its mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtyFlags {
+    /// ISIG, Ctrl-C, Ctrl-Z and Ctrl-\ raise signals instead of being read.
+    pub signals: bool,
+    /// IXON, Ctrl-S and Ctrl-Q stop and start output.
+    pub flow_control: bool,
+    /// OPOST, output is processed, "\n" becomes "\r\n".
+    pub output_processing: bool,
+}
+
+impl TtyFlags {
+    /// Nothing kept, what `enter_raw` uses.
+    pub const RAW: TtyFlags = TtyFlags {
+        signals: false,
+        flow_control: false,
+        output_processing: false,
+    };
+    /// Everything kept, what `enter_cbreak` uses.
+    pub const CBREAK: TtyFlags = TtyFlags {
+        signals: true,
+        flow_control: true,
+        output_processing: true,
+    };
+}
+
+/// A tty with the settings it had when the guard was made, put back by
+/// `restore` or on drop.
+#[derive(Debug)]
+pub struct TtyGuard {
+    tty: FileDesc,
+    original: Termios,
+    changed: bool,
+}
+
+impl TtyGuard {
+    /// Guards the host terminal, stdin when it is one, else `/dev/tty`.
+    pub fn open() -> io::Result<TtyGuard> {
+        TtyGuard::with_fd(tty_fd()?)
+    }
+
+    /// Guards the tty `fd`, which has to stay open as long as the guard.
+    pub fn new(fd: RawFd) -> io::Result<TtyGuard> {
+        TtyGuard::with_fd(FileDesc::new(fd, false))
+    }
+
+    fn with_fd(tty: FileDesc) -> io::Result<TtyGuard> {
+        let original = get_term_attr(tty.raw_fd())?;
+        Ok(TtyGuard { tty, original, changed: false })
+    }
+
+    pub fn enter_raw(&mut self) -> io::Result<()> {
+        self.enter(TtyMode::Raw, TtyFlags::RAW)
+    }
+
+    pub fn enter_cbreak(&mut self) -> io::Result<()> {
+        self.enter(TtyMode::Cbreak, TtyFlags::CBREAK)
+    }
+
+    /// Puts the tty in `mode` starting from its original settings, with
+    /// `flags` deciding what of signals, flow control and output
+    /// processing is left on.
+    pub fn enter(&mut self, mode: TtyMode, flags: TtyFlags) -> io::Result<()> {
+        let mut ios = self.original;
+        match mode {
+            TtyMode::Raw => {unsafe { libc::cfmakeraw(&mut ios) }},
+            TtyMode::Cbreak => {
+                ios.c_lflag &= !(libc::ICANON | libc::ECHO);
+                ios.c_cc[libc::VMIN] = 1;
+                ios.c_cc[libc::VTIME] = 0;
+            }
         }
+        set_flag(&mut ios.c_lflag, libc::ISIG, flags.signals);
+        set_flag(&mut ios.c_iflag, libc::IXON, flags.flow_control);
+        set_flag(&mut ios.c_oflag, libc::OPOST, flags.output_processing);
+
+        // marked first, a tcsetattr that failed half way still needs undoing
+        self.changed = true;
+        set_term_attr(self.tty.raw_fd(), &ios)
+    }
+
+    /// Puts back the settings the tty had. Does nothing when they were
+    /// never changed or already put back.
+    pub fn restore(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        set_term_attr(self.tty.raw_fd(), &self.original)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// The tty is in a mode `enter` set.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Settings the tty had when the guard was made.
+    pub fn original(&self) -> &Termios {
+        &self.original
+    }
+
+    /// Size of the tty as (cols, rows).
+    pub fn window_size(&self) -> io::Result<(u16, u16)> {
+        fd_window_size(self.tty.raw_fd())
     }
 }
 
-fn no_raw(mut prior_mode: Option<Termios>) -> io::Result<Option<Termios>> {
-    if let Some(new_prior_mode) = prior_mode.as_ref() {
-        let tty = tty_fd()?;
-        unsafe { tcsetattr(tty.raw_fd(), TCSANOW, new_prior_mode) };
-        prior_mode = None;
+impl Drop for TtyGuard {
+    fn drop(&mut self) {
+        let _ = self.restore();
     }
-    Ok(prior_mode)
 }
 
-fn set_raw(mut prior_mode: Option<Termios>) -> io::Result<Option<Termios>> {
-    if prior_mode.is_some(){
-        return Ok(prior_mode)
+impl AsRawFd for TtyGuard {
+    fn as_raw_fd(&self) -> RawFd {
+        self.tty.raw_fd()
     }
-    let tty = tty_fd()?;
-    let fd = tty.raw_fd();
-    let mut ios = get_term_attr(fd)?;
-    let new_prior_mode = ios;
+}
 
-    unsafe { 
-        cfmakeraw(&mut ios); 
-        tcsetattr(fd, TCSANOW, &ios);
-    };
+/// Size of the host terminal as (cols, rows).
+pub fn window_size() -> io::Result<(u16, u16)> {
+    fd_window_size(tty_fd()?.raw_fd())
+}
 
-    prior_mode = Some(new_prior_mode);
+fn fd_window_size(fd: RawFd) -> io::Result<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if size.ws_col == 0 || size.ws_row == 0 {
+        return Err(error_log!("terminal reported a zero size"));
+    }
 
-    Ok(prior_mode)
+    Ok((size.ws_col, size.ws_row))
 }
 
-pub fn raw_mode(prior_mode: Option<Termios>) -> io::Result<Option<Termios>> {
-    if prior_mode.is_none(){
-        set_raw(prior_mode)
-    } else {
-        no_raw(prior_mode)
+fn set_flag(flags: &mut libc::tcflag_t, flag: libc::tcflag_t, on: bool) {
+    match on {
+        true => {*flags |= flag},
+        false => {*flags &= !flag}
+    }
+}
+
+fn get_term_attr(fd: RawFd) -> io::Result<Termios> {
+    let mut termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(termios)
+}
+
+/// Sets the tty's attributes, retrying when a signal gets in the way.
+fn set_term_attr(fd: RawFd, termios: &Termios) -> io::Result<()> {
+    loop {
+        if unsafe { libc::tcsetattr(fd, TCSANOW, termios) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
     }
 }
 
-use libc::size_t;
 #[derive(Debug)]
 struct FileDesc {
     fd: RawFd,
@@ -69,22 +192,6 @@ impl FileDesc {
         FileDesc { fd, close_on_drop }
     }
 
-    pub fn read(&self, buffer: &mut [u8], size: usize) -> io::Result<usize> {
-        let result = unsafe {
-            libc::read(
-                self.fd,
-                buffer.as_mut_ptr() as *mut libc::c_void,
-                size as size_t,
-            )
-        };
-
-        if result < 0 {
-            Err(io::Error::last_os_error())
-        } else {
-            Ok(result as usize)
-        }
-    }
-
     pub fn raw_fd(&self) -> RawFd {
         self.fd
     }
@@ -98,12 +205,6 @@ impl Drop for FileDesc {
     }
 }
 
-impl AsRawFd for FileDesc {
-    fn as_raw_fd(&self) -> RawFd {
-        self.raw_fd()
-    }
-}
-
 fn tty_fd() -> io::Result<FileDesc> {
     let is_tty = unsafe{libc::isatty(libc::STDIN_FILENO) == 1};
     if is_tty {
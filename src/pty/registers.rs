@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use crate::ascii::input::InputEvent;
+
+/// Input recorded into named registers to be typed again later, into the
+/// same session or another. Registers hold events rather than bytes, so a
+/// replay is encoded for the modes of the program it goes to.
+///
+/// Names are letters and digits. Recording into a capital letter adds to
+/// the lower case register, and `@` names the register last replayed, the
+/// way vim has them.
+#[derive(Debug, Default)]
+pub struct Registers {
+    registers: HashMap<char, Vec<InputEvent>>,
+    recording: Option<(char, Vec<InputEvent>)>,
+    last: Option<char>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Registers::default()
+    }
+
+    /// Whether `name` can name a register to record into.
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric()
+    }
+
+    /// Starts recording into `name`, ending any recording before. False
+    /// when `name` is not a register.
+    pub fn start(&mut self, name: char) -> bool {
+        if !Registers::is_valid(name) {
+            return false;
+        }
+        self.stop();
+        let events = match name.is_ascii_uppercase() {
+            true => {self.registers.get(&name.to_ascii_lowercase()).cloned().unwrap_or_default()},
+            false => {Vec::new()}
+        };
+        self.recording = Some((name.to_ascii_lowercase(), events));
+        true
+    }
+
+    /// Keeps what was recorded and returns the register it went to.
+    pub fn stop(&mut self) -> Option<char> {
+        let (name, events) = self.recording.take()?;
+        self.registers.insert(name, events);
+        Some(name)
+    }
+
+    /// The register being recorded into.
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(name, _)| *name)
+    }
+
+    /// Adds an event delivered to a session to the recording, if any.
+    pub fn record(&mut self, event: &InputEvent) {
+        if let Some((_, events)) = self.recording.as_mut() {
+            events.push(event.clone());
+        }
+    }
+
+    pub fn get(&self, name: char) -> Option<&[InputEvent]> {
+        self.registers.get(&name.to_ascii_lowercase()).map(Vec::as_slice)
+    }
+
+    /// The events to replay for `name`, remembering it for `@`. None for
+    /// the register being recorded, it would be recorded into itself and
+    /// grow with every replay.
+    pub fn replay(&mut self, name: char) -> Option<Vec<InputEvent>> {
+        let name = match name {
+            '@' => {self.last?},
+            name => {name.to_ascii_lowercase()}
+        };
+        if self.recording() == Some(name) {
+            return None;
+        }
+        let events = self.get(name)?.to_vec();
+        self.last = Some(name);
+        Some(events)
+    }
+}
@@ -0,0 +1,444 @@
+use portable_pty::{PtyPair, PtySize, PtySystem};
+use std::io::{self, Read};
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use super::exit::ChildExit;
+use super::poll::PollSet;
+use super::process::ProcessInfo;
+use super::spawn::{respawn_cwd, CwdSource, SpawnSpec};
+use super::writer::{PtyWriter, INPUT_LIMIT};
+use crate::ascii::escapes::Sequence;
+use crate::ascii::parser::SequenceParser;
+use crate::screen::grid::Screen;
+use crate::error_log;
+
+pub type SessionId = u32;
+
+/// What a session list shows about one session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub title: String,
+    pub cwd: Option<PathBuf>,
+    pub created: SystemTime,
+    pub exited: Option<ChildExit>,
+}
+
+/// A child in its own pty and the screen model of what it drew. Sessions
+/// never touch the host terminal, whoever shows one renders its screen.
+pub struct Session {
+    id: SessionId,
+    created: SystemTime,
+    screen: Screen,
+    parser: SequenceParser,
+    child: Box<dyn portable_pty::Child + Send + Sync>,
+    spawn: SpawnSpec,
+    pty_pair: PtyPair,
+    pty_fd: RawFd,
+    pty_writer: PtyWriter,
+    pty_reader: Box<dyn Read + Send>,
+    exited: Option<ChildExit>,
+    hung_up: bool,
+    started: Instant,
+}
+
+/// The in-crate backend with the `native-pty` feature, portable_pty's
+/// otherwise.
+pub fn pty_system() -> Box<dyn PtySystem + Send> {
+    #[cfg(feature = "native-pty")]
+    return Box::new(super::native::NativePtySystem);
+    #[cfg(not(feature = "native-pty"))]
+    return portable_pty::native_pty_system();
+}
+
+impl Session {
+    pub fn spawn(id: SessionId, spawn: SpawnSpec, size_x: u16, size_y: u16) -> io::Result<Session> {
+        let pair = pty_system().openpty(PtySize {
+            rows: size_y,
+            cols: size_x,
+            pixel_width: 0,
+            pixel_height: 0,
+        });
+
+        let pair = match pair {
+            Ok(pair) => {pair},
+            Err(_) => {return Err(error_log!("failed to make pty pair"))}
+        };
+        let pty_fd = match pair.master.as_raw_fd() {
+            Some(fd) => {fd},
+            None => {return Err(error_log!("pty master has no fd"))}
+        };
+
+        let child = match pair.slave.spawn_command(spawn.to_command()) {
+            Ok(child) => {child},
+            Err(_) => {return Err(error_log!("failed to spawn process"))}
+        };
+
+        let pty_reader = match pair.master.try_clone_reader() {
+            Ok(reader) => {reader},
+            Err(_) => {return Err(error_log!("failed to clone pty reader"))}
+        };
+        let pty_writer = match pair.master.take_writer() {
+            Ok(writer) => {PtyWriter::new(writer, pty_fd, INPUT_LIMIT)?},
+            Err(_) => {return Err(error_log!("failed to take pty writer"))}
+        };
+
+        Ok(Session {
+            id,
+            created: SystemTime::now(),
+            screen: Screen::new(size_x as usize, size_y as usize),
+            parser: SequenceParser::new(),
+            child,
+            spawn,
+            pty_pair: pair,
+            pty_fd,
+            pty_writer,
+            pty_reader,
+            exited: None,
+            hung_up: false,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn spawn_spec(&self) -> &SpawnSpec {
+        &self.spawn
+    }
+
+    pub fn pty_fd(&self) -> RawFd {
+        self.pty_fd
+    }
+
+    pub fn child_pid(&self) -> Option<u32> {
+        self.child.process_id()
+    }
+
+    /// How the child ended, once it has.
+    pub fn exit_status(&self) -> Option<&ChildExit> {
+        self.exited.as_ref()
+    }
+
+    /// The pty was hung up before the child could be reaped. It is not
+    /// read again, the child is left for `reap`.
+    pub fn set_hung_up(&mut self) {
+        self.hung_up = true;
+    }
+
+    /// Output can still come from the pty.
+    pub fn is_readable(&self) -> bool {
+        self.exited.is_none() && !self.hung_up
+    }
+
+    /// How long the current child has been running.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The title the program set, or the command it runs.
+    pub fn title(&self) -> String {
+        match self.screen.title() {
+            Some(title) if !title.is_empty() => {title.to_owned()},
+            _ => {self.spawn.argv().join(" ")}
+        }
+    }
+
+    /// Where the shell said it is, or else where /proc says it is.
+    pub fn cwd(&self) -> Option<PathBuf> {
+        match self.screen.working_dir() {
+            Some(dir) => {Some(PathBuf::from(dir))},
+            None => {self.get_process_pwd().ok().map(PathBuf::from)}
+        }
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id,
+            title: self.title(),
+            cwd: self.cwd(),
+            created: self.created,
+            exited: self.exited.clone(),
+        }
+    }
+
+    /// Reads what the child wrote. 0 means the pty was hung up, the pty is
+    /// non-blocking so WouldBlock means there was nothing after all.
+    pub fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.pty_reader.read(buf) {
+                Ok(n) => {return Ok(n)},
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                // linux reports EIO once the last slave fd is closed
+                Err(err) if err.raw_os_error() == Some(libc::EIO) => {return Ok(0)},
+                Err(err) => {return Err(err)}
+            }
+        }
+    }
+
+    /// Runs output through the screen model and hands it back for showing.
+    pub fn process(&mut self, bytes: &[u8]) -> Vec<Sequence> {
+        let seqs = self.parser.feed(bytes);
+        for seq in &seqs {
+            self.screen.process(seq);
+        }
+        seqs
+    }
+
+    /// Processes whatever output is left without waiting for more.
+    pub fn drain_output(&mut self) -> io::Result<Vec<Sequence>> {
+        let mut buf = [0u8; 4096];
+        let mut seqs = Vec::new();
+        loop {
+            let mut poll = PollSet::new();
+            let pty = poll.add(self.pty_fd);
+            poll.wait(Some(Duration::ZERO))?;
+            if !poll.readable(pty) {
+                return Ok(seqs);
+            }
+            match self.read(&mut buf) {
+                Ok(0) => {return Ok(seqs)},
+                Ok(n) => {seqs.append(&mut self.process(&buf[..n]))},
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {return Ok(seqs)},
+                Err(err) => {return Err(err)}
+            }
+        }
+    }
+
+    /// Queues `bytes` as one unit of input and sends what the pty takes
+    /// right away. Returns false when the input queue is full and nothing
+    /// was queued.
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<bool> {
+        // nobody reads a dead child's pty, input for it goes nowhere
+        if bytes.is_empty() || self.exited.is_some() {
+            return Ok(true);
+        }
+        if !self.pty_writer.queue(bytes) {
+            return Ok(false);
+        }
+        self.pty_writer.flush()?;
+        Ok(true)
+    }
+
+    /// Sends more queued input, for when the pty became writable.
+    pub fn flush_input(&mut self) -> io::Result<()> {
+        self.pty_writer.flush()
+    }
+
+    /// Input is queued waiting for the pty to become writable.
+    pub fn wants_write(&self) -> bool {
+        self.pty_writer.pending() > 0 && self.exited.is_none()
+    }
+
+    /// No more input is taken until the child reads some.
+    pub fn input_full(&self) -> bool {
+        self.pty_writer.is_full()
+    }
+
+    /// Keeps the screen model and the child's pty in step so programs
+    /// inside redraw for the new size.
+    pub fn resize(&mut self, size_x: u16, size_y: u16) -> io::Result<()> {
+        self.screen.resize(size_x as usize, size_y as usize);
+
+        let resized = self.pty_pair.master.resize(PtySize {
+            rows: size_y,
+            cols: size_x,
+            pixel_width: 0,
+            pixel_height: 0,
+        });
+        match resized {
+            Ok(_) => {Ok(())},
+            Err(_) => {Err(error_log!("failed to resize pty"))}
+        }
+    }
+
+    /// Reaps the child if it exited since the last call.
+    pub fn reap(&mut self) -> io::Result<Option<ChildExit>> {
+        if self.exited.is_some() {
+            return Ok(None);
+        }
+        let exit = self.child.try_wait()?.map(|status| ChildExit::from_status(&status));
+        if exit.is_some() {
+            self.pty_writer.clear();
+        }
+        self.exited = exit.clone();
+        Ok(exit)
+    }
+
+    /// What runs in the foreground of the pty right now.
+    pub fn foreground_process(&self) -> io::Result<ProcessInfo> {
+        ProcessInfo::foreground(self.pty_fd, self.child.process_id())
+    }
+
+    /// The foreground job, unless that is the session's own program idling.
+    pub fn running_job(&self) -> Option<ProcessInfo> {
+        if self.exited.is_some() {
+            return None;
+        }
+        self.foreground_process().ok().filter(ProcessInfo::has_running_job)
+    }
+
+    pub fn get_process_pwd(&self) -> io::Result<String> {
+        // a setuid job hides its cwd, the shell that started it is next best
+        let cwd = self.foreground_process().ok().and_then(|info| info.cwd).or_else(|| {
+            let pid = self.child.process_id()?;
+            ProcessInfo::from_pid(pid as libc::pid_t).ok()?.cwd
+        });
+        match cwd {
+            Some(cwd) => {Ok(cwd.to_string_lossy().into_owned())},
+            None => {Err(error_log!("process id returned None"))}
+        }
+    }
+
+    /// Restarts the program from its original spec in the directory the
+    /// old one was last in. Returns that directory and where it came from,
+    /// `None` being the home directory.
+    pub fn respawn(&mut self) -> io::Result<(Option<PathBuf>, CwdSource)> {
+        let proc_cwd = self.get_process_pwd().ok().map(PathBuf::from);
+        let (cwd, source) = respawn_cwd(self.screen.working_dir(), proc_cwd, &self.spawn);
+        let mut spawn = self.spawn.clone();
+        spawn.cwd = cwd.clone();
+
+        // the pty can only be the controlling terminal of one session, the
+        // old child has to be gone before the new one starts
+        self.end_child();
+        self.pty_writer.clear();
+        self.child = match self.pty_pair.slave.spawn_command(spawn.to_command()) {
+            Ok(child) => {child},
+            Err(_) => {return Err(error_log!("failed to spawn process"))}
+        };
+        // nothing the old program asked of the terminal holds for the new one
+        self.screen.reset_modes();
+        self.parser = SequenceParser::new();
+        self.exited = None;
+        self.hung_up = false;
+        self.started = Instant::now();
+        Ok((cwd, source))
+    }
+
+    /// Hangs up the child without waiting on it. Returns its pid if it is
+    /// still around and has to be reaped later.
+    pub fn hang_up(&mut self) -> Option<u32> {
+        // a killer only signals, where the child's own kill may wait on it
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.clone_killer().kill();
+        }
+        match self.child.try_wait() {
+            Ok(None) => {self.child.process_id()},
+            _ => {None}
+        }
+    }
+
+    /// Hangs up the child, kills it if that was not enough, and reaps it.
+    fn end_child(&mut self) {
+        if let Some(pid) = self.hang_up() {
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+            let _ = self.child.wait();
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.hang_up();
+    }
+}
+
+/// Every session fi runs, whether shown or not.
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Vec<Session>,
+    next_id: SessionId,
+    /// Children of destroyed sessions that did not exit on the hangup yet.
+    orphans: Vec<u32>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create(&mut self, spawn: SpawnSpec, size_x: u16, size_y: u16) -> io::Result<SessionId> {
+        let id = self.next_id;
+        self.sessions.push(Session::spawn(id, spawn, size_x, size_y)?);
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: SessionId) -> Option<&Session> {
+        self.sessions.iter().find(|session| session.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: SessionId) -> Option<&mut Session> {
+        self.sessions.iter_mut().find(|session| session.id == id)
+    }
+
+    /// Oldest first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        self.sessions.iter().map(Session::info).collect()
+    }
+
+    pub fn ids(&self) -> Vec<SessionId> {
+        self.sessions.iter().map(|session| session.id).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Session> {
+        self.sessions.iter_mut()
+    }
+
+    /// The session after or before `id`, wrapping around.
+    pub fn next_after(&self, id: SessionId, forward: bool) -> Option<SessionId> {
+        let i = self.sessions.iter().position(|session| session.id == id)?;
+        let len = self.sessions.len();
+        let next = if forward { (i + 1) % len } else { (i + len - 1) % len };
+        Some(self.sessions[next].id)
+    }
+
+    /// Forgets the session, which hangs up its child. Returns whether it
+    /// existed.
+    pub fn destroy(&mut self, id: SessionId) -> bool {
+        match self.sessions.iter().position(|session| session.id == id) {
+            Some(i) => {
+                let mut session = self.sessions.remove(i);
+                self.orphans.extend(session.hang_up());
+                true
+            },
+            None => {false}
+        }
+    }
+
+    /// Reaps the children of destroyed sessions that exited since.
+    pub fn reap_orphans(&mut self) {
+        self.orphans.retain(|pid| {
+            let mut raw = 0;
+            unsafe { libc::waitpid(*pid as libc::pid_t, &mut raw, libc::WNOHANG) == 0 }
+        });
+    }
+
+    /// Children of destroyed sessions still waiting to be reaped.
+    pub fn orphans(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
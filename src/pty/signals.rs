@@ -0,0 +1,93 @@
+use libc::c_int;
+use crate::error_log;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+static SIGNAL_PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+/// Async signal safe handler. All it does is push the signal number down
+/// the self-pipe so the real work happens on a normal thread.
+extern "C" fn forward_signal(signo: c_int) {
+    let fd = SIGNAL_PIPE_WRITE.load(Ordering::Relaxed);
+    if fd < 0 {
+        return;
+    }
+    unsafe {
+        let errno = *libc::__errno_location();
+        let byte = signo as u8;
+        libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+/// Read end of the self-pipe that signal handlers write into.
+pub struct SignalPipe {
+    read_fd: RawFd,
+}
+
+impl SignalPipe {
+    /// Installs handlers for `signals`. Only one pipe exists per process,
+    /// installing again while a `SignalPipe` is alive is an error.
+    pub fn install(signals: &[c_int]) -> io::Result<SignalPipe> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe {
+            let flags = libc::fcntl(fds[1], libc::F_GETFL);
+            libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+
+        if SIGNAL_PIPE_WRITE.compare_exchange(-1, fds[1], Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            unsafe {
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+            }
+            return Err(error_log!("signal handlers are already installed"));
+        }
+        // dropped on error below, which gives the pipe back
+        let pipe = SignalPipe { read_fd: fds[0] };
+
+        for signo in signals {
+            let handler = forward_signal as extern "C" fn(c_int) as libc::sighandler_t;
+            if unsafe { libc::signal(*signo, handler) } == libc::SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(pipe)
+    }
+
+    /// Blocks until a signal arrives and returns its number.
+    pub fn wait(&self) -> io::Result<c_int> {
+        let mut byte = 0u8;
+        loop {
+            let n = unsafe { libc::read(self.read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            if n == 1 {
+                return Ok(byte as c_int);
+            }
+            let err = io::Error::last_os_error();
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "signal pipe closed"));
+            } else if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn raw_fd(&self) -> RawFd {
+        self.read_fd
+    }
+}
+
+impl Drop for SignalPipe {
+    fn drop(&mut self) {
+        // handlers still installed find no pipe and drop their signal
+        let write_fd = SIGNAL_PIPE_WRITE.swap(-1, Ordering::SeqCst);
+        if write_fd >= 0 {
+            unsafe { libc::close(write_fd) };
+        }
+        unsafe { libc::close(self.read_fd) };
+    }
+}
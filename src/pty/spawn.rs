@@ -0,0 +1,136 @@
+use std::ffi::{CStr, OsString};
+use std::path::{Path, PathBuf};
+use portable_pty::CommandBuilder;
+
+const DEFAULT_TERM: &str = "xterm-256color";
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+/// What a pane runs and the environment it starts in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Set on top of the inherited environment, or the empty one when
+    /// `env_clear` is set. These win over `term` and `colorterm`.
+    pub env: Vec<(String, String)>,
+    pub env_remove: Vec<String>,
+    /// Start from an empty environment. `program` then needs to be an
+    /// absolute path unless PATH is given in `env`.
+    pub env_clear: bool,
+    /// Starting directory, the home directory when `None`.
+    pub cwd: Option<PathBuf>,
+    pub term: String,
+    /// `None` unsets COLORTERM, so programs inside do not pick truecolor
+    /// up from the host terminal.
+    pub colorterm: Option<String>,
+    /// Starts the program as a login shell with `-l`, which bash, zsh,
+    /// fish and dash all understand.
+    pub login: bool,
+}
+
+impl Default for SpawnSpec {
+    fn default() -> Self {
+        SpawnSpec::shell()
+    }
+}
+
+impl SpawnSpec {
+    /// The user's login shell.
+    pub fn shell() -> Self {
+        SpawnSpec {
+            login: true,
+            ..SpawnSpec::program(default_shell(), Vec::new())
+        }
+    }
+
+    /// Runs `program` directly, without a shell around it.
+    pub fn program<S: Into<String>>(program: S, args: Vec<String>) -> Self {
+        SpawnSpec {
+            program: program.into(),
+            args,
+            env: Vec::new(),
+            env_remove: Vec::new(),
+            env_clear: false,
+            cwd: None,
+            term: String::from(DEFAULT_TERM),
+            colorterm: None,
+            login: false,
+        }
+    }
+
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv = vec![self.program.clone()];
+        if self.login {
+            argv.push(String::from("-l"));
+        }
+        argv.extend(self.args.iter().cloned());
+
+        argv
+    }
+
+    pub fn to_command(&self) -> CommandBuilder {
+        let mut cmd = CommandBuilder::from_argv(self.argv().into_iter().map(OsString::from).collect());
+        if self.env_clear {
+            cmd.env_clear();
+        }
+        for key in &self.env_remove {
+            cmd.env_remove(key);
+        }
+        cmd.env("TERM", &self.term);
+        match &self.colorterm {
+            Some(colorterm) => {cmd.env("COLORTERM", colorterm)},
+            None => {cmd.env_remove("COLORTERM")}
+        }
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        if let Some(cwd) = &self.cwd {
+            cmd.cwd(cwd);
+        }
+
+        cmd
+    }
+}
+
+/// Where the directory of a respawned pane came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CwdSource {
+    /// Reported by the shell with OSC 7.
+    Osc7,
+    /// Read from /proc/<pid>/cwd of the foreground process.
+    ProcFs,
+    /// Neither was usable, the spec's own cwd is kept.
+    Fallback,
+}
+
+/// Picks the directory to respawn in. What the shell reported is trusted
+/// first since /proc only knows where the foreground process is, which
+/// may be a program started somewhere else.
+pub fn respawn_cwd(reported: Option<&str>, proc_cwd: Option<PathBuf>, spec: &SpawnSpec) -> (Option<PathBuf>, CwdSource) {
+    if let Some(dir) = reported.filter(|dir| Path::new(dir).is_dir()) {
+        return (Some(PathBuf::from(dir)), CwdSource::Osc7);
+    }
+    if let Some(dir) = proc_cwd.filter(|dir| dir.is_dir()) {
+        return (Some(dir), CwdSource::ProcFs);
+    }
+
+    (spec.cwd.clone(), CwdSource::Fallback)
+}
+
+/// $SHELL, or the shell from the user's passwd entry when it is not set.
+pub fn default_shell() -> String {
+    match std::env::var("SHELL") {
+        Ok(shell) if !shell.is_empty() => {shell},
+        _ => {passwd_shell().unwrap_or_else(|| String::from(FALLBACK_SHELL))}
+    }
+}
+
+fn passwd_shell() -> Option<String> {
+    let entry = unsafe { libc::getpwuid(libc::getuid()) };
+    if entry.is_null() {
+        return None;
+    }
+    let shell = unsafe { CStr::from_ptr((*entry).pw_shell) };
+
+    shell.to_str().ok().filter(|shell| !shell.is_empty()).map(str::to_owned)
+}
@@ -0,0 +1,83 @@
+use std::io::{self, Write};
+use std::os::unix::io::RawFd;
+
+/// Input queued for a child past this is refused until some of it drained.
+pub const INPUT_LIMIT: usize = 256 * 1024;
+
+/// Input on its way to a child's pty. The pty is written without blocking,
+/// what it does not take yet stays queued, in order, until poll says it is
+/// writable again. Each `queue` call is one unit, a key or a whole paste,
+/// and is taken whole or not at all so it never gets truncated or split by
+/// a keystroke queued after it.
+pub struct PtyWriter {
+    writer: Box<dyn Write + Send>,
+    pending: Vec<u8>,
+    /// How much of `pending` already went out.
+    sent: usize,
+    limit: usize,
+}
+
+impl PtyWriter {
+    /// `fd` is the pty master behind `writer`, switched to non-blocking.
+    pub fn new(writer: Box<dyn Write + Send>, fd: RawFd, limit: usize) -> io::Result<PtyWriter> {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(PtyWriter {
+            writer,
+            pending: Vec::new(),
+            sent: 0,
+            limit,
+        })
+    }
+
+    /// Queues `bytes` behind everything queued before. Returns false, and
+    /// queues nothing, when the queue is already full. A unit bigger than
+    /// the limit still goes in whole when it finds the queue below it.
+    pub fn queue(&mut self, bytes: &[u8]) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.pending.extend_from_slice(bytes);
+        true
+    }
+
+    /// Writes as much as the pty takes right now.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while self.sent < self.pending.len() {
+            match self.writer.write(&self.pending[self.sent..]) {
+                Ok(0) => {break},
+                Ok(n) => {self.sent += n},
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {break},
+                Err(err) => {return Err(err)}
+            }
+        }
+
+        if self.sent == self.pending.len() {
+            self.pending.clear();
+            self.sent = 0;
+        } else if self.sent > self.limit {
+            self.pending.drain(..self.sent);
+            self.sent = 0;
+        }
+        Ok(())
+    }
+
+    /// Bytes still waiting for the pty.
+    pub fn pending(&self) -> usize {
+        self.pending.len() - self.sent
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pending() >= self.limit
+    }
+
+    /// Drops whatever is queued, for when the child it was for is gone.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.sent = 0;
+    }
+}
@@ -0,0 +1,8 @@
+pub mod grid;
+pub mod modes;
+pub mod render;
+pub mod selection;
+pub mod snapshot;
+pub mod style;
+pub mod width;
+pub mod zones;
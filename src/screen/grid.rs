@@ -0,0 +1,760 @@
+use std::collections::VecDeque;
+
+use crate::ascii::escapes::{Escape, Sequence};
+use super::modes::Modes;
+use super::style::Style;
+use super::width::{char_width, joins_cluster};
+use super::zones::{Mark, PromptMark};
+
+const TAB_WIDTH: usize = 8;
+const SCROLLBACK_LIMIT: usize = 10_000;
+const TITLE_STACK: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    pub width: u8,
+    pub style: Style,
+}
+
+impl Cell {
+    pub fn blank() -> Self {
+        Cell { text: String::from(" "), width: 1, style: Style::default() }
+    }
+
+    /// The right half of a wide char. It holds no text of its own.
+    fn spacer(style: Style) -> Self {
+        Cell { text: String::new(), width: 0, style }
+    }
+
+    pub fn is_spacer(&self) -> bool {
+        self.width == 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    pub wrapped: bool,
+    pub marks: Vec<Mark>,
+}
+
+impl Row {
+    fn new(cols: usize) -> Self {
+        Row { cells: vec![Cell::blank(); cols], wrapped: false, marks: Vec::new() }
+    }
+
+    pub fn text(&self) -> String {
+        self.cells.iter().map(|cell| cell.text.as_str()).collect()
+    }
+
+    fn is_blank(&self) -> bool {
+        self.marks.is_empty() && self.cells.iter().all(|cell| *cell == Cell::blank())
+    }
+}
+
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    grid: Vec<Row>,
+    scrollback: VecDeque<Row>,
+    pen: Style,
+    cursor_row: usize,
+    cursor_col: usize,
+    pending_wrap: bool,
+    saved_cursor: (usize, usize),
+    saved_pen: Style,
+    modes: Modes,
+    // inclusive rows of the DECSTBM scroll region
+    scroll_top: usize,
+    scroll_bottom: usize,
+    // the normal screen while the alternate one is shown
+    primary: Option<Vec<Row>>,
+    working_dir: Option<String>,
+    title: Option<String>,
+    /// Titles saved with CSI 22 t, most recent last.
+    titles: Vec<Option<String>>,
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        Screen {
+            cols,
+            rows,
+            grid: (0..rows).map(|_| Row::new(cols)).collect(),
+            scrollback: VecDeque::new(),
+            pen: Style::default(),
+            cursor_row: 0,
+            cursor_col: 0,
+            pending_wrap: false,
+            saved_cursor: (0, 0),
+            saved_pen: Style::default(),
+            modes: Modes::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            primary: None,
+            working_dir: None,
+            title: None,
+            titles: Vec::new(),
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Zero based (row, col) of the cursor.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_row, self.cursor_col)
+    }
+
+    /// Set after printing into the last column. Like xterm, the cursor
+    /// stays put and the wrap happens when the next printable char arrives.
+    pub fn pending_wrap(&self) -> bool {
+        self.pending_wrap
+    }
+
+    pub fn row(&self, row: usize) -> Option<&Row> {
+        self.grid.get(row)
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.grid.get(row).and_then(|r| r.cells.get(col))
+    }
+
+    /// Style that newly printed chars will get.
+    pub fn pen(&self) -> Style {
+        self.pen
+    }
+
+    pub fn modes(&self) -> Modes {
+        self.modes
+    }
+
+    /// Zero based, inclusive (top, bottom) rows of the scroll region.
+    pub fn scroll_region(&self) -> (usize, usize) {
+        (self.scroll_top, self.scroll_bottom)
+    }
+
+    /// Directory the shell last reported with OSC 7.
+    pub fn working_dir(&self) -> Option<&str> {
+        self.working_dir.as_deref()
+    }
+
+    /// Window title last set with OSC 0 or 2.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    /// Number of lines addressable through `line`, scrollback included.
+    pub fn total_lines(&self) -> usize {
+        self.scrollback.len() + self.grid.len()
+    }
+
+    /// Lines are numbered from the oldest scrollback line down to the
+    /// bottom of the visible screen.
+    pub fn line(&self, line: usize) -> Option<&Row> {
+        if line < self.scrollback.len() {
+            self.scrollback.get(line)
+        } else {
+            self.grid.get(line - self.scrollback.len())
+        }
+    }
+
+    /// Line numbers of the rows currently on screen.
+    pub fn visible_lines(&self) -> std::ops::Range<usize> {
+        self.scrollback.len()..self.total_lines()
+    }
+
+    /// Changes the grid size. When shrinking, rows are taken off the top
+    /// first so the line with the cursor stays visible, the same way xterm
+    /// keeps the prompt on screen.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+
+        if cols != self.cols {
+            resize_rows(&mut self.grid, cols);
+            if let Some(primary) = self.primary.as_mut() {
+                resize_rows(primary, cols);
+            }
+            self.cols = cols;
+        }
+        if rows < self.rows {
+            let overflow = (self.cursor_row + 1).saturating_sub(rows);
+            let scrolled: Vec<Row> = self.grid.drain(..overflow).collect();
+            self.grid.truncate(rows);
+            self.cursor_row -= overflow;
+            match self.primary.take() {
+                // the alt screen has no scrollback, the normal screen under
+                // it keeps its lines down to the last one written or the
+                // cursor saved on the way in
+                Some(mut primary) => {
+                    let written = primary.iter().rposition(|row| !row.is_blank()).map_or(0, |last| last + 1);
+                    let used = written.max(self.saved_cursor.0 + 1);
+                    let overflow = used.saturating_sub(rows);
+                    let scrolled: Vec<Row> = primary.drain(..overflow).collect();
+                    primary.truncate(rows);
+                    self.push_scrollback(scrolled);
+                    self.primary = Some(primary);
+                    self.saved_cursor.0 = self.saved_cursor.0.saturating_sub(overflow);
+                },
+                None => {
+                    self.push_scrollback(scrolled);
+                    self.saved_cursor.0 = self.saved_cursor.0.saturating_sub(overflow);
+                }
+            }
+        } else {
+            self.grid.resize_with(rows, || Row::new(cols));
+            if let Some(primary) = self.primary.as_mut() {
+                primary.resize_with(rows, || Row::new(cols));
+            }
+        }
+        self.rows = rows;
+
+        self.cursor_row = self.cursor_row.min(rows - 1);
+        self.cursor_col = self.cursor_col.min(cols - 1);
+        self.saved_cursor = (self.saved_cursor.0.min(rows - 1), self.saved_cursor.1.min(cols - 1));
+        self.pending_wrap = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+    }
+
+    /// Puts modes, pen and scroll region back to their defaults for a new
+    /// program, leaving the alt screen. What was drawn on the normal screen
+    /// stays.
+    pub fn reset_modes(&mut self) {
+        if self.primary.is_some() {
+            self.leave_alt_screen();
+            self.restore_cursor();
+        }
+        self.modes = Modes::default();
+        self.pen = Style::default();
+        self.pending_wrap = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows - 1;
+    }
+
+    pub fn process(&mut self, seq: &Sequence) {
+        match seq {
+            Sequence::Text(c) => {
+                self.print(*c);
+            },
+            Sequence::Escape(escs) => {
+                for esc in escs {
+                    self.apply_escape(esc);
+                }
+            }
+        }
+    }
+
+    pub fn print(&mut self, c: char) {
+        if c.is_control() {
+            self.control(c);
+            return;
+        }
+
+        if !c.is_ascii() && self.attach_to_previous(c) {
+            return;
+        }
+
+        let width = char_width(c);
+        if width == 0 {
+            // nothing to combine with, xterm drops these as well
+            return;
+        }
+
+        if self.pending_wrap {
+            self.wrap();
+        }
+
+        if width == 2 && self.cursor_col + 1 >= self.cols {
+            if self.cols < 2 {
+                return;
+            }
+            if self.modes.autowrap {
+                // a wide char never straddles the margin, the last column is
+                // left blank and the char moves to the next line
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                self.erase_cells(row, col, col + 1);
+                self.wrap();
+            } else {
+                self.cursor_col = self.cols - 2;
+            }
+        }
+
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        self.clear_wide_remnants(row, col);
+        if width == 2 {
+            self.clear_wide_remnants(row, col + 1);
+        }
+
+        let style = self.pen;
+        let cells = &mut self.grid[row].cells;
+        cells[col] = Cell { text: c.to_string(), width: width as u8, style };
+        if width == 2 {
+            cells[col + 1] = Cell::spacer(style);
+        }
+
+        self.cursor_col += width;
+        if self.cursor_col >= self.cols {
+            // without autowrap the last column keeps being overwritten
+            self.cursor_col = self.cols - 1;
+            self.pending_wrap = self.modes.autowrap;
+        }
+    }
+
+    fn attach_to_previous(&mut self, c: char) -> bool {
+        let (row, col) = match self.previous_cell() {
+            Some(pos) => {pos},
+            None => {return false}
+        };
+
+        let cell = &mut self.grid[row].cells[col];
+        if joins_cluster(&cell.text, c) {
+            cell.text.push(c);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The cell left of the cursor, skipping over the spacer of a wide char.
+    fn previous_cell(&self) -> Option<(usize, usize)> {
+        let mut col = if self.pending_wrap {
+            self.cursor_col
+        } else {
+            self.cursor_col.checked_sub(1)?
+        };
+        if self.grid[self.cursor_row].cells[col].is_spacer() {
+            col = col.checked_sub(1)?;
+        }
+
+        Some((self.cursor_row, col))
+    }
+
+    fn control(&mut self, c: char) {
+        match c {
+            '\r' => {
+                self.cursor_col = 0;
+                self.pending_wrap = false;
+            },
+            '\n' | '\x0b' | '\x0c' => {
+                self.line_feed();
+            },
+            '\x08' => {
+                self.cursor_col = self.cursor_col.saturating_sub(1);
+                self.pending_wrap = false;
+            },
+            '\t' => {
+                let next_stop = (self.cursor_col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.cursor_col = next_stop.min(self.cols - 1);
+            },
+            _ => {}
+        }
+    }
+
+    fn apply_escape(&mut self, esc: &Escape) {
+        match esc {
+            Escape::ZeroCursor => {
+                self.move_to(0, 0);
+            },
+            Escape::MoveCursorTo((line, col)) => {
+                self.move_to(line.saturating_sub(1) as usize, col.saturating_sub(1) as usize);
+            },
+            Escape::CursorUp(i) => {
+                let top = if self.cursor_row >= self.scroll_top { self.scroll_top } else { 0 };
+                self.move_to(self.cursor_row.saturating_sub(count(*i)).max(top), self.cursor_col);
+            },
+            Escape::CursorDown(i) => {
+                let bottom = if self.cursor_row <= self.scroll_bottom { self.scroll_bottom } else { self.rows - 1 };
+                self.move_to((self.cursor_row + count(*i)).min(bottom), self.cursor_col);
+            },
+            Escape::CursorRight(i) => {
+                self.move_to(self.cursor_row, self.cursor_col + count(*i));
+            },
+            Escape::CursorLeft(i) => {
+                self.move_to(self.cursor_row, self.cursor_col.saturating_sub(count(*i)));
+            },
+            Escape::CursorToNextLineStart(i) => {
+                self.move_to(self.cursor_row + count(*i), 0);
+            },
+            Escape::CursorToPastLineStart(i) => {
+                self.move_to(self.cursor_row.saturating_sub(count(*i)), 0);
+            },
+            Escape::CursorToCol(i) => {
+                self.move_to(self.cursor_row, count(*i) - 1);
+            },
+            Escape::CursorToLine(i) => {
+                self.move_to(count(*i) - 1, self.cursor_col);
+            },
+            Escape::CursorMoveOneLineUp => {
+                self.reverse_index();
+            },
+            Escape::Index => {
+                self.line_feed();
+            },
+            Escape::NextLine => {
+                self.cursor_col = 0;
+                self.line_feed();
+            },
+            Escape::SetScrollRegion((top, bottom)) => {
+                let top = count(*top) - 1;
+                let bottom = if *bottom == 0 { self.rows } else { (*bottom as usize).min(self.rows) };
+                if top + 1 < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom - 1;
+                    self.move_to(0, 0);
+                }
+            },
+            Escape::ScrollUp(i) => {
+                self.scroll_up(count(*i));
+            },
+            Escape::ScrollDown(i) => {
+                self.scroll_down(count(*i));
+            },
+            Escape::InsertLines(i) => {
+                self.insert_lines(count(*i));
+            },
+            Escape::DeleteLines(i) => {
+                self.delete_lines(count(*i));
+            },
+            Escape::InsertChars(i) => {
+                self.insert_chars(count(*i));
+            },
+            Escape::DeleteChars(i) => {
+                self.delete_chars(count(*i));
+            },
+            Escape::EraseChars(i) => {
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                self.erase_cells(row, col, col + count(*i));
+            },
+            Escape::EnterAltScreen => {
+                self.save_cursor();
+                self.enter_alt_screen();
+                self.clear_grid();
+            },
+            Escape::ExitAltScreen => {
+                self.leave_alt_screen();
+                self.restore_cursor();
+            },
+            Escape::SaveScreen => {
+                self.enter_alt_screen();
+            },
+            Escape::RestoreScreen => {
+                self.leave_alt_screen();
+            },
+            Escape::ResetTerminal => {
+                let scrollback = std::mem::take(&mut self.scrollback);
+                let working_dir = self.working_dir.take();
+                let title = self.title.take();
+                *self = Screen::new(self.cols, self.rows);
+                self.scrollback = scrollback;
+                self.working_dir = working_dir;
+                self.title = title;
+            },
+            Escape::ScreenAlignment => {
+                for row in &mut self.grid {
+                    *row = Row::new(self.cols);
+                    for cell in &mut row.cells {
+                        cell.text = String::from("E");
+                    }
+                }
+                self.scroll_top = 0;
+                self.scroll_bottom = self.rows - 1;
+                self.move_to(0, 0);
+            },
+            Escape::ClearInDisplay | Escape::ClearDisplayUntilScreenEnd => {
+                let (row, col) = (self.cursor_row, self.erase_start());
+                self.erase_cells(row, col, self.cols);
+                for r in row + 1..self.rows {
+                    self.grid[r] = Row::new(self.cols);
+                }
+            },
+            Escape::ClearDisplayUntilScreenStart => {
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                for r in 0..row {
+                    self.grid[r] = Row::new(self.cols);
+                }
+                self.erase_cells(row, 0, col + 1);
+            },
+            Escape::ClearAll => {
+                self.clear_grid();
+            },
+            Escape::EraseSavedLine => {
+                self.scrollback.clear();
+            },
+            Escape::PromptStart => {
+                self.mark(PromptMark::PromptStart);
+            },
+            Escape::CommandStart => {
+                self.mark(PromptMark::CommandStart);
+            },
+            Escape::CommandExecuted => {
+                self.mark(PromptMark::OutputStart);
+            },
+            Escape::CommandFinished(exit) => {
+                self.mark(PromptMark::CommandFinished(*exit));
+            },
+            Escape::CurrentDirectory(path) => {
+                self.working_dir = Some(path.clone());
+            },
+            Escape::SetTitle(title) => {
+                self.title = Some(title.clone());
+            },
+            Escape::PushTitle => {
+                // xterm keeps ten, a program pushing in a loop can not grow it
+                if self.titles.len() == TITLE_STACK {
+                    self.titles.remove(0);
+                }
+                self.titles.push(self.title.clone());
+            },
+            Escape::PopTitle => {
+                if let Some(title) = self.titles.pop() {
+                    self.title = title;
+                }
+            },
+            Escape::EraseInLine | Escape::EraseFromCursorToEnd => {
+                let (row, col) = (self.cursor_row, self.erase_start());
+                self.erase_cells(row, col, self.cols);
+            },
+            Escape::EraseFromCursorToStart => {
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                self.erase_cells(row, 0, col + 1);
+            },
+            Escape::EraseLine => {
+                let row = self.cursor_row;
+                self.erase_cells(row, 0, self.cols);
+            },
+            Escape::SaveCursorPos => {
+                self.save_cursor();
+            },
+            Escape::RestoreCursorPos => {
+                self.restore_cursor();
+            },
+            _ => {
+                if !self.modes.apply(esc) {
+                    self.pen.apply(esc);
+                }
+            }
+        }
+    }
+
+    /// Where an erase to the end of the line starts. Like tmux, the last
+    /// column is kept when the cursor is waiting to wrap after filling it,
+    /// top pads every line to the full width and then sends EL.
+    fn erase_start(&self) -> usize {
+        if self.pending_wrap {
+            self.cols
+        } else {
+            self.cursor_col
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = (self.cursor_row, self.cursor_col);
+        self.saved_pen = self.pen;
+    }
+
+    fn restore_cursor(&mut self) {
+        let (row, col) = self.saved_cursor;
+        self.move_to(row, col);
+        self.pen = self.saved_pen;
+    }
+
+    fn clear_grid(&mut self) {
+        for r in 0..self.rows {
+            self.grid[r] = Row::new(self.cols);
+        }
+    }
+
+    fn enter_alt_screen(&mut self) {
+        if self.primary.is_none() {
+            let alt = (0..self.rows).map(|_| Row::new(self.cols)).collect();
+            self.primary = Some(std::mem::replace(&mut self.grid, alt));
+            self.modes.alt_screen = true;
+        }
+    }
+
+    fn leave_alt_screen(&mut self) {
+        if let Some(primary) = self.primary.take() {
+            self.grid = primary;
+            self.modes.alt_screen = false;
+        }
+    }
+
+    fn mark(&mut self, kind: PromptMark) {
+        let col = self.cursor_col;
+        self.grid[self.cursor_row].marks.push(Mark { kind, col });
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.cursor_row = row.min(self.rows - 1);
+        self.cursor_col = col.min(self.cols - 1);
+        self.pending_wrap = false;
+    }
+
+    fn wrap(&mut self) {
+        self.grid[self.cursor_row].wrapped = true;
+        self.cursor_col = 0;
+        self.pending_wrap = false;
+        self.line_feed();
+    }
+
+    fn line_feed(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor_row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        }
+    }
+
+    /// Scrolls the scroll region up. Lines only go to the scrollback when
+    /// they leave the top of the normal screen.
+    fn scroll_up(&mut self, n: usize) {
+        let n = n.min(self.scroll_bottom - self.scroll_top + 1);
+        let scrolled: Vec<Row> = self.grid.drain(self.scroll_top..self.scroll_top + n).collect();
+        let at = self.scroll_bottom + 1 - n;
+        for _ in 0..n {
+            self.grid.insert(at, Row::new(self.cols));
+        }
+        if self.scroll_top == 0 && !self.modes.alt_screen {
+            self.push_scrollback(scrolled);
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.scroll_bottom - self.scroll_top + 1);
+        self.grid.drain(self.scroll_bottom + 1 - n..=self.scroll_bottom);
+        for _ in 0..n {
+            self.grid.insert(self.scroll_top, Row::new(self.cols));
+        }
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        if self.cursor_row < self.scroll_top || self.cursor_row > self.scroll_bottom {
+            return;
+        }
+        let n = n.min(self.scroll_bottom - self.cursor_row + 1);
+        self.grid.drain(self.scroll_bottom + 1 - n..=self.scroll_bottom);
+        for _ in 0..n {
+            self.grid.insert(self.cursor_row, Row::new(self.cols));
+        }
+        self.cursor_col = 0;
+        self.pending_wrap = false;
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        if self.cursor_row < self.scroll_top || self.cursor_row > self.scroll_bottom {
+            return;
+        }
+        let n = n.min(self.scroll_bottom - self.cursor_row + 1);
+        self.grid.drain(self.cursor_row..self.cursor_row + n);
+        let at = self.scroll_bottom + 1 - n;
+        for _ in 0..n {
+            self.grid.insert(at, Row::new(self.cols));
+        }
+        self.cursor_col = 0;
+        self.pending_wrap = false;
+    }
+
+    fn insert_chars(&mut self, n: usize) {
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        let n = n.min(self.cols - col);
+        self.clear_wide_remnants(row, col);
+        let cells = &mut self.grid[row].cells;
+        for _ in 0..n {
+            cells.insert(col, Cell::blank());
+        }
+        cells.truncate(self.cols);
+        // a wide char pushed against the margin loses its spacer
+        if cells[self.cols - 1].width == 2 {
+            cells[self.cols - 1] = Cell::blank();
+        }
+        self.pending_wrap = false;
+    }
+
+    fn delete_chars(&mut self, n: usize) {
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        let n = n.min(self.cols - col);
+        self.clear_wide_remnants(row, col);
+        self.clear_wide_remnants(row, col + n - 1);
+        let cells = &mut self.grid[row].cells;
+        cells.drain(col..col + n);
+        cells.resize(self.cols, Cell::blank());
+        self.pending_wrap = false;
+    }
+
+    fn push_scrollback(&mut self, rows: Vec<Row>) {
+        for row in rows {
+            if self.scrollback.len() >= SCROLLBACK_LIMIT {
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(row);
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor_row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor_row > 0 {
+            self.cursor_row -= 1;
+        }
+    }
+
+    /// Blanks `start..end` on a row. Wide chars cut in half by either edge
+    /// are blanked completely so no orphaned halves are left behind.
+    fn erase_cells(&mut self, row: usize, start: usize, end: usize) {
+        let end = end.min(self.cols);
+        if start >= end {
+            return;
+        }
+        self.clear_wide_remnants(row, start);
+        self.clear_wide_remnants(row, end - 1);
+        for cell in &mut self.grid[row].cells[start..end] {
+            *cell = Cell::blank();
+        }
+    }
+
+    /// Before `col` is overwritten, blank the other half of any wide char
+    /// that currently covers it.
+    fn clear_wide_remnants(&mut self, row: usize, col: usize) {
+        let cells = &mut self.grid[row].cells;
+        if col >= cells.len() {
+            return;
+        }
+        if cells[col].is_spacer() && col > 0 {
+            cells[col - 1] = Cell::blank();
+            cells[col] = Cell::blank();
+        } else if cells[col].width == 2 && col + 1 < cells.len() {
+            cells[col + 1] = Cell::blank();
+        }
+    }
+}
+
+/// Changes the width of every row, blanking wide chars cut at the margin.
+fn resize_rows(rows: &mut [Row], cols: usize) {
+    for row in rows {
+        row.cells.resize(cols, Cell::blank());
+        if row.cells[cols - 1].width == 2 {
+            row.cells[cols - 1] = Cell::blank();
+        }
+    }
+}
+
+/// CSI counts of zero are treated as one.
+fn count(i: u32) -> usize {
+    (i as usize).max(1)
+}
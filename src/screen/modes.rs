@@ -0,0 +1,141 @@
+use crate::ascii::escapes::Escape;
+
+/// Terminal modes switched by the program running in the pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modes {
+    pub app_cursor: bool,
+    pub app_keypad: bool,
+    pub autowrap: bool,
+    pub cursor_visible: bool,
+    pub alt_screen: bool,
+    pub bracketed_paste: bool,
+    /// xterm modifyOtherKeys level, 0 for off.
+    pub modify_other_keys: u32,
+    pub keyboard: KeyboardFlags,
+    pub mouse: MouseTracking,
+    /// Mouse reports use the SGR encoding, mode 1006.
+    pub sgr_mouse: bool,
+    /// The program is told when the pane gains and loses focus, mode 1004.
+    pub focus_reports: bool,
+}
+
+/// Which mouse events the program asked to be told about. Each mode
+/// reports everything the ones before it do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    /// Presses and releases, mode 1000.
+    Clicks,
+    /// Moves while a button is held too, mode 1002.
+    Drag,
+    /// Every move, mode 1003.
+    Motion,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Modes {
+            app_cursor: false,
+            app_keypad: false,
+            autowrap: true,
+            cursor_visible: true,
+            alt_screen: false,
+            bracketed_paste: false,
+            modify_other_keys: 0,
+            keyboard: KeyboardFlags::default(),
+            mouse: MouseTracking::Off,
+            sgr_mouse: false,
+            focus_reports: false,
+        }
+    }
+}
+
+impl Modes {
+    /// Updates a mode flag from an escape. Returns false for escapes that
+    /// are not simple mode switches. The alt screen flag is kept by
+    /// `Screen` since switching it also swaps the grid.
+    pub fn apply(&mut self, esc: &Escape) -> bool {
+        match esc {
+            Escape::EnableAppCursor       => { self.app_cursor = true },
+            Escape::DisableAppCursor      => { self.app_cursor = false },
+            Escape::EnableAppKeypad       => { self.app_keypad = true },
+            Escape::DisableAppKeypad      => { self.app_keypad = false },
+            Escape::EnableAutoWrap        => { self.autowrap = true },
+            Escape::DisableAutoWrap       => { self.autowrap = false },
+            Escape::SetCursorVisible      => { self.cursor_visible = true },
+            Escape::SetCursorInvisible    => { self.cursor_visible = false },
+            Escape::EnableBracketedPaste  => { self.bracketed_paste = true },
+            Escape::DisableBracketedPaste => { self.bracketed_paste = false },
+            Escape::EnableMouseClicks     => { self.mouse = MouseTracking::Clicks },
+            Escape::EnableMouseDrag       => { self.mouse = MouseTracking::Drag },
+            Escape::EnableMouseMotion     => { self.mouse = MouseTracking::Motion },
+            // like tmux, turning off any of them turns tracking off
+            Escape::DisableMouseClicks | Escape::DisableMouseDrag | Escape::DisableMouseMotion => { self.mouse = MouseTracking::Off },
+            Escape::EnableSgrMouse        => { self.sgr_mouse = true },
+            Escape::DisableSgrMouse       => { self.sgr_mouse = false },
+            Escape::EnableFocusReports    => { self.focus_reports = true },
+            Escape::DisableFocusReports   => { self.focus_reports = false },
+            Escape::SetModifyOtherKeys(level) => { self.modify_other_keys = *level },
+            Escape::PushKeyboardFlags(flags) => { self.keyboard.push(*flags) },
+            Escape::PopKeyboardFlags(count) => { self.keyboard.pop(*count) },
+            Escape::SetKeyboardFlags((flags, mode)) => { self.keyboard.set(*flags, *mode) },
+            _ => { return false }
+        }
+
+        true
+    }
+}
+
+/// Kitty keyboard protocol flags the program pushed, the ones in effect
+/// on top. The stack is bounded, pushing onto a full one drops the oldest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyboardFlags {
+    stack: [u8; 8],
+    len: usize,
+}
+
+impl KeyboardFlags {
+    pub const DISAMBIGUATE: u32 = 1;
+    pub const REPORT_EVENTS: u32 = 2;
+    pub const REPORT_ALTERNATES: u32 = 4;
+    pub const REPORT_ALL_KEYS: u32 = 8;
+    pub const REPORT_TEXT: u32 = 16;
+
+    pub fn current(&self) -> u32 {
+        match self.len {
+            0 => {0},
+            len => {self.stack[len - 1] as u32}
+        }
+    }
+
+    pub fn push(&mut self, flags: u32) {
+        if self.len == self.stack.len() {
+            self.stack.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.stack[self.len] = (flags & 0x1f) as u8;
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self, count: u32) {
+        self.len = self.len.saturating_sub(count as usize);
+    }
+
+    /// Mode 1 replaces the flags in effect, 2 sets the given ones and 3
+    /// clears them.
+    pub fn set(&mut self, flags: u32, mode: u32) {
+        let current = self.current();
+        let flags = match mode {
+            1 => {flags},
+            2 => {current | flags},
+            3 => {current & !flags},
+            _ => {return}
+        };
+        if self.len == 0 {
+            self.push(flags);
+        } else {
+            self.stack[self.len - 1] = (flags & 0x1f) as u8;
+        }
+    }
+}
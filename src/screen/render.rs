@@ -0,0 +1,93 @@
+use crate::ascii::escapes::Escape;
+use super::grid::Screen;
+use super::selection::Selection;
+use super::snapshot::{SnapshotFormat, SnapshotOptions};
+
+/// Paints a screen's worth of lines starting at `top` onto the host, with
+/// the pane placed at the given offset.
+pub fn render_lines(screen: &Screen, top: usize, offset_x: u32, offset_y: u32) -> Vec<u8> {
+    let mut out = Escape::SetCursorInvisible.into_bytes();
+    for row in 0..screen.rows() {
+        out.append(&mut Escape::MoveCursorTo((offset_y + row as u32 + 1, offset_x + 1)).into_bytes());
+        let line = top + row;
+        if line < screen.total_lines() {
+            let text = screen.snapshot(&SnapshotOptions {
+                format: SnapshotFormat::Ansi,
+                lines: Some(line..line + 1),
+                trim_trailing: false,
+            });
+            out.extend_from_slice(text.as_bytes());
+        } else {
+            out.append(&mut " ".repeat(screen.cols()).into_bytes());
+        }
+    }
+
+    out
+}
+
+/// Repaints the live screen and leaves the host cursor and pen where the
+/// child program expects them.
+pub fn render_screen(screen: &Screen, offset_x: u32, offset_y: u32) -> Vec<u8> {
+    let mut out = render_lines(screen, screen.scrollback_len(), offset_x, offset_y);
+    let (row, col) = screen.cursor();
+    out.append(&mut Escape::MoveCursorTo((offset_y + row as u32 + 1, offset_x + col as u32 + 1)).into_bytes());
+    for esc in screen.pen().to_escapes() {
+        out.append(&mut esc.into_bytes());
+    }
+    out.append(&mut Escape::SetCursorVisible.into_bytes());
+
+    out
+}
+
+/// Draws the part of `selection` within the lines shown from `top` in
+/// inverse video, leaving the host cursor and pen as they were.
+pub fn render_selection(screen: &Screen, selection: &Selection, top: usize, offset_x: u32, offset_y: u32) -> Vec<u8> {
+    let mut out = Escape::SaveCursorPos.into_bytes();
+    for row in 0..screen.rows() {
+        let line = top + row;
+        let cells = match screen.line(line) {
+            Some(line) => {&line.cells},
+            None => {break}
+        };
+        let columns = match selection.columns(line, cells.len()) {
+            Some(columns) if !columns.is_empty() => {columns},
+            _ => {continue}
+        };
+        out.append(&mut Escape::MoveCursorTo((offset_y + row as u32 + 1, offset_x + columns.start as u32 + 1)).into_bytes());
+        out.append(&mut Escape::ResetAllModes.into_bytes());
+        out.append(&mut Escape::SetInverse.into_bytes());
+        for cell in &cells[columns] {
+            out.extend_from_slice(cell.text.as_bytes());
+        }
+    }
+    out.append(&mut Escape::RestoreCursorPos.into_bytes());
+
+    out
+}
+
+/// Inverse video line drawn over the bottom row of the pane.
+pub fn render_status(screen: &Screen, text: &str, offset_x: u32, offset_y: u32) -> Vec<u8> {
+    let line: String = text.chars().chain(std::iter::repeat(' ')).take(screen.cols()).collect();
+    let mut out = Escape::MoveCursorTo((offset_y + screen.rows() as u32, offset_x + 1)).into_bytes();
+    out.append(&mut Escape::ResetAllModes.into_bytes());
+    out.append(&mut Escape::SetInverse.into_bytes());
+    out.append(&mut line.into_bytes());
+    out.append(&mut Escape::ResetAllModes.into_bytes());
+
+    out
+}
+
+/// Inverse video label in the top right corner of the pane, leaving the
+/// host cursor and pen as they were.
+pub fn render_badge(screen: &Screen, text: &str, offset_x: u32, offset_y: u32) -> Vec<u8> {
+    let text: String = text.chars().take(screen.cols()).collect();
+    let col = screen.cols() - text.chars().count();
+    let mut out = Escape::SaveCursorPos.into_bytes();
+    out.append(&mut Escape::MoveCursorTo((offset_y + 1, offset_x + col as u32 + 1)).into_bytes());
+    out.append(&mut Escape::ResetAllModes.into_bytes());
+    out.append(&mut Escape::SetInverse.into_bytes());
+    out.append(&mut text.into_bytes());
+    out.append(&mut Escape::RestoreCursorPos.into_bytes());
+
+    out
+}
@@ -0,0 +1,83 @@
+use super::grid::Screen;
+
+/// Text picked with the mouse, from the cell the button went down on to
+/// the one it is at now. Positions are (line, col) with lines numbered the
+/// way `Screen::line` numbers them, so a selection stays on its text while
+/// the view scrolls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+}
+
+impl Selection {
+    pub fn new(line: usize, col: usize) -> Selection {
+        Selection {
+            anchor: (line, col),
+            head: (line, col),
+        }
+    }
+
+    pub fn extend(&mut self, line: usize, col: usize) {
+        self.head = (line, col);
+    }
+
+    /// First and last selected cell, in reading order.
+    pub fn bounds(&self) -> ((usize, usize), (usize, usize)) {
+        match self.anchor <= self.head {
+            true => {(self.anchor, self.head)},
+            false => {(self.head, self.anchor)}
+        }
+    }
+
+    /// Selected columns of `line` as a half open range, `None` when the
+    /// line is not part of the selection.
+    pub fn columns(&self, line: usize, cols: usize) -> Option<std::ops::Range<usize>> {
+        let ((start_line, start_col), (end_line, end_col)) = self.bounds();
+        if line < start_line || line > end_line {
+            return None;
+        }
+        let start = match line == start_line {
+            true => {start_col.min(cols)},
+            false => {0}
+        };
+        let end = match line == end_line {
+            true => {(end_col + 1).min(cols)},
+            false => {cols}
+        };
+        Some(start..end)
+    }
+
+    /// A click without a drag selects a single cell, which is not worth
+    /// copying.
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+}
+
+impl Screen {
+    /// The selected text. Rows that wrapped are joined back into one line
+    /// and trailing blanks are dropped, like the host terminal would.
+    pub fn selection_text(&self, selection: &Selection) -> String {
+        let ((start_line, _), (end_line, _)) = selection.bounds();
+        let mut text = String::new();
+        for line in start_line..=end_line {
+            let row = match self.line(line) {
+                Some(row) => {row},
+                None => {break}
+            };
+            let columns = selection.columns(line, row.cells.len()).unwrap_or_default();
+            let part: String = row.cells[columns.clone()].iter().map(|cell| cell.text.as_str()).collect();
+            let wraps = row.wrapped && columns.end == row.cells.len();
+            match wraps {
+                true => {text.push_str(&part)},
+                false => {text.push_str(part.trim_end_matches(' '))}
+            }
+            if line != end_line && !wraps {
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+}
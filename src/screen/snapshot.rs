@@ -0,0 +1,204 @@
+use std::ops::Range;
+
+use super::grid::{Cell, Row, Screen};
+use super::style::{palette_rgb, Color, Style};
+use crate::ascii::escapes::Escape;
+
+// colours used for inverse video when the cell uses the default colours
+const HTML_DEFAULT_FG: &str = "#e5e5e5";
+const HTML_DEFAULT_BG: &str = "#000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Plain,
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    pub format: SnapshotFormat,
+    /// Lines to dump, numbered the same way as `Screen::line`. `None` dumps
+    /// the visible screen.
+    pub lines: Option<Range<usize>>,
+    pub trim_trailing: bool,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        SnapshotOptions {
+            format: SnapshotFormat::Plain,
+            lines: None,
+            trim_trailing: true,
+        }
+    }
+}
+
+impl Screen {
+    /// Dumps screen contents as text. Soft wrapped rows are joined back
+    /// into one line so copied output matches what the program printed.
+    pub fn snapshot(&self, options: &SnapshotOptions) -> String {
+        let lines = options.lines.clone().unwrap_or_else(|| self.visible_lines());
+        let end = lines.end.min(self.total_lines());
+        let mut out = String::new();
+
+        if options.format == SnapshotFormat::Html {
+            out.push_str("<pre class=\"fi-snapshot\">");
+        }
+        for line in lines.start..end {
+            let row = match self.line(line) {
+                Some(row) => {row},
+                None => {break}
+            };
+            let last = line + 1 == end;
+            let soft_wrapped = row.wrapped && !last;
+            let cells = kept_cells(row, options.format, options.trim_trailing && !soft_wrapped);
+
+            match options.format {
+                SnapshotFormat::Plain => {push_plain(&mut out, cells)},
+                SnapshotFormat::Ansi => {push_ansi(&mut out, cells)},
+                SnapshotFormat::Html => {push_html(&mut out, cells)},
+            }
+            if !soft_wrapped && !last {
+                out.push('\n');
+            }
+        }
+        if options.format == SnapshotFormat::Html {
+            out.push_str("</pre>");
+        }
+
+        out
+    }
+
+    /// Plain text of the visible screen with trailing whitespace removed.
+    pub fn visible_text(&self) -> String {
+        self.snapshot(&SnapshotOptions::default())
+    }
+
+    /// Plain text of the scrollback followed by the visible screen.
+    pub fn all_text(&self) -> String {
+        self.snapshot(&SnapshotOptions {
+            lines: Some(0..self.total_lines()),
+            ..SnapshotOptions::default()
+        })
+    }
+}
+
+/// The cells of a row, minus trailing blanks when trimming. Blanks with a
+/// background colour are kept for the styled formats since they are visible.
+fn kept_cells(row: &Row, format: SnapshotFormat, trim: bool) -> &[Cell] {
+    if !trim {
+        return &row.cells;
+    }
+    let is_blank = |cell: &Cell| {
+        let invisible = format == SnapshotFormat::Plain
+            || (cell.style.bg == Color::Default && !cell.style.inverse);
+        cell.text.trim().is_empty() && invisible
+    };
+    let end = row.cells.iter().rposition(|cell| !is_blank(cell)).map_or(0, |i| i + 1);
+
+    &row.cells[..end]
+}
+
+fn push_plain(out: &mut String, cells: &[Cell]) {
+    for cell in cells {
+        out.push_str(&cell.text);
+    }
+}
+
+fn push_ansi(out: &mut String, cells: &[Cell]) {
+    let mut current = Style::default();
+    for cell in cells {
+        if cell.style != current {
+            for esc in cell.style.to_escapes() {
+                out.push_str(&String::from_utf8_lossy(&esc.into_bytes()));
+            }
+            current = cell.style;
+        }
+        out.push_str(&cell.text);
+    }
+    if !current.is_default() {
+        out.push_str(&String::from_utf8_lossy(&Escape::ResetAllModes.into_bytes()));
+    }
+}
+
+fn push_html(out: &mut String, cells: &[Cell]) {
+    let mut i = 0;
+    while i < cells.len() {
+        let style = cells[i].style;
+        let run = cells[i..].iter().take_while(|cell| cell.style == style).count();
+        let css = html_css(&style);
+        if !css.is_empty() {
+            out.push_str(&format!("<span style=\"{}\">", css));
+        }
+        for cell in &cells[i..i + run] {
+            for c in cell.text.chars() {
+                match c {
+                    '&' => {out.push_str("&amp;")},
+                    '<' => {out.push_str("&lt;")},
+                    '>' => {out.push_str("&gt;")},
+                    '"' => {out.push_str("&quot;")},
+                    _ => {out.push(c)}
+                }
+            }
+        }
+        if !css.is_empty() {
+            out.push_str("</span>");
+        }
+        i += run;
+    }
+}
+
+fn html_color(color: Color) -> Option<String> {
+    match color {
+        Color::Default => {None},
+        Color::Indexed(i) => {
+            let (r, g, b) = palette_rgb(i);
+            Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+        },
+        Color::Rgb(r, g, b) => {Some(format!("#{:02x}{:02x}{:02x}", r, g, b))}
+    }
+}
+
+fn html_css(style: &Style) -> String {
+    let mut fg = html_color(style.fg);
+    let mut bg = html_color(style.bg);
+    if style.inverse {
+        let swapped_fg = bg.unwrap_or_else(|| HTML_DEFAULT_BG.to_string());
+        let swapped_bg = fg.unwrap_or_else(|| HTML_DEFAULT_FG.to_string());
+        fg = Some(swapped_fg);
+        bg = Some(swapped_bg);
+    }
+
+    let mut decls = Vec::new();
+    if let Some(fg) = fg {
+        decls.push(format!("color:{}", fg));
+    }
+    if let Some(bg) = bg {
+        decls.push(format!("background:{}", bg));
+    }
+    if style.bold {
+        decls.push(String::from("font-weight:bold"));
+    }
+    if style.dim {
+        decls.push(String::from("opacity:0.5"));
+    }
+    if style.italic {
+        decls.push(String::from("font-style:italic"));
+    }
+    if style.underline || style.strikethrough {
+        let mut lines = Vec::new();
+        if style.underline {
+            lines.push("underline");
+        }
+        if style.strikethrough {
+            lines.push("line-through");
+        }
+        decls.push(format!("text-decoration:{}", lines.join(" ")));
+    }
+    if style.hidden {
+        decls.push(String::from("visibility:hidden"));
+    }
+
+    decls.join(";")
+}
@@ -0,0 +1,214 @@
+use crate::ascii::escapes::Escape;
+
+/// Cell colour. Indexes 0-7 are the normal colours, 8-15 the bright ones
+/// and 16-255 the xterm 256 colour palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blinking: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+impl Style {
+    pub fn is_default(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// Updates the style from an SGR escape. Returns false for escapes that
+    /// are not graphic renditions.
+    pub fn apply(&mut self, esc: &Escape) -> bool {
+        match esc {
+            Escape::ResetAllModes            => { *self = Style::default() },
+            Escape::SetBold                  => { self.bold = true },
+            Escape::SetDim                   => { self.dim = true },
+            Escape::SetItalic                => { self.italic = true },
+            Escape::SetUnderline             => { self.underline = true },
+            Escape::SetBlinking              => { self.blinking = true },
+            Escape::SetInverse               => { self.inverse = true },
+            Escape::SetHidden                => { self.hidden = true },
+            Escape::SetStrikethrough         => { self.strikethrough = true },
+            Escape::ResetBold                => { self.bold = false },
+            Escape::ResetDim                 => { self.dim = false },
+            Escape::ResetItalic              => { self.italic = false },
+            Escape::ResetUnderline           => { self.underline = false },
+            Escape::ResetBlinking            => { self.blinking = false },
+            Escape::ResetInverse             => { self.inverse = false },
+            Escape::ResetHidden              => { self.hidden = false },
+            Escape::ResetStrikethrough       => { self.strikethrough = false },
+            Escape::SetForgroundDefault      => { self.fg = Color::Default },
+            Escape::SetBackgroundDefault     => { self.bg = Color::Default },
+            Escape::SetForgroundCustomColor(i)  => { self.fg = Color::Indexed(*i) },
+            Escape::SetBackgroundCustomColor(i) => { self.bg = Color::Indexed(*i) },
+            Escape::SetForgroundRgbColor(r, g, b)  => { self.fg = Color::Rgb(*r, *g, *b) },
+            Escape::SetBackgroundRgbColor(r, g, b) => { self.bg = Color::Rgb(*r, *g, *b) },
+            _ => {
+                match (fg_index(esc), bg_index(esc)) {
+                    (Some(i), _) => { self.fg = Color::Indexed(i) },
+                    (_, Some(i)) => { self.bg = Color::Indexed(i) },
+                    _ => { return false }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// The escapes that recreate this style starting from a reset.
+    pub fn to_escapes(&self) -> Vec<Escape> {
+        let mut escapes = vec![Escape::ResetAllModes];
+        let flags = [
+            (self.bold, Escape::SetBold),
+            (self.dim, Escape::SetDim),
+            (self.italic, Escape::SetItalic),
+            (self.underline, Escape::SetUnderline),
+            (self.blinking, Escape::SetBlinking),
+            (self.inverse, Escape::SetInverse),
+            (self.hidden, Escape::SetHidden),
+            (self.strikethrough, Escape::SetStrikethrough),
+        ];
+        for (set, esc) in flags {
+            if set {
+                escapes.push(esc);
+            }
+        }
+        match self.fg {
+            Color::Default => {},
+            Color::Indexed(i) => {escapes.push(fg_escape(i))},
+            Color::Rgb(r, g, b) => {escapes.push(Escape::SetForgroundRgbColor(r, g, b))}
+        }
+        match self.bg {
+            Color::Default => {},
+            Color::Indexed(i) => {escapes.push(bg_escape(i))},
+            Color::Rgb(r, g, b) => {escapes.push(Escape::SetBackgroundRgbColor(r, g, b))}
+        }
+
+        escapes
+    }
+}
+
+fn fg_index(esc: &Escape) -> Option<u8> {
+    let i = match esc {
+        Escape::SetForgroundBlack      => {0},
+        Escape::SetForgroundRed        => {1},
+        Escape::SetForgroundGreen      => {2},
+        Escape::SetForgroundYellow     => {3},
+        Escape::SetForgroundBlue       => {4},
+        Escape::SetForgroundMagenta    => {5},
+        Escape::SetForgroundCyan       => {6},
+        Escape::SetForgroundWhite      => {7},
+        Escape::SetForgroundBriBlack   => {8},
+        Escape::SetForgroundBriRed     => {9},
+        Escape::SetForgroundBriGreen   => {10},
+        Escape::SetForgroundBriYellow  => {11},
+        Escape::SetForgroundBriBlue    => {12},
+        Escape::SetForgroundBriMagenta => {13},
+        Escape::SetForgroundBriCyan    => {14},
+        Escape::SetForgroundBriWhite   => {15},
+        _ => {return None}
+    };
+    Some(i)
+}
+
+fn bg_index(esc: &Escape) -> Option<u8> {
+    let i = match esc {
+        Escape::SetBackgroundBlack      => {0},
+        Escape::SetBackgroundRed        => {1},
+        Escape::SetBackgroundGreen      => {2},
+        Escape::SetBackgroundYellow     => {3},
+        Escape::SetBackgroundBlue       => {4},
+        Escape::SetBackgroundMagenta    => {5},
+        Escape::SetBackgroundCyan       => {6},
+        Escape::SetBackgroundWhite      => {7},
+        Escape::SetBackgroundBriBlack   => {8},
+        Escape::SetBackgroundBriRed     => {9},
+        Escape::SetBackgroundBriGreen   => {10},
+        Escape::SetBackgroundBriYellow  => {11},
+        Escape::SetBackgroundBriBlue    => {12},
+        Escape::SetBackgroundBriMagenta => {13},
+        Escape::SetBackgroundBriCyan    => {14},
+        Escape::SetBackgroundBriWhite   => {15},
+        _ => {return None}
+    };
+    Some(i)
+}
+
+fn fg_escape(i: u8) -> Escape {
+    match i {
+        0  => {Escape::SetForgroundBlack},
+        1  => {Escape::SetForgroundRed},
+        2  => {Escape::SetForgroundGreen},
+        3  => {Escape::SetForgroundYellow},
+        4  => {Escape::SetForgroundBlue},
+        5  => {Escape::SetForgroundMagenta},
+        6  => {Escape::SetForgroundCyan},
+        7  => {Escape::SetForgroundWhite},
+        8  => {Escape::SetForgroundBriBlack},
+        9  => {Escape::SetForgroundBriRed},
+        10 => {Escape::SetForgroundBriGreen},
+        11 => {Escape::SetForgroundBriYellow},
+        12 => {Escape::SetForgroundBriBlue},
+        13 => {Escape::SetForgroundBriMagenta},
+        14 => {Escape::SetForgroundBriCyan},
+        15 => {Escape::SetForgroundBriWhite},
+        _  => {Escape::SetForgroundCustomColor(i)}
+    }
+}
+
+fn bg_escape(i: u8) -> Escape {
+    match i {
+        0  => {Escape::SetBackgroundBlack},
+        1  => {Escape::SetBackgroundRed},
+        2  => {Escape::SetBackgroundGreen},
+        3  => {Escape::SetBackgroundYellow},
+        4  => {Escape::SetBackgroundBlue},
+        5  => {Escape::SetBackgroundMagenta},
+        6  => {Escape::SetBackgroundCyan},
+        7  => {Escape::SetBackgroundWhite},
+        8  => {Escape::SetBackgroundBriBlack},
+        9  => {Escape::SetBackgroundBriRed},
+        10 => {Escape::SetBackgroundBriGreen},
+        11 => {Escape::SetBackgroundBriYellow},
+        12 => {Escape::SetBackgroundBriBlue},
+        13 => {Escape::SetBackgroundBriMagenta},
+        14 => {Escape::SetBackgroundBriCyan},
+        15 => {Escape::SetBackgroundBriWhite},
+        _  => {Escape::SetBackgroundCustomColor(i)}
+    }
+}
+
+/// RGB value of a palette entry using the xterm defaults.
+pub fn palette_rgb(i: u8) -> (u8, u8, u8) {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00), (0xcd, 0x00, 0x00), (0x00, 0xcd, 0x00), (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee), (0xcd, 0x00, 0xcd), (0x00, 0xcd, 0xcd), (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f), (0xff, 0x00, 0x00), (0x00, 0xff, 0x00), (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff), (0xff, 0x00, 0xff), (0x00, 0xff, 0xff), (0xff, 0xff, 0xff),
+    ];
+    match i {
+        0..=15 => {BASE[i as usize]},
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = i - 16;
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        },
+        _ => {
+            let gray = 8 + (i - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
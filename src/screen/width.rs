@@ -0,0 +1,23 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// Number of cells a char takes up on its own. Control chars have no
+/// width and zero width chars (combining marks, ZWJ, variation selectors)
+/// are reported as 0 so the caller can attach them to the previous cell.
+pub fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Whether `c` continues the grapheme cluster already stored in a cell
+/// rather than starting a new one. This covers combining marks, ZWJ emoji
+/// sequences, variation selectors and skin tone modifiers.
+pub fn joins_cluster(cluster: &str, c: char) -> bool {
+    if cluster.is_empty() || c.is_control() {
+        return false;
+    }
+    let mut joined = String::with_capacity(cluster.len() + c.len_utf8());
+    joined.push_str(cluster);
+    joined.push(c);
+
+    joined.graphemes(true).count() == 1
+}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use super::grid::Screen;
+use super::snapshot::SnapshotOptions;
+
+/// Shell integration marks sent with OSC 133.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptMark {
+    PromptStart,
+    CommandStart,
+    OutputStart,
+    CommandFinished(Option<i32>),
+}
+
+/// A mark and the column it arrived at, stored on the row it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mark {
+    pub kind: PromptMark,
+    pub col: usize,
+}
+
+/// One prompt and the command run from it. Line numbers are the same as
+/// `Screen::line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandZone {
+    pub prompt_line: usize,
+    pub output: Option<Range<usize>>,
+    pub exit_status: Option<i32>,
+    pub finished: bool,
+}
+
+impl Screen {
+    /// Every prompt found in the scrollback and on screen, oldest first.
+    pub fn commands(&self) -> Vec<CommandZone> {
+        let mut zones = Vec::new();
+        let mut current: Option<CommandZone> = None;
+        // output of a command that is still running ends at the cursor
+        let cursor_end = self.scrollback_len() + self.cursor().0 + 1;
+
+        for line in 0..self.total_lines() {
+            let row = match self.line(line) {
+                Some(row) => {row},
+                None => {break}
+            };
+            for mark in &row.marks {
+                let end = if mark.col == 0 { line } else { line + 1 };
+                match mark.kind {
+                    PromptMark::PromptStart => {
+                        if let Some(mut zone) = current.take() {
+                            close_output(&mut zone, end);
+                            zones.push(zone);
+                        }
+                        current = Some(CommandZone {
+                            prompt_line: line,
+                            output: None,
+                            exit_status: None,
+                            finished: false,
+                        });
+                    },
+                    PromptMark::CommandStart => {},
+                    PromptMark::OutputStart => {
+                        if let Some(zone) = current.as_mut() {
+                            zone.output = Some(line..cursor_end.max(line));
+                        }
+                    },
+                    PromptMark::CommandFinished(exit) => {
+                        if let Some(zone) = current.as_mut() {
+                            close_output(zone, end);
+                            zone.exit_status = exit;
+                            zone.finished = true;
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(zone) = current {
+            zones.push(zone);
+        }
+
+        zones
+    }
+
+    /// Closest prompt line above `line`.
+    pub fn previous_prompt(&self, line: usize) -> Option<usize> {
+        self.commands().iter().rev().map(|zone| zone.prompt_line).find(|prompt| *prompt < line)
+    }
+
+    /// Closest prompt line below `line`.
+    pub fn next_prompt(&self, line: usize) -> Option<usize> {
+        self.commands().iter().map(|zone| zone.prompt_line).find(|prompt| *prompt > line)
+    }
+
+    /// The most recent command that produced an output zone.
+    pub fn last_command(&self) -> Option<CommandZone> {
+        self.commands().into_iter().rev().find(|zone| zone.output.is_some())
+    }
+
+    pub fn command_output(&self, zone: &CommandZone) -> Option<String> {
+        let lines = zone.output.clone()?;
+        Some(self.snapshot(&SnapshotOptions {
+            lines: Some(lines),
+            ..SnapshotOptions::default()
+        }))
+    }
+
+    pub fn last_command_output(&self) -> Option<String> {
+        self.command_output(&self.last_command()?)
+    }
+}
+
+/// Ends a still open output zone at `end`. Output that was already closed
+/// by a finish mark is left alone.
+fn close_output(zone: &mut CommandZone, end: usize) {
+    if zone.finished {
+        return;
+    }
+    if let Some(output) = zone.output.as_mut() {
+        output.end = end.max(output.start);
+    }
+}
@@ -0,0 +1,253 @@
+// Conformance cases live in tests/conformance/*.vt and look like:
+//
+//   # comment lines, usually where the case came from
+//   --- size 20x5
+//   --- input
+//   escaped bytes, \e \r \n \t \b \a \xHH and \\ are understood and the
+//   line breaks of the file itself are ignored
+//   --- screen
+//   expected rows, trailing blanks and empty rows are not compared
+//   --- cursor 2,3
+//   --- modes alt_screen -autowrap
+//   --- scrollback
+//   expected scrollback rows, oldest first
+//
+// Only the sections present are checked. Cursor positions are zero based
+// and a mode prefixed with `-` is expected to be off. Every case is fed
+// once in one go and once a byte at a time, both must give the same state.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use fi::ascii::parser::SequenceParser;
+use fi::screen::grid::{Row, Screen};
+use fi::screen::modes::{Modes, MouseTracking};
+
+#[derive(Default)]
+struct Case {
+    name: String,
+    cols: usize,
+    rows: usize,
+    input: Vec<u8>,
+    screen: Option<Vec<String>>,
+    cursor: Option<(usize, usize)>,
+    modes: Vec<(String, bool)>,
+    scrollback: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq)]
+struct State {
+    screen: Vec<String>,
+    cursor: (usize, usize),
+    modes: Modes,
+    scrollback: Vec<String>,
+}
+
+fn parse_case(path: &Path) -> Case {
+    let text = fs::read_to_string(path).unwrap();
+    let mut case = Case {
+        name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+        cols: 80,
+        rows: 24,
+        ..Case::default()
+    };
+    let mut section: Option<&str> = None;
+    let mut block: Vec<String> = Vec::new();
+
+    for line in text.lines().chain(std::iter::once("--- end")) {
+        let header = match line.strip_prefix("--- ") {
+            Some(header) => {header},
+            None => {
+                match section {
+                    Some(_) => {block.push(line.to_string())},
+                    None => {
+                        assert!(line.starts_with('#') || line.trim().is_empty(), "{}: stray line {:?}", case.name, line);
+                    }
+                }
+                continue;
+            }
+        };
+
+        match section.take() {
+            Some("input") => {case.input = unescape(&block.concat())},
+            Some("screen") => {case.screen = Some(trim_rows(&block))},
+            Some("scrollback") => {case.scrollback = Some(trim_rows(&block))},
+            _ => {}
+        }
+        block.clear();
+
+        let (name, value) = header.split_once(' ').unwrap_or((header, ""));
+        match name {
+            "size" => {
+                let (cols, rows) = value.split_once('x').unwrap();
+                case.cols = cols.trim().parse().unwrap();
+                case.rows = rows.trim().parse().unwrap();
+            },
+            "cursor" => {
+                let (row, col) = value.split_once(',').unwrap();
+                case.cursor = Some((row.trim().parse().unwrap(), col.trim().parse().unwrap()));
+            },
+            "modes" => {
+                for mode in value.split_whitespace() {
+                    match mode.strip_prefix('-') {
+                        Some(mode) => {case.modes.push((mode.to_string(), false))},
+                        None => {case.modes.push((mode.to_string(), true))}
+                    }
+                }
+            },
+            "input" => {section = Some("input")},
+            "screen" => {section = Some("screen")},
+            "scrollback" => {section = Some("scrollback")},
+            "end" => {},
+            _ => {panic!("{}: unknown section {:?}", case.name, header)}
+        }
+    }
+
+    case
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('e') => {bytes.push(0x1b)},
+            Some('r') => {bytes.push(b'\r')},
+            Some('n') => {bytes.push(b'\n')},
+            Some('t') => {bytes.push(b'\t')},
+            Some('b') => {bytes.push(0x08)},
+            Some('a') => {bytes.push(0x07)},
+            Some('\\') => {bytes.push(b'\\')},
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+            },
+            other => {panic!("bad escape \\{:?}", other)}
+        }
+    }
+
+    bytes
+}
+
+/// Drops trailing blanks of each row and the empty rows at the bottom.
+fn trim_rows<S: AsRef<str>>(rows: &[S]) -> Vec<String> {
+    let mut rows: Vec<String> = rows.iter().map(|row| row.as_ref().trim_end().to_string()).collect();
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+
+    rows
+}
+
+fn row_texts<'a>(rows: impl Iterator<Item = &'a Row>) -> Vec<String> {
+    let texts: Vec<String> = rows.map(|row| row.text()).collect();
+    trim_rows(&texts)
+}
+
+fn run(case: &Case, chunk_size: usize) -> State {
+    let mut screen = Screen::new(case.cols, case.rows);
+    let mut parser = SequenceParser::new();
+    for chunk in case.input.chunks(chunk_size.max(1)) {
+        for seq in parser.feed(chunk) {
+            screen.process(&seq);
+        }
+    }
+
+    State {
+        screen: row_texts((0..screen.rows()).filter_map(|row| screen.row(row))),
+        cursor: screen.cursor(),
+        modes: screen.modes(),
+        scrollback: row_texts((0..screen.scrollback_len()).filter_map(|line| screen.line(line))),
+    }
+}
+
+fn mode(modes: &Modes, name: &str) -> bool {
+    match name {
+        "app_cursor" => {modes.app_cursor},
+        "app_keypad" => {modes.app_keypad},
+        "autowrap" => {modes.autowrap},
+        "cursor_visible" => {modes.cursor_visible},
+        "alt_screen" => {modes.alt_screen},
+        "bracketed_paste" => {modes.bracketed_paste},
+        "mouse_clicks" => {modes.mouse == MouseTracking::Clicks},
+        "mouse_drag" => {modes.mouse == MouseTracking::Drag},
+        "mouse_motion" => {modes.mouse == MouseTracking::Motion},
+        "sgr_mouse" => {modes.sgr_mouse},
+        "focus_reports" => {modes.focus_reports},
+        _ => {panic!("unknown mode {}", name)}
+    }
+}
+
+fn check(case: &Case) -> Vec<String> {
+    let mut failures = Vec::new();
+    let state = run(case, case.input.len());
+
+    if let Some(screen) = &case.screen {
+        if *screen != state.screen {
+            failures.push(format!(
+                "screen differs\n--- expected\n{}\n--- got\n{}",
+                screen.join("\n"),
+                state.screen.join("\n")
+            ));
+        }
+    }
+    if let Some(cursor) = case.cursor {
+        if cursor != state.cursor {
+            failures.push(format!("cursor expected {:?}, got {:?}", cursor, state.cursor));
+        }
+    }
+    for (name, on) in &case.modes {
+        if mode(&state.modes, name) != *on {
+            failures.push(format!("mode {} expected {}", name, on));
+        }
+    }
+    if let Some(scrollback) = &case.scrollback {
+        if *scrollback != state.scrollback {
+            failures.push(format!(
+                "scrollback differs\n--- expected\n{}\n--- got\n{}",
+                scrollback.join("\n"),
+                state.scrollback.join("\n")
+            ));
+        }
+    }
+    if run(case, 1) != state {
+        failures.push(String::from("feeding one byte at a time gives a different state"));
+    }
+
+    failures.into_iter().map(|failure| format!("{}: {}", case.name, failure)).collect()
+}
+
+fn run_cases(prefix: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vt"))
+        .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with(prefix))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no {} cases", prefix);
+
+    let failures: Vec<String> = paths.iter().flat_map(|path| check(&parse_case(path))).collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn vttest_cases() {
+    run_cases("vttest_");
+}
+
+#[test]
+fn esctest_cases() {
+    run_cases("esctest_");
+}
+
+#[test]
+fn captured_sessions() {
+    run_cases("capture_");
+}
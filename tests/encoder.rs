@@ -0,0 +1,117 @@
+use fi::ascii::escapes::{encode_input, strip_escapes, Escape};
+use fi::ascii::input::*;
+use fi::ascii::parser::parse_bytes;
+use fi::ascii::escapes::Sequence;
+use fi::screen::modes::Modes;
+
+fn modes_after(bytes: &[u8]) -> Modes {
+    let mut modes = Modes::default();
+    for seq in parse_bytes(bytes) {
+        if let Sequence::Escape(escs) = seq {
+            for esc in &escs {
+                modes.apply(esc);
+            }
+        }
+    }
+    modes
+}
+
+fn encode(key: Key, modifiers: Modifiers, modes: &Modes) -> Vec<u8> {
+    encode_input(&InputEvent::Key(KeyEvent::new(key, modifiers)), modes)
+}
+
+#[test]
+fn legacy_keys() {
+    let modes = Modes::default();
+    assert_eq!(encode(Key::Char('a'), Modifiers::NONE, &modes), b"a");
+    assert_eq!(encode(Key::Char('a'), Modifiers::SHIFT, &modes), b"A");
+    assert_eq!(encode(Key::Char('c'), Modifiers::CTRL, &modes), b"\x03");
+    assert_eq!(encode(Key::Char('x'), Modifiers::ALT, &modes), b"\x1bx");
+    assert_eq!(encode(Key::Backspace, Modifiers::NONE, &modes), b"\x7f");
+    assert_eq!(encode(Key::Tab, Modifiers::SHIFT, &modes), b"\x1b[Z");
+    assert_eq!(encode(Key::Up, Modifiers::NONE, &modes), b"\x1b[A");
+    assert_eq!(encode(Key::Up, Modifiers::CTRL, &modes), b"\x1b[1;5A");
+    assert_eq!(encode(Key::F(1), Modifiers::NONE, &modes), b"\x1bOP");
+    assert_eq!(encode(Key::F(5), Modifiers::SHIFT, &modes), b"\x1b[15;2~");
+    assert_eq!(encode(Key::Delete, Modifiers::NONE, &modes), b"\x1b[3~");
+    assert_eq!(encode(Key::Keypad('5'), Modifiers::NONE, &modes), b"5");
+}
+
+#[test]
+fn cursor_and_keypad_modes() {
+    let modes = modes_after(b"\x1b[?1h\x1b=");
+    assert_eq!(encode(Key::Up, Modifiers::NONE, &modes), b"\x1bOA");
+    // modifiers always use the CSI form
+    assert_eq!(encode(Key::Up, Modifiers::SHIFT, &modes), b"\x1b[1;2A");
+    assert_eq!(encode(Key::Keypad('5'), Modifiers::NONE, &modes), b"\x1bOu");
+    assert_eq!(encode(Key::KeypadEnter, Modifiers::NONE, &modes), b"\x1bOM");
+}
+
+#[test]
+fn modify_other_keys() {
+    let modes = modes_after(b"\x1b[>4;2m");
+    assert_eq!(modes.modify_other_keys, 2);
+    assert_eq!(encode(Key::Char('i'), Modifiers::CTRL, &modes), b"\x1b[27;5;105~");
+    assert_eq!(encode(Key::Enter, Modifiers::SHIFT, &modes), b"\x1b[27;2;13~");
+    assert_eq!(encode(Key::Char('a'), Modifiers::NONE, &modes), b"a");
+
+    // level 1 keeps the keys that have a legacy encoding
+    let modes = modes_after(b"\x1b[>4;1m");
+    assert_eq!(encode(Key::Char('i'), Modifiers::CTRL, &modes), b"\x09");
+    assert_eq!(encode(Key::Enter, Modifiers::CTRL, &modes), b"\x1b[27;5;13~");
+    assert_eq!(modes_after(b"\x1b[>4;1m\x1b[>4m").modify_other_keys, 0);
+}
+
+#[test]
+fn kitty_keyboard_flags() {
+    let modes = modes_after(b"\x1b[>1u");
+    assert_eq!(modes.keyboard.current(), 1);
+    assert_eq!(encode(Key::Escape, Modifiers::NONE, &modes), b"\x1b[27u");
+    assert_eq!(encode(Key::Char('i'), Modifiers::CTRL, &modes), b"\x1b[105;5u");
+    assert_eq!(encode(Key::Char('a'), Modifiers::NONE, &modes), b"a");
+    assert_eq!(encode(Key::Enter, Modifiers::NONE, &modes), b"\r");
+    assert_eq!(encode(Key::Up, Modifiers::NONE, &modes), b"\x1b[A");
+
+    let modes = modes_after(b"\x1b[>1u\x1b[>9u");
+    assert_eq!(encode(Key::Char('A'), Modifiers::NONE, &modes), b"\x1b[97;2u");
+    assert_eq!(encode(Key::Enter, Modifiers::NONE, &modes), b"\x1b[13u");
+
+    assert_eq!(modes_after(b"\x1b[>1u\x1b[>9u\x1b[<u").keyboard.current(), 1);
+    assert_eq!(modes_after(b"\x1b[>1u\x1b[=8;2u").keyboard.current(), 9);
+    assert_eq!(modes_after(b"\x1b[>1u\x1b[<5u").keyboard.current(), 0);
+    assert_eq!(parse_bytes(b"\x1b[?u"), vec![Sequence::Escape(vec![Escape::QueryKeyboardFlags])]);
+}
+
+#[test]
+fn pastes_are_wrapped_only_when_asked_for() {
+    let paste = InputEvent::Paste(b"ls\r".to_vec());
+    assert_eq!(encode_input(&paste, &Modes::default()), b"ls\r");
+    assert_eq!(encode_input(&paste, &modes_after(b"\x1b[?2004h")), b"\x1b[200~ls\r\x1b[201~");
+}
+
+#[test]
+fn decoded_input_encodes_back_to_itself() {
+    let modes = modes_after(b"\x1b[?1000h\x1b[?1006h");
+    let input = b"ab\x03\x1d\r\x1b[A\x1b[1;5C\x1bOP\x1b[15~\x1b[3;2~\x1bx\x1b[5;3R\x1b[<0;3;4M";
+    let mut parser = InputParser::new();
+    let encoded: Vec<u8> = parser.feed(input).iter().flat_map(|event| encode_input(event, &modes)).collect();
+
+    assert_eq!(encoded, input.to_vec());
+}
+
+#[test]
+fn pasted_escapes_are_stripped() {
+    let paste = InputEvent::Paste(b"echo a\x1b[201~\x1b]0;title\x07b\x1bx\r".to_vec());
+    assert_eq!(encode_input(&paste, &Modes::default()), b"echo ab\r");
+    assert_eq!(encode_input(&paste, &modes_after(b"\x1b[?2004h")), b"\x1b[200~echo ab\r\x1b[201~");
+    assert_eq!(strip_escapes("é\x1b[1;31mred\x1bP1$r\x1b\\".as_bytes()), "éred".as_bytes());
+}
+
+#[test]
+fn focus_is_reported_only_when_asked_for() {
+    assert_eq!(encode_input(&InputEvent::FocusIn, &Modes::default()), b"");
+    let modes = modes_after(b"\x1b[?1004h");
+    assert_eq!(encode_input(&InputEvent::FocusIn, &modes), b"\x1b[I");
+    assert_eq!(encode_input(&InputEvent::FocusOut, &modes), b"\x1b[O");
+    assert!(!modes_after(b"\x1b[?1004h\x1b[?1004l").focus_reports);
+}
@@ -0,0 +1,256 @@
+use std::io::{Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+
+use fi::pty::control::{channel, Control};
+use fi::pty::poll::PollSet;
+use fi::pty::signals::SignalPipe;
+
+/// fi running in a pty of its own, the test standing in for the host
+/// terminal.
+struct Fi {
+    child: Box<dyn Child + Send + Sync>,
+    input: Box<dyn Write + Send>,
+    output: mpsc::Receiver<Vec<u8>>,
+    /// Everything fi drew so far.
+    host: Vec<u8>,
+    _pty: Box<dyn MasterPty + Send>,
+}
+
+impl Fi {
+    fn start(args: &[&str]) -> Fi {
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_fi"));
+        cmd.args(["--keys", "/dev/null"]);
+        cmd.args(args);
+        // new sessions get a plain shell without anyone's profile
+        cmd.env("SHELL", "/bin/sh");
+        cmd.env("HOME", std::env::temp_dir());
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        // the writer is kept open, dropping it sends an EOF
+        let input = pair.master.take_writer().unwrap();
+
+        let (sender, output) = mpsc::channel();
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                if sender.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Fi { child, input, output, host: Vec::new(), _pty: pair.master }
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        self.input.write_all(bytes).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    /// Where what fi draws next starts in `host`.
+    fn mark(&self) -> usize {
+        self.host.len()
+    }
+
+    /// Reads what fi draws for `time`.
+    fn read_for(&mut self, time: Duration) {
+        let started = Instant::now();
+        while started.elapsed() < time {
+            if let Ok(mut bytes) = self.output.recv_timeout(Duration::from_millis(10)) {
+                self.host.append(&mut bytes);
+            }
+        }
+    }
+
+    /// Reads what fi draws until `text` shows up after `from`.
+    fn wait_for(&mut self, from: usize, text: &str) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            if self.host[from..].windows(text.len()).any(|w| w == text.as_bytes()) {
+                return true;
+            }
+            if let Ok(mut bytes) = self.output.recv_timeout(Duration::from_millis(20)) {
+                self.host.append(&mut bytes);
+            }
+        }
+        false
+    }
+
+    fn is_running(&mut self) -> bool {
+        self.child.try_wait().unwrap().is_none()
+    }
+}
+
+impl Drop for Fi {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn control_requests_wake_the_poll() {
+    let (controller, mut control) = channel().unwrap();
+    let mut poll = PollSet::new();
+    let wake = poll.add(control.raw_fd());
+
+    assert_eq!(poll.wait(Some(Duration::ZERO)).unwrap(), 0);
+    assert!(!poll.readable(wake));
+
+    controller.send(Control::Resize(100, 30)).unwrap();
+    controller.send(Control::Input(b"ls\r".to_vec())).unwrap();
+    assert_eq!(poll.wait(Some(Duration::from_secs(1))).unwrap(), 1);
+    assert!(poll.readable(wake));
+    assert_eq!(control.drain(), vec![Control::Resize(100, 30), Control::Input(b"ls\r".to_vec())]);
+
+    // draining empties the wake pipe too
+    assert_eq!(poll.wait(Some(Duration::ZERO)).unwrap(), 0);
+    assert!(control.drain().is_empty());
+}
+
+#[test]
+fn other_threads_wake_a_blocked_poll() {
+    let (controller, mut control) = channel().unwrap();
+    let sender = controller.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        sender.send(Control::Close).unwrap();
+    });
+
+    let mut poll = PollSet::new();
+    let wake = poll.add(control.raw_fd());
+    while !poll.readable(wake) {
+        poll.wait(None).unwrap();
+    }
+    handle.join().unwrap();
+
+    assert_eq!(control.drain(), vec![Control::Close]);
+}
+
+#[test]
+fn one_signal_pipe_at_a_time() {
+    let signals = SignalPipe::install(&[libc::SIGUSR1]).unwrap();
+    // a second pipe would leave the first one's reader without signals
+    assert!(SignalPipe::install(&[libc::SIGUSR2]).is_err());
+
+    unsafe { libc::raise(libc::SIGUSR1) };
+    assert_eq!(signals.wait().unwrap(), libc::SIGUSR1);
+
+    drop(signals);
+    let signals = SignalPipe::install(&[libc::SIGUSR1]).unwrap();
+    unsafe { libc::raise(libc::SIGUSR1) };
+    assert_eq!(signals.wait().unwrap(), libc::SIGUSR1);
+}
+
+#[test]
+fn hold_keeps_the_exited_session() {
+    let mut fi = Fi::start(&["--on-exit", "hold", "/bin/sh", "-c", "echo bye; exit 3"]);
+
+    assert!(fi.wait_for(0, "[exited 3]"));
+    assert!(fi.is_running());
+
+    fi.send(b"q");
+    fi.read_for(Duration::from_millis(300));
+    assert!(!fi.is_running());
+}
+
+#[test]
+fn respawn_restarts_the_program() {
+    let runs = std::env::temp_dir().join(format!("fi-runs-{}", std::process::id()));
+    let _ = std::fs::remove_file(&runs);
+    let script = format!("echo >> {0}; printf 'run %s\\n' $(wc -l < {0}); sleep 1.2", runs.display());
+    let mut fi = Fi::start(&["--on-exit", "respawn", "/bin/sh", "-c", &script]);
+
+    assert!(fi.wait_for(0, "run 1"));
+    let respawned = fi.mark();
+    assert!(fi.wait_for(respawned, "run 2"));
+    assert!(fi.is_running());
+    let _ = std::fs::remove_file(&runs);
+}
+
+#[test]
+fn a_stale_history_view_is_drawn_once() {
+    let mut fi = Fi::start(&["--fps", "20", "/bin/sh", "-c", "seq 100; sleep 5"]);
+    assert!(fi.wait_for(0, "99\r\n100"));
+
+    // wheel up into the scrollback, then have something ask for a repaint
+    let browsing = fi.mark();
+    fi.send(b"\x1b[<64;10;10M");
+    assert!(fi.wait_for(browsing, "75 "));
+    let toggled = fi.mark();
+    fi.send(b"b");
+    assert!(fi.wait_for(toggled, "75 "));
+    fi.read_for(Duration::from_millis(300));
+
+    let settled = fi.mark();
+    fi.read_for(Duration::from_millis(500));
+    assert_eq!(fi.host.len(), settled, "{:?}", String::from_utf8_lossy(&fi.host[settled..]));
+}
+
+/// The lines of `path` once it has `count` of them.
+fn lines_of(path: &std::path::Path, count: usize) -> Vec<String> {
+    let started = Instant::now();
+    loop {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        if lines.len() >= count || started.elapsed() > Duration::from_secs(5) {
+            return lines;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn broadcast_types_and_pastes_into_every_session() {
+    let file = std::env::temp_dir().join(format!("fi-broadcast-{}", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+    let mut fi = Fi::start(&["/bin/sh"]);
+    thread::sleep(Duration::from_millis(200));
+
+    let started = fi.mark();
+    fi.send(b"c");
+    fi.send(b"b");
+    assert!(fi.wait_for(started, "BROADCAST to 2"));
+    fi.send(b"i");
+    fi.send(format!("echo $$ >> {}\r", file.display()).as_bytes());
+    let pids = lines_of(&file, 2);
+    assert_eq!(pids.len(), 2);
+    assert_ne!(pids[0], pids[1]);
+
+    // sh takes no bracketed pastes, so several lines are confirmed first
+    let pasting = fi.mark();
+    fi.send(format!("\x1b[200~echo one >> {0}\necho two >> {0}\n\x1b[201~", file.display()).as_bytes());
+    assert!(fi.wait_for(pasting, "paste 2 lines"));
+    fi.send(b"y");
+    let mut pasted = lines_of(&file, 6).split_off(2);
+    pasted.sort();
+    assert_eq!(pasted, ["one", "one", "two", "two"]);
+    let _ = std::fs::remove_file(&file);
+}
+
+#[test]
+fn broadcast_skips_exited_sessions() {
+    let file = std::env::temp_dir().join(format!("fi-broadcast-exited-{}", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+    let mut fi = Fi::start(&["--on-exit", "hold", "/bin/sh", "-c", "exit 3"]);
+    assert!(fi.wait_for(0, "[exited 3]"));
+
+    let started = fi.mark();
+    fi.send(b"c");
+    fi.send(b"c");
+    fi.send(b"b");
+    assert!(fi.wait_for(started, "BROADCAST to 2"));
+    fi.send(b"i");
+    fi.send(format!("echo $$ >> {}\r", file.display()).as_bytes());
+
+    assert_eq!(lines_of(&file, 2).len(), 2);
+    // nothing more trickles in from the held session
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(lines_of(&file, 0).len(), 2);
+    let _ = std::fs::remove_file(&file);
+}
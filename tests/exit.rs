@@ -0,0 +1,40 @@
+use portable_pty::{native_pty_system, CommandBuilder, ExitStatus, PtySize};
+
+use fi::pty::exit::{ChildExit, ExitPolicy};
+
+fn run(script: &str) -> ChildExit {
+    let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+    let mut cmd = CommandBuilder::new("/bin/sh");
+    cmd.args(["-c", script]);
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+
+    ChildExit::from_status(&child.wait().unwrap())
+}
+
+#[test]
+fn exit_codes_are_reported() {
+    let exit = ChildExit::from_status(&ExitStatus::with_exit_code(1));
+
+    assert_eq!(exit, ChildExit { code: 1, signal: None });
+    assert!(!exit.success());
+    assert_eq!(exit.banner(), "[exited 1]");
+    assert!(ChildExit::from_status(&ExitStatus::with_exit_code(0)).success());
+}
+
+#[test]
+fn children_are_reaped_with_their_status() {
+    assert_eq!(run("exit 3"), ChildExit { code: 3, signal: None });
+
+    let killed = run("kill -TERM $$");
+    assert_eq!(killed.signal.as_deref(), Some("Terminated"));
+    assert!(!killed.success());
+    assert_eq!(killed.banner(), "[exited: Terminated]");
+}
+
+#[test]
+fn policies_parse_from_their_names() {
+    assert_eq!(ExitPolicy::default(), ExitPolicy::Close);
+    assert_eq!(ExitPolicy::from_name("hold"), Some(ExitPolicy::Hold));
+    assert_eq!(ExitPolicy::from_name("respawn"), Some(ExitPolicy::Respawn));
+    assert_eq!(ExitPolicy::from_name("linger"), None);
+}
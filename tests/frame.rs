@@ -0,0 +1,69 @@
+use std::io::Read;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+use fi::pty::frame::FramePacer;
+
+#[test]
+fn frames_are_spaced_by_the_interval() {
+    let mut pacer = FramePacer::new(50);
+    let start = Instant::now();
+    assert_eq!(pacer.interval(), Duration::from_millis(20));
+    assert!(pacer.ready(start));
+
+    pacer.drawn(start);
+    assert!(!pacer.ready(start + Duration::from_millis(5)));
+    assert_eq!(pacer.wait(start + Duration::from_millis(5)), Duration::from_millis(15));
+    assert!(pacer.ready(start + Duration::from_millis(20)));
+    assert_eq!(pacer.wait(start + Duration::from_secs(1)), Duration::ZERO);
+}
+
+#[test]
+fn zero_fps_is_uncapped() {
+    let mut pacer = FramePacer::new(0);
+    let start = Instant::now();
+    pacer.drawn(start);
+
+    assert!(pacer.ready(start));
+}
+
+#[test]
+fn mode_changes_survive_a_flood_of_output() {
+    let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+    let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_fi"));
+    // one frame a second, the first drawn for `ready`. The mouse mode
+    // change after it waits for the next frame, which the flood overflows.
+    let script = "printf ready; sleep 0.3; printf '\\033[?1003h'; sleep 0.1; head -c 300000 /dev/zero | tr '\\0' x; sleep 5";
+    cmd.args(["--fps", "1", "--keys", "/dev/null", "/bin/sh", "-c", script]);
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+    let _input = pair.master.take_writer().unwrap();
+
+    let (sender, output) = mpsc::channel();
+    let mut reader = pair.master.try_clone_reader().unwrap();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while let Ok(n @ 1..) = reader.read(&mut buf) {
+            if sender.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let mut host = Vec::new();
+    let mut drawn = false;
+    while started.elapsed() < Duration::from_secs(4) {
+        if let Ok(mut bytes) = output.recv_timeout(Duration::from_millis(50)) {
+            host.append(&mut bytes);
+        }
+        if host.windows(8).any(|w| w == b"\x1b[?1003h") {
+            drawn = true;
+            break;
+        }
+    }
+    child.kill().unwrap();
+
+    assert!(drawn, "the host never got the child's mouse mode");
+}
@@ -0,0 +1,66 @@
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+use fi::pty::exit::ChildExit;
+use fi::pty::guard::{hang_up, TerminalGuard};
+
+#[test]
+fn hang_up_reaches_the_whole_process_group() {
+    let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+    let mut cmd = CommandBuilder::new("/bin/sh");
+    // the background sleep shares the shell's group and keeps it waiting
+    cmd.args(["-c", "sleep 30 & wait"]);
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    hang_up(child.process_id(), pair.master.as_raw_fd());
+    let exit = ChildExit::from_status(&child.wait().unwrap());
+
+    assert_eq!(exit.signal.as_deref(), Some("Hangup"));
+}
+
+#[test]
+fn hang_up_never_signals_fi_itself() {
+    let own = unsafe { libc::getpgrp() } as u32;
+
+    // would take the test runner down with it if it went through
+    hang_up(Some(own), None);
+}
+
+/// The local modes of stdin's terminal.
+fn local_modes() -> libc::tcflag_t {
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::tcgetattr(0, &mut termios) }, 0);
+    termios.c_lflag
+}
+
+#[test]
+fn a_nested_enter_is_refused() {
+    if std::env::var_os("FI_TEST_IN_PTY").is_none() {
+        // the guard needs a terminal, the test runs again inside a pty
+        let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+        let mut cmd = CommandBuilder::new(std::env::current_exe().unwrap());
+        cmd.args(["--exact", "a_nested_enter_is_refused"]);
+        cmd.env("FI_TEST_IN_PTY", "1");
+        let mut child = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+        let mut output = pair.master.try_clone_reader().unwrap();
+        let output = std::thread::spawn(move || {
+            let mut text = String::new();
+            let _ = std::io::Read::read_to_string(&mut output, &mut text);
+            text
+        });
+
+        let exit = ChildExit::from_status(&child.wait().unwrap());
+        drop(pair.master);
+        assert!(exit.success(), "{}", output.join().unwrap());
+        return;
+    }
+
+    let cooked = local_modes();
+    let guard = TerminalGuard::enter().unwrap();
+    assert_ne!(local_modes(), cooked);
+    assert!(TerminalGuard::enter().is_err());
+
+    drop(guard);
+    assert_eq!(local_modes(), cooked);
+}
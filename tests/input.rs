@@ -0,0 +1,200 @@
+use std::time::{Duration, Instant};
+
+use fi::ascii::input::*;
+
+fn keys(bytes: &[u8]) -> Vec<InputEvent> {
+    let mut parser = InputParser::new();
+    let mut events = parser.feed(bytes);
+    events.append(&mut parser.flush());
+    events
+}
+
+fn key(key: Key, modifiers: Modifiers) -> InputEvent {
+    InputEvent::Key(KeyEvent::new(key, modifiers))
+}
+
+#[test]
+fn text_and_control_keys() {
+    assert_eq!(keys("aé\r\t\x7f".as_bytes()), vec![
+        key(Key::Char('a'), Modifiers::NONE),
+        key(Key::Char('é'), Modifiers::NONE),
+        key(Key::Enter, Modifiers::NONE),
+        key(Key::Tab, Modifiers::NONE),
+        key(Key::Backspace, Modifiers::NONE),
+    ]);
+    assert_eq!(keys(b"\x03\x1d\x00"), vec![
+        InputEvent::Key(KeyEvent::ctrl('c')),
+        InputEvent::Key(KeyEvent::ctrl(']')),
+        InputEvent::Key(KeyEvent::ctrl(' ')),
+    ]);
+    assert_eq!(keys(b"\xff"), vec![InputEvent::Unknown(vec![0xff])]);
+}
+
+#[test]
+fn cursor_and_function_keys() {
+    let ctrl_shift = Modifiers { shift: true, ctrl: true, ..Modifiers::NONE };
+    assert_eq!(keys(b"\x1b[A\x1bOB\x1b[1;5C\x1b[1;6D"), vec![
+        key(Key::Up, Modifiers::NONE),
+        key(Key::Down, Modifiers::NONE),
+        key(Key::Right, Modifiers::CTRL),
+        key(Key::Left, ctrl_shift),
+    ]);
+    assert_eq!(keys(b"\x1bOP\x1b[15~\x1b[24;3~\x1b[3~\x1b[5;5~\x1b[Z"), vec![
+        key(Key::F(1), Modifiers::NONE),
+        key(Key::F(5), Modifiers::NONE),
+        key(Key::F(12), Modifiers::ALT),
+        key(Key::Delete, Modifiers::NONE),
+        key(Key::PageUp, Modifiers::CTRL),
+        key(Key::Tab, Modifiers::SHIFT),
+    ]);
+    assert_eq!(keys(b"\x1bOM\x1bOq"), vec![
+        key(Key::KeypadEnter, Modifiers::NONE),
+        key(Key::Keypad('1'), Modifiers::NONE),
+    ]);
+}
+
+#[test]
+fn kitty_and_modify_other_keys() {
+    assert_eq!(keys(b"\x1b[97;5u\x1b[13;2u\x1b[27;5;105~"), vec![
+        key(Key::Char('a'), Modifiers::CTRL),
+        key(Key::Enter, Modifiers::SHIFT),
+        key(Key::Char('i'), Modifiers::CTRL),
+    ]);
+    // releases are dropped, private use keys fi does not know are kept raw
+    assert_eq!(keys(b"\x1b[97;1:3u\x1b[57441u"), vec![InputEvent::Unknown(b"\x1b[57441u".to_vec())]);
+}
+
+#[test]
+fn mouse_reports() {
+    assert_eq!(keys(b"\x1b[<0;10;5M\x1b[<32;11;5M\x1b[<0;11;5m\x1b[<65;1;1M\x1b[<35;3;4M"), vec![
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Press, button: Some(MouseButton::Left), col: 9, row: 4, modifiers: Modifiers::NONE }),
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Drag, button: Some(MouseButton::Left), col: 10, row: 4, modifiers: Modifiers::NONE }),
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Release, button: Some(MouseButton::Left), col: 10, row: 4, modifiers: Modifiers::NONE }),
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Press, button: Some(MouseButton::WheelDown), col: 0, row: 0, modifiers: Modifiers::NONE }),
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Move, button: None, col: 2, row: 3, modifiers: Modifiers::NONE }),
+    ]);
+    assert_eq!(keys(b"\x1b[<18;2;2M"), vec![
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Press, button: Some(MouseButton::Right), col: 1, row: 1, modifiers: Modifiers::CTRL }),
+    ]);
+    assert_eq!(keys(b"\x1b[M !!"), vec![
+        InputEvent::Mouse(MouseEvent { action: MouseAction::Press, button: Some(MouseButton::Left), col: 0, row: 0, modifiers: Modifiers::NONE }),
+    ]);
+}
+
+#[test]
+fn focus_and_paste() {
+    assert_eq!(keys(b"\x1b[I\x1b[200~ls\x1b[A\r\x1b[201~\x1b[O"), vec![
+        InputEvent::FocusIn,
+        InputEvent::Paste(b"ls\x1b[A\r".to_vec()),
+        InputEvent::FocusOut,
+    ]);
+}
+
+#[test]
+fn sequences_split_across_reads() {
+    let mut parser = InputParser::new();
+    assert!(parser.feed(b"\x1b[1;").is_empty());
+    assert_eq!(parser.feed(b"5A\xc3"), vec![key(Key::Up, Modifiers::CTRL)]);
+    assert_eq!(parser.feed(b"\xa9"), vec![key(Key::Char('é'), Modifiers::NONE)]);
+
+    // a paste waits for its end however long it takes
+    assert!(parser.feed(b"\x1b[200~one\r").is_empty());
+    assert_eq!(parser.timeout(Instant::now()), None);
+    assert_eq!(parser.feed(b"two\x1b[201~"), vec![InputEvent::Paste(b"one\rtwo".to_vec())]);
+    assert!(!parser.has_pending());
+}
+
+#[test]
+fn escape_or_alt_is_decided_by_timeout() {
+    let mut parser = InputParser::new();
+    assert!(parser.feed(b"\x1b").is_empty());
+    let now = Instant::now();
+    assert!(parser.expire(now).is_empty());
+    assert!(parser.timeout(now).unwrap() <= ESC_TIMEOUT);
+    assert_eq!(parser.expire(now + ESC_TIMEOUT), vec![key(Key::Escape, Modifiers::NONE)]);
+    assert_eq!(parser.timeout(now), None);
+
+    // arriving together ESC and a key are the key with Alt
+    assert_eq!(parser.feed(b"\x1bx"), vec![key(Key::Char('x'), Modifiers::ALT)]);
+    assert_eq!(keys(b"\x1b\x1b[A"), vec![key(Key::Up, Modifiers::ALT)]);
+    assert_eq!(keys(b"\x1b["), vec![key(Key::Char('['), Modifiers::ALT)]);
+    assert_eq!(keys(b"\x1b\x1b"), vec![key(Key::Escape, Modifiers::NONE), key(Key::Escape, Modifiers::NONE)]);
+
+    assert!(parser.feed(b"\x1b").is_empty());
+    assert_eq!(parser.expire(Instant::now() + Duration::from_secs(1)).len(), 1);
+}
+
+#[test]
+fn adaptive_escape_waits_only_once_sequences_split() {
+    let mut parser = InputParser::new();
+    parser.set_adaptive(true);
+    assert!(parser.is_eager());
+    assert_eq!(parser.feed(b"\x1b"), vec![key(Key::Escape, Modifiers::NONE)]);
+    assert_eq!(parser.feed(b"\x1b[A"), vec![key(Key::Up, Modifiers::NONE)]);
+    assert!(parser.is_eager());
+
+    // the rest of a sequence right after its ESC was taken as a key is
+    // still that key
+    assert_eq!(parser.feed(b"\x1b").len(), 1);
+    assert_eq!(parser.feed(b"[B"), vec![key(Key::Down, Modifiers::NONE)]);
+    assert!(!parser.is_eager());
+    assert!(parser.feed(b"\x1b").is_empty());
+    assert_eq!(parser.expire(Instant::now() + ESC_TIMEOUT), vec![key(Key::Escape, Modifiers::NONE)]);
+
+    // a sequence cut off at the end of a read is a split too
+    let mut parser = InputParser::new();
+    parser.set_adaptive(true);
+    assert!(parser.feed(b"\x1b[1;").is_empty());
+    assert!(!parser.is_eager());
+}
+
+#[test]
+fn adaptive_escape_is_not_fooled_by_typing() {
+    let mut parser = InputParser::new();
+    parser.set_adaptive(true);
+
+    // <Esc>O in vim, typed by a person rather than sent as one sequence
+    assert_eq!(parser.feed(b"\x1b"), vec![key(Key::Escape, Modifiers::NONE)]);
+    assert_eq!(parser.feed(b"O"), vec![key(Key::Char('O'), Modifiers::NONE)]);
+    assert!(parser.is_eager());
+    // and the same after a pause longer than a split takes
+    assert_eq!(parser.feed(b"\x1b").len(), 1);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(parser.feed(b"[A"), vec![key(Key::Char('['), Modifiers::NONE), key(Key::Char('A'), Modifiers::NONE)]);
+    assert!(parser.is_eager());
+
+    // Alt-Escape comes as ESC ESC in one write
+    assert_eq!(parser.feed(b"\x1b\x1b"), vec![key(Key::Escape, Modifiers::NONE), key(Key::Escape, Modifiers::NONE)]);
+    assert!(parser.is_eager());
+
+    // utf8 cut in two is no sequence
+    assert!(parser.feed(b"\xc3").is_empty());
+    assert_eq!(parser.feed(b"\xa9"), vec![key(Key::Char('é'), Modifiers::NONE)]);
+    assert!(parser.is_eager());
+}
+
+#[test]
+fn adaptive_escape_recovers_after_whole_reads() {
+    let mut parser = InputParser::new();
+    parser.set_adaptive(true);
+    assert!(parser.feed(b"\x1b[1;").is_empty());
+    assert!(!parser.is_eager());
+
+    assert_eq!(parser.feed(b"5A"), vec![key(Key::Up, Modifiers::CTRL)]);
+    for _ in 0..30 {
+        parser.feed(b"x");
+    }
+    assert!(!parser.is_eager());
+    parser.feed(b"\x1b[A");
+    assert!(parser.is_eager());
+}
+
+#[test]
+fn escape_timeout_is_configurable() {
+    let mut parser = InputParser::new();
+    parser.set_escape_timeout(Duration::from_millis(200));
+    assert!(parser.feed(b"\x1b").is_empty());
+    let now = Instant::now();
+    assert!(parser.expire(now + ESC_TIMEOUT).is_empty());
+    assert_eq!(parser.expire(now + Duration::from_millis(200)).len(), 1);
+}
@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+use fi::ascii::input::{Key, KeyEvent, Modifiers};
+use fi::pty::keymap::*;
+
+fn keys(text: &str) -> Vec<KeyEvent> {
+    parse_keys(text).unwrap()
+}
+
+#[test]
+fn key_notation() {
+    assert_eq!(keys("<C-]> g t"), vec![KeyEvent::ctrl(']'), KeyEvent::plain(Key::Char('g')), KeyEvent::plain(Key::Char('t'))]);
+    assert_eq!(keys("gT"), keys("g T"));
+    assert_eq!(keys("<C-CR>"), vec![KeyEvent::new(Key::Enter, Modifiers::CTRL)]);
+    assert_eq!(keys("<C-S-F5>"), vec![KeyEvent::new(Key::F(5), Modifiers { shift: true, ctrl: true, ..Modifiers::NONE })]);
+    assert_eq!(keys("<C-A>"), keys("<C-a>"));
+    assert_eq!(keys("<S-x>"), keys("X"));
+    assert_eq!(keys("<lt><Space>"), vec![KeyEvent::plain(Key::Char('<')), KeyEvent::plain(Key::Char(' '))]);
+    assert_eq!(parse_keys(""), None);
+    assert_eq!(parse_keys("<C-]"), None);
+    assert_eq!(parse_keys("<Nope>"), None);
+}
+
+#[test]
+fn chords_wait_for_their_last_key() {
+    let keymap = Keymap::default();
+    assert_eq!(keymap.lookup(InputMode::Insert, &keys("<C-]>")), Lookup::Action(Action::Normal));
+    assert_eq!(keymap.lookup(InputMode::Insert, &keys("q")), Lookup::Unbound);
+    assert_eq!(keymap.lookup(InputMode::Normal, &keys("q")), Lookup::Action(Action::Quit));
+    assert_eq!(keymap.lookup(InputMode::Normal, &keys("g")), Lookup::Prefix(None));
+    assert_eq!(keymap.lookup(InputMode::Normal, &keys("g T")), Lookup::Action(Action::PreviousSession));
+    assert_eq!(keymap.lookup(InputMode::Normal, &keys("g x")), Lookup::Unbound);
+}
+
+#[test]
+fn config_rebinds_the_prefix() {
+    let mut keymap = Keymap::default();
+    keymap.apply_config("
+        # the global escape on Ctrl-Enter
+        map insert <C-]> <C-w> next-session
+        prefix <C-CR>
+        map normal <Tab> next-session   # like a browser
+        unmap normal q
+    ").unwrap();
+
+    assert_eq!(keymap.prefix(), Some(keys("<C-CR>").as_slice()));
+    assert_eq!(keymap.lookup(InputMode::Insert, &keys("<C-]>")), Lookup::Unbound);
+    assert_eq!(keymap.lookup(InputMode::Insert, &keys("<C-CR>")), Lookup::Prefix(Some(Action::Normal)));
+    assert_eq!(keymap.action(InputMode::Insert, &keys("<C-CR> <C-w>")), Some(Action::NextSession));
+    assert_eq!(keymap.action(InputMode::Normal, &keys("<Tab>")), Some(Action::NextSession));
+    assert_eq!(keymap.action(InputMode::Normal, &keys("q")), None);
+
+    // only modifyOtherKeys or the kitty protocol tell Ctrl-Enter from Enter
+    assert!(!Keymap::default().needs_extended_keys());
+    assert!(keymap.needs_extended_keys());
+}
+
+#[test]
+fn hash_is_a_key_unless_it_starts_a_word() {
+    let mut keymap = Keymap::default();
+    keymap.apply_config("
+        map normal g# next-session # only after whitespace is this a comment
+        map normal <Hash> previous-session
+        #map normal x quit
+    ").unwrap();
+
+    assert_eq!(keymap.action(InputMode::Normal, &keys("g #")), Some(Action::NextSession));
+    assert_eq!(keymap.action(InputMode::Normal, &keys("#")), Some(Action::PreviousSession));
+    assert_eq!(keymap.action(InputMode::Normal, &keys("x")), None);
+    // a lone # is taken as the start of a comment, leaving no action
+    assert!(keymap.apply_config("map normal # quit").is_err());
+}
+
+#[test]
+fn recording_can_move_back_to_q() {
+    let mut keymap = Keymap::default();
+    assert_eq!(keymap.action(InputMode::Normal, &keys("Q")), Some(Action::Record));
+
+    // the README's config for vim's keys
+    keymap.apply_config("map normal q record\nmap normal ZZ quit\n").unwrap();
+    assert_eq!(keymap.action(InputMode::Normal, &keys("q")), Some(Action::Record));
+    assert_eq!(keymap.action(InputMode::Normal, &keys("ZZ")), Some(Action::Quit));
+}
+
+#[test]
+fn config_errors_name_the_line() {
+    let mut keymap = Keymap::default();
+    let err = keymap.apply_config("map normal x quit\nmap normal x explode\n").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("line 2"), "{}", err);
+    assert!(keymap.apply_config("map visual x quit").is_err());
+    assert!(keymap.apply_config("unmap normal").is_err());
+}
+
+#[test]
+fn input_after_quit_is_dropped() {
+    let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+    let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_fi"));
+    cmd.args(["--keys", "/dev/null", "/bin/cat"]);
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+    // drained so drawing to the host never blocks fi
+    let mut output = pair.master.try_clone_reader().unwrap();
+    std::thread::spawn(move || io::copy(&mut output, &mut io::sink()));
+    std::thread::sleep(Duration::from_millis(300));
+
+    // fi starts in normal mode, what is left of the read after `q` has no
+    // session to go to. The writer stays open, dropping it sends an EOF.
+    let mut input = pair.master.take_writer().unwrap();
+    input.write_all(b"q").unwrap();
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "fi did not quit");
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    assert!(status.success(), "{:?}", status);
+}
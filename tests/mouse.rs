@@ -0,0 +1,64 @@
+use fi::ascii::escapes::encode_mouse;
+use fi::ascii::input::{Modifiers, MouseAction, MouseButton, MouseEvent};
+use fi::ascii::parser::SequenceParser;
+use fi::screen::grid::Screen;
+use fi::screen::modes::{Modes, MouseTracking};
+use fi::screen::selection::Selection;
+
+fn mouse(action: MouseAction, button: Option<MouseButton>, col: u16, row: u16) -> MouseEvent {
+    MouseEvent { action, button, col, row, modifiers: Modifiers::NONE }
+}
+
+#[test]
+fn reports_only_what_the_tracking_mode_asks_for() {
+    let press = mouse(MouseAction::Press, Some(MouseButton::Left), 4, 2);
+    let drag = mouse(MouseAction::Drag, Some(MouseButton::Left), 5, 2);
+    let moved = mouse(MouseAction::Move, None, 5, 2);
+
+    let mut modes = Modes::default();
+    assert!(encode_mouse(&press, &modes).is_empty());
+
+    modes.mouse = MouseTracking::Clicks;
+    modes.sgr_mouse = true;
+    assert_eq!(encode_mouse(&press, &modes), b"\x1b[<0;5;3M");
+    assert!(encode_mouse(&drag, &modes).is_empty());
+
+    modes.mouse = MouseTracking::Drag;
+    assert_eq!(encode_mouse(&drag, &modes), b"\x1b[<32;6;3M");
+    assert!(encode_mouse(&moved, &modes).is_empty());
+
+    modes.mouse = MouseTracking::Motion;
+    assert_eq!(encode_mouse(&moved, &modes), b"\x1b[<35;6;3M");
+}
+
+#[test]
+fn legacy_encoding() {
+    let modes = Modes { mouse: MouseTracking::Clicks, ..Modes::default() };
+    assert_eq!(encode_mouse(&mouse(MouseAction::Press, Some(MouseButton::Right), 0, 0), &modes), b"\x1b[M\x22!!");
+    // releases do not say which button, and wheels have none
+    assert_eq!(encode_mouse(&mouse(MouseAction::Release, Some(MouseButton::Right), 0, 0), &modes), b"\x1b[M#!!");
+    assert!(encode_mouse(&mouse(MouseAction::Release, Some(MouseButton::WheelUp), 0, 0), &modes).is_empty());
+    // past column 223 the legacy encoding has no bytes left
+    assert!(encode_mouse(&mouse(MouseAction::Press, Some(MouseButton::Left), 300, 0), &modes).is_empty());
+}
+
+#[test]
+fn selected_text() {
+    let mut screen = Screen::new(10, 4);
+    let mut parser = SequenceParser::new();
+    for seq in parser.feed(b"one   two\r\nthreefourfiveX\r\nsix") {
+        screen.process(&seq);
+    }
+
+    let mut selection = Selection::new(0, 6);
+    selection.extend(3, 1);
+    assert_eq!(screen.selection_text(&selection), "two\nthreefourfiveX\nsi");
+
+    // dragging backwards selects the same text
+    let backwards = Selection { anchor: (3, 1), head: (0, 6) };
+    assert_eq!(screen.selection_text(&backwards), "two\nthreefourfiveX\nsi");
+    assert_eq!(selection.columns(1, 10), Some(0..10));
+    assert_eq!(selection.columns(3, 10), Some(0..2));
+    assert_eq!(selection.columns(4, 10), None);
+    assert!(Selection::new(1, 1).is_empty());
+}
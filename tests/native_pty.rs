@@ -0,0 +1,118 @@
+#![cfg(feature = "native-pty")]
+
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use portable_pty::{CommandBuilder, PtySize, PtySystem};
+
+use fi::pty::exit::ChildExit;
+use fi::pty::native::NativePtySystem;
+use fi::pty::poll::PollSet;
+
+fn size(cols: u16, rows: u16) -> PtySize {
+    PtySize { rows, cols, pixel_width: 0, pixel_height: 0 }
+}
+
+/// Everything the child writes until it exits.
+fn run(script: &str, pty_size: PtySize) -> (String, ChildExit) {
+    let pair = NativePtySystem.openpty(pty_size).unwrap();
+    let mut cmd = CommandBuilder::new("sh");
+    cmd.args(["-c", script]);
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+    drop(pair.slave);
+
+    let mut reader = pair.master.try_clone_reader().unwrap();
+    let fd = pair.master.as_raw_fd().unwrap();
+    let started = Instant::now();
+    let mut out = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+        let mut poll = PollSet::new();
+        let pty = poll.add(fd);
+        poll.wait(Some(Duration::from_millis(50))).unwrap();
+        if !(poll.readable(pty) || poll.hung_up(pty)) {
+            continue;
+        }
+        // EIO once the child and with it the last slave fd is gone
+        match reader.read(&mut buf) {
+            Ok(0) | Err(_) => {break},
+            Ok(n) => {out.extend_from_slice(&buf[..n])}
+        }
+    }
+    let exit = ChildExit::from_status(&child.wait().unwrap());
+
+    (String::from_utf8_lossy(&out).replace("\r\n", "\n"), exit)
+}
+
+#[test]
+fn child_gets_the_pty_as_controlling_terminal() {
+    let (out, exit) = run("tty; test -t 0 && test -t 1 && test -t 2 && echo stdio; ps -o sid= -p $$; echo $$", size(80, 24));
+    let lines: Vec<&str> = out.lines().map(str::trim).collect();
+
+    assert!(lines[0].starts_with("/dev/pts/"), "{:?}", lines);
+    assert_eq!(lines[1], "stdio");
+    // setsid made the shell its own session leader
+    assert_eq!(lines[2], lines[3]);
+    assert!(exit.success());
+}
+
+#[test]
+fn window_size_is_set_and_resizable() {
+    let (out, _) = run("stty size", size(100, 30));
+    assert_eq!(out.trim(), "30 100");
+
+    let pair = NativePtySystem.openpty(size(10, 5)).unwrap();
+    pair.master.resize(size(120, 40)).unwrap();
+    assert_eq!(pair.master.get_size().unwrap(), size(120, 40));
+}
+
+#[test]
+fn only_stdio_is_inherited() {
+    let (out, _) = run("ls /proc/$$/fd", size(80, 24));
+    let fds: Vec<&str> = out.split_whitespace().collect();
+
+    assert_eq!(fds, vec!["0", "1", "2"]);
+}
+
+#[test]
+fn exit_codes_and_signals_are_reported() {
+    assert_eq!(run("exit 5", size(80, 24)).1, ChildExit { code: 5, signal: None });
+    assert_eq!(run("kill -KILL $$", size(80, 24)).1.signal.as_deref(), Some("Killed"));
+}
+
+#[test]
+fn exec_failures_are_errors_not_exits() {
+    let pair = NativePtySystem.openpty(size(80, 24)).unwrap();
+
+    assert!(pair.slave.spawn_command(CommandBuilder::new("fi-no-such-program")).is_err());
+    let mut cmd = CommandBuilder::new("/bin/true");
+    cmd.cwd("/nonexistent/fi-test");
+    assert!(pair.slave.spawn_command(cmd).is_err());
+}
+
+#[test]
+fn writer_can_only_be_taken_once() {
+    let pair = NativePtySystem.openpty(size(80, 24)).unwrap();
+
+    assert!(pair.master.take_writer().is_ok());
+    assert!(pair.master.take_writer().is_err());
+}
+
+#[test]
+fn kill_hangs_up_without_waiting() {
+    let pair = NativePtySystem.openpty(size(80, 24)).unwrap();
+    let mut cmd = CommandBuilder::new("sh");
+    cmd.args(["-c", "trap '' HUP; sleep 5"]);
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    child.kill().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(20));
+    // the hangup was ignored, it is up to the caller to do more
+    assert!(child.try_wait().unwrap().is_none());
+
+    unsafe { libc::kill(child.process_id().unwrap() as libc::pid_t, libc::SIGKILL) };
+    assert_eq!(ChildExit::from_status(&child.wait().unwrap()).signal.as_deref(), Some("Killed"));
+}
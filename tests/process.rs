@@ -0,0 +1,68 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+use fi::pty::guard::hang_up;
+use fi::pty::process::ProcessInfo;
+
+#[test]
+fn own_process_is_read_from_proc() {
+    let info = ProcessInfo::from_pid(std::process::id() as libc::pid_t).unwrap();
+
+    assert_eq!(info.pid, std::process::id() as libc::pid_t);
+    assert_eq!(info.cwd, Some(std::env::current_dir().unwrap()));
+    assert_eq!(info.argv[0], std::env::args().next().unwrap());
+    assert!(!info.name.is_empty() && info.name.len() <= 15);
+}
+
+#[test]
+fn command_line_drops_the_path_and_login_dash() {
+    let mut info = ProcessInfo {
+        pid: 1,
+        name: String::from("vim"),
+        argv: vec![String::from("/usr/bin/vim"), String::from("src/main.rs")],
+        cwd: None,
+        is_shell: false,
+    };
+    assert_eq!(info.command_line(), "vim src/main.rs");
+    assert!(info.has_running_job());
+
+    info.argv = vec![String::from("-bash")];
+    info.is_shell = true;
+    assert_eq!(info.command_line(), "bash");
+    assert!(!info.has_running_job());
+
+    info.argv.clear();
+    assert_eq!(info.command_line(), "vim");
+}
+
+#[test]
+fn foreground_job_is_told_apart_from_the_shell() {
+    let pair = native_pty_system().openpty(PtySize::default()).unwrap();
+    let fd = pair.master.as_raw_fd().unwrap();
+    let mut cmd = CommandBuilder::new("/bin/sh");
+    // interactive so the shell puts the job in its own foreground group
+    cmd.args(["-i", "-c", "sleep 30; true"]);
+    cmd.cwd("/tmp");
+    let mut child = pair.slave.spawn_command(cmd).unwrap();
+
+    let started = Instant::now();
+    let mut job = ProcessInfo::foreground(fd, child.process_id());
+    while started.elapsed() < Duration::from_secs(5) {
+        if let Ok(info) = &job {
+            if info.name == "sleep" {
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(20));
+        job = ProcessInfo::foreground(fd, child.process_id());
+    }
+    hang_up(child.process_id(), Some(fd));
+    child.wait().unwrap();
+
+    let job = job.unwrap();
+    assert_eq!(job.command_line(), "sleep 30");
+    assert_eq!(job.cwd.as_deref(), Some(std::path::Path::new("/tmp")));
+    assert!(job.has_running_job());
+}
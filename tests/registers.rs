@@ -0,0 +1,59 @@
+use fi::ascii::input::{InputEvent, Key, KeyEvent};
+use fi::pty::registers::Registers;
+
+fn key(c: char) -> InputEvent {
+    InputEvent::Key(KeyEvent::plain(Key::Char(c)))
+}
+
+#[test]
+fn records_and_replays_named_registers() {
+    let mut registers = Registers::new();
+    registers.record(&key('x'));
+    assert!(registers.start('a'));
+    assert_eq!(registers.recording(), Some('a'));
+    registers.record(&key('l'));
+    registers.record(&InputEvent::Paste(b"s -la\r".to_vec()));
+    assert_eq!(registers.stop(), Some('a'));
+    assert_eq!(registers.stop(), None);
+    registers.record(&key('y'));
+
+    let recorded = vec![key('l'), InputEvent::Paste(b"s -la\r".to_vec())];
+    assert_eq!(registers.get('a'), Some(recorded.as_slice()));
+    assert_eq!(registers.replay('b'), None);
+    assert_eq!(registers.replay('@'), None);
+    assert_eq!(registers.replay('a'), Some(recorded.clone()));
+    assert_eq!(registers.replay('@'), Some(recorded));
+    assert!(!registers.start('"'));
+}
+
+#[test]
+fn capitals_add_to_a_register() {
+    let mut registers = Registers::new();
+    registers.start('a');
+    registers.record(&key('1'));
+    // starting another recording keeps the one before
+    registers.start('A');
+    registers.record(&key('2'));
+    registers.stop();
+
+    assert_eq!(registers.get('a'), Some([key('1'), key('2')].as_slice()));
+    assert_eq!(registers.get('A'), registers.get('a'));
+}
+
+#[test]
+fn the_register_being_recorded_is_not_replayed() {
+    let mut registers = Registers::new();
+    registers.start('a');
+    registers.record(&key('1'));
+    registers.start('b');
+    registers.record(&key('2'));
+    assert_eq!(registers.replay('a'), Some(vec![key('1')]));
+    registers.start('A');
+
+    // neither by name nor as the last one replayed
+    assert_eq!(registers.replay('a'), None);
+    assert_eq!(registers.replay('@'), None);
+    registers.stop();
+    assert_eq!(registers.get('a'), Some([key('1')].as_slice()));
+    assert_eq!(registers.replay('@'), Some(vec![key('1')]));
+}
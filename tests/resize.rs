@@ -0,0 +1,96 @@
+use std::borrow::Cow;
+
+use fi::ascii::parser::parse_sequences;
+use fi::screen::grid::Screen;
+
+fn screen_with(cols: usize, rows: usize, input: &str) -> Screen {
+    let mut screen = Screen::new(cols, rows);
+    feed(&mut screen, input);
+    screen
+}
+
+fn feed(screen: &mut Screen, input: &str) {
+    for seq in parse_sequences(Cow::from(input)) {
+        screen.process(&seq);
+    }
+}
+
+fn row_text(screen: &Screen, row: usize) -> String {
+    screen.row(row).unwrap().text().trim_end().to_owned()
+}
+
+fn line_text(screen: &Screen, line: usize) -> String {
+    screen.line(line).unwrap().text().trim_end().to_owned()
+}
+
+#[test]
+fn shrinking_under_the_cursor_scrolls_rows_into_scrollback() {
+    let mut screen = screen_with(10, 4, "1\r\n2\r\n3\r\n4");
+    screen.resize(10, 2);
+
+    assert_eq!(screen.rows(), 2);
+    assert_eq!(screen.cursor(), (1, 1));
+    assert_eq!((row_text(&screen, 0), row_text(&screen, 1)), (String::from("3"), String::from("4")));
+    assert_eq!(screen.scrollback_len(), 2);
+    assert_eq!(line_text(&screen, 0), "1");
+    assert_eq!(line_text(&screen, 1), "2");
+}
+
+#[test]
+fn shrinking_drops_blank_rows_below_the_cursor_first() {
+    let mut screen = screen_with(10, 4, "a\r\nb");
+    screen.resize(10, 2);
+
+    assert_eq!(screen.scrollback_len(), 0);
+    assert_eq!(screen.cursor(), (1, 1));
+    assert_eq!(row_text(&screen, 0), "a");
+}
+
+#[test]
+fn wide_char_cut_by_the_new_margin_is_blanked() {
+    let mut screen = screen_with(6, 2, "abcd世");
+    assert!(screen.pending_wrap());
+    screen.resize(5, 2);
+
+    assert_eq!(screen.row(0).unwrap().text(), "abcd ");
+    assert_eq!(screen.cell(0, 4).unwrap().width, 1);
+    assert_eq!(screen.cursor(), (0, 4));
+    assert!(!screen.pending_wrap());
+
+    // nothing left over to wrap once it grows back
+    screen.resize(8, 2);
+    assert_eq!(screen.row(0).unwrap().text(), "abcd    ");
+}
+
+#[test]
+fn saved_cursor_and_scroll_region_are_clamped() {
+    let mut screen = screen_with(10, 6, "\x1b[2;5r\x1b[6;9H\x1b7");
+    screen.resize(5, 3);
+
+    assert_eq!(screen.scroll_region(), (0, 2));
+    assert_eq!(screen.cursor(), (2, 4));
+
+    feed(&mut screen, "\x1b[H\x1b8");
+    assert_eq!(screen.cursor(), (2, 4));
+}
+
+#[test]
+fn resizing_on_the_alt_screen_keeps_the_normal_screen() {
+    let mut screen = screen_with(10, 4, "1\r\n2\r\n3\r\n4\x1b[?1049h\x1b[Halt");
+    screen.resize(10, 2);
+
+    assert!(screen.modes().alt_screen);
+    assert_eq!(row_text(&screen, 0), "alt");
+    // only the normal screen has scrollback
+    assert_eq!(screen.scrollback_len(), 2);
+    assert_eq!(line_text(&screen, 0), "1");
+
+    screen.resize(10, 3);
+    feed(&mut screen, "\x1b[?1049l");
+    assert!(!screen.modes().alt_screen);
+    assert_eq!(screen.rows(), 3);
+    assert_eq!(row_text(&screen, 0), "3");
+    assert_eq!(row_text(&screen, 1), "4");
+    assert_eq!(row_text(&screen, 2), "");
+    assert_eq!(screen.cursor(), (1, 1));
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+use fi::pty::poll::PollSet;
+use fi::pty::session::{Session, SessionManager};
+use fi::pty::spawn::SpawnSpec;
+use fi::screen::modes::{Modes, MouseTracking};
+
+fn sh(script: &str) -> SpawnSpec {
+    SpawnSpec::program("/bin/sh", vec![String::from("-c"), String::from(script)])
+}
+
+/// Feeds the session's output to its screen until `done` holds.
+fn pump(session: &mut Session, done: impl Fn(&Session) -> bool) {
+    let started = Instant::now();
+    let mut buf = [0u8; 4096];
+    while !done(session) {
+        assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+        let mut poll = PollSet::new();
+        let pty = poll.add(session.pty_fd());
+        poll.wait(Some(Duration::from_millis(50))).unwrap();
+        if poll.readable(pty) {
+            let n = session.read(&mut buf).unwrap();
+            session.process(&buf[..n]);
+        }
+    }
+}
+
+fn first_line(session: &Session) -> String {
+    session.screen().row(0).unwrap().text().trim_end().to_owned()
+}
+
+#[test]
+fn sessions_keep_their_own_screens() {
+    let mut sessions = SessionManager::new();
+    let a = sessions.create(sh("printf one; sleep 5"), 20, 4).unwrap();
+    let b = sessions.create(sh("printf two; sleep 5"), 30, 6).unwrap();
+
+    pump(sessions.get_mut(a).unwrap(), |s| first_line(s) == "one");
+    pump(sessions.get_mut(b).unwrap(), |s| first_line(s) == "two");
+
+    assert_ne!(a, b);
+    assert_eq!(sessions.ids(), vec![a, b]);
+    assert_eq!(sessions.get(a).unwrap().screen().cols(), 20);
+    assert_eq!(sessions.get(b).unwrap().screen().cols(), 30);
+    assert_eq!(first_line(sessions.get(a).unwrap()), "one");
+}
+
+#[test]
+fn metadata_follows_what_the_program_reports() {
+    let mut sessions = SessionManager::new();
+    let mut spawn = sh("printf '\\033]2;build\\007\\033]7;file:///tmp\\007ok'; sleep 5");
+    spawn.cwd = Some(std::env::temp_dir());
+    let id = sessions.create(spawn, 20, 4).unwrap();
+    let before = sessions.list()[0].clone();
+
+    assert_eq!(before.id, id);
+    assert!(before.title.starts_with("/bin/sh -c"));
+    assert!(before.created <= std::time::SystemTime::now());
+    assert_eq!(before.exited, None);
+
+    pump(sessions.get_mut(id).unwrap(), |s| first_line(s) == "ok");
+    let after = sessions.list()[0].clone();
+    assert_eq!(after.title, "build");
+    assert_eq!(after.cwd, Some(std::path::PathBuf::from("/tmp")));
+    assert_eq!(after.created, before.created);
+}
+
+#[test]
+fn destroyed_sessions_are_gone_and_ids_are_not_reused() {
+    let mut sessions = SessionManager::new();
+    let a = sessions.create(sh("sleep 5"), 10, 2).unwrap();
+    let b = sessions.create(sh("sleep 5"), 10, 2).unwrap();
+    let c = sessions.create(sh("sleep 5"), 10, 2).unwrap();
+
+    assert_eq!(sessions.next_after(c, true), Some(a));
+    assert_eq!(sessions.next_after(a, false), Some(c));
+
+    assert!(sessions.destroy(b));
+    assert!(!sessions.destroy(b));
+    assert!(sessions.get(b).is_none());
+    assert_eq!(sessions.next_after(a, true), Some(c));
+
+    let d = sessions.create(sh("sleep 5"), 10, 2).unwrap();
+    assert!(d > c);
+    assert_eq!(sessions.len(), 3);
+}
+
+#[test]
+fn destroying_does_not_wait_for_the_child() {
+    let mut sessions = SessionManager::new();
+    let id = sessions.create(sh("trap '' HUP; sleep 0.5"), 10, 2).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    assert!(sessions.destroy(id));
+    assert!(started.elapsed() < Duration::from_millis(20));
+
+    // the child ignored the hangup and is reaped once it gets to exit
+    sessions.reap_orphans();
+    assert_eq!(sessions.orphans(), 1);
+    std::thread::sleep(Duration::from_millis(600));
+    sessions.reap_orphans();
+    assert_eq!(sessions.orphans(), 0);
+}
+
+#[test]
+fn exits_are_reaped_once() {
+    let mut sessions = SessionManager::new();
+    let id = sessions.create(sh("exit 4"), 10, 2).unwrap();
+    let session = sessions.get_mut(id).unwrap();
+
+    let started = Instant::now();
+    let exit = loop {
+        if let Some(exit) = session.reap().unwrap() {
+            break exit;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    assert_eq!(exit.code, 4);
+    assert_eq!(session.exit_status(), Some(&exit));
+    assert_eq!(session.reap().unwrap(), None);
+    // writing to a dead child is dropped instead of blocking
+    session.write(b"ignored").unwrap();
+}
+
+#[test]
+fn respawned_programs_start_from_default_modes() {
+    let marker = std::env::temp_dir().join(format!("fi-respawn-{}", std::process::id()));
+    let _ = std::fs::remove_file(&marker);
+    // the first run leaves modes on and a sequence half written
+    let script = format!(
+        "if [ -e {0} ]; then printf second; else touch {0}; printf '\\033[?1000h\\033[?1h\\033[?2004h\\033[?1049hfirst\\033['; fi; sleep 5",
+        marker.display()
+    );
+    let mut sessions = SessionManager::new();
+    let id = sessions.create(sh(&script), 20, 4).unwrap();
+    let session = sessions.get_mut(id).unwrap();
+    pump(session, |s| first_line(s) == "first");
+
+    let modes = session.screen().modes();
+    assert_eq!(modes.mouse, MouseTracking::Clicks);
+    assert!(modes.app_cursor && modes.bracketed_paste && modes.alt_screen);
+
+    session.respawn().unwrap();
+    assert_eq!(session.screen().modes(), Modes::default());
+    // nothing of the old half sequence swallows the new output
+    pump(session, |s| first_line(s) == "second");
+    let _ = std::fs::remove_file(&marker);
+}
@@ -0,0 +1,107 @@
+use std::borrow::Cow;
+
+use fi::ascii::parser::parse_sequences;
+use fi::screen::grid::Screen;
+use fi::screen::snapshot::{SnapshotFormat, SnapshotOptions};
+use fi::screen::style::Color;
+
+fn screen_with(cols: usize, rows: usize, input: &str) -> Screen {
+    let mut screen = Screen::new(cols, rows);
+    for seq in parse_sequences(Cow::from(input)) {
+        screen.process(&seq);
+    }
+    screen
+}
+
+fn styled(format: SnapshotFormat) -> SnapshotOptions {
+    SnapshotOptions { format, ..SnapshotOptions::default() }
+}
+
+#[test]
+fn scrolled_lines_land_in_scrollback() {
+    let screen = screen_with(10, 3, "one\r\ntwo\r\nthree\r\nfour");
+
+    assert_eq!(screen.scrollback_len(), 1);
+    assert_eq!(screen.visible_text(), "two\nthree\nfour");
+    assert_eq!(screen.all_text(), "one\ntwo\nthree\nfour");
+}
+
+#[test]
+fn untrimmed_rows_keep_padding() {
+    let screen = screen_with(4, 2, "ab\r\nc");
+    let options = SnapshotOptions { trim_trailing: false, ..SnapshotOptions::default() };
+
+    assert_eq!(screen.snapshot(&options), "ab  \nc   ");
+}
+
+#[test]
+fn soft_wrapped_rows_are_joined() {
+    let screen = screen_with(5, 3, "abcdefgh\r\nnext");
+
+    assert_eq!(screen.visible_text(), "abcdefgh\nnext");
+}
+
+#[test]
+fn line_ranges_select_rows() {
+    let screen = screen_with(10, 4, "zero\r\none\r\ntwo\r\nthree");
+    let options = SnapshotOptions { lines: Some(1..3), ..SnapshotOptions::default() };
+
+    assert_eq!(screen.snapshot(&options), "one\ntwo");
+}
+
+#[test]
+fn ansi_snapshot_round_trips() {
+    let screen = screen_with(20, 2, "\x1b[1;31mred\x1b[0m plain \x1b[38;5;196mx");
+    let ansi = screen.snapshot(&styled(SnapshotFormat::Ansi));
+
+    assert_eq!(ansi, "\x1b[0m\x1b[1m\x1b[31mred\x1b[0m plain \x1b[0m\x1b[38;5;196mx\x1b[0m\n");
+
+    let copy = screen_with(20, 2, &ansi);
+    assert_eq!(copy.visible_text(), screen.visible_text());
+    assert!(copy.cell(0, 0).unwrap().style.bold);
+    assert_eq!(copy.cell(0, 0).unwrap().style.fg, Color::Indexed(1));
+    assert_eq!(copy.cell(0, 10).unwrap().style.fg, Color::Indexed(196));
+}
+
+#[test]
+fn html_snapshot_escapes_and_styles() {
+    let screen = screen_with(20, 1, "<a & b> \x1b[32mok");
+    let html = screen.snapshot(&styled(SnapshotFormat::Html));
+
+    assert_eq!(
+        html,
+        "<pre class=\"fi-snapshot\">&lt;a &amp; b&gt; <span style=\"color:#00cd00\">ok</span></pre>"
+    );
+}
+
+#[test]
+fn html_keeps_coloured_trailing_blanks() {
+    let screen = screen_with(4, 1, "\x1b[41m  ");
+    let html = screen.snapshot(&styled(SnapshotFormat::Html));
+
+    assert_eq!(html, "<pre class=\"fi-snapshot\"><span style=\"background:#cd0000\">  </span></pre>");
+}
+
+#[test]
+fn truecolor_in_either_notation() {
+    let screen = screen_with(20, 1, "\x1b[38;2;1;2;3;1mhi\x1b[0;38:2::255:128:0;48:5:17mx\x1b[48:2:4:5:6my\x1b[38;2;9mz");
+    let style = |col| screen.cell(0, col).unwrap().style;
+
+    assert_eq!(style(0).fg, Color::Rgb(1, 2, 3));
+    assert!(style(0).bold);
+    assert_eq!((style(2).fg, style(2).bg), (Color::Rgb(255, 128, 0), Color::Indexed(17)));
+    assert_eq!(style(3).bg, Color::Rgb(4, 5, 6));
+    // too few components leave the colour as it was
+    assert_eq!(style(4), style(3));
+
+    let ansi = screen.snapshot(&styled(SnapshotFormat::Ansi));
+    assert!(ansi.starts_with("\x1b[0m\x1b[1m\x1b[38;2;1;2;3mhi"), "{:?}", ansi);
+    let copy = screen_with(20, 1, &ansi);
+    for col in 0..5 {
+        assert_eq!(copy.cell(0, col).unwrap().style, style(col));
+    }
+
+    let html = screen.snapshot(&styled(SnapshotFormat::Html));
+    assert!(html.contains("color:#010203"), "{}", html);
+    assert!(html.contains("background:#040506"), "{}", html);
+}
@@ -0,0 +1,85 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use fi::ascii::escapes::Escape;
+use fi::ascii::parser::parse_sequences;
+use fi::pty::spawn::{default_shell, respawn_cwd, CwdSource, SpawnSpec};
+use fi::screen::grid::Screen;
+
+#[test]
+fn shell_is_started_as_login_shell() {
+    let spec = SpawnSpec::shell();
+
+    assert_eq!(spec.program, default_shell());
+    assert!(spec.login);
+    assert_eq!(spec.argv(), vec![default_shell(), String::from("-l")]);
+}
+
+#[test]
+fn programs_run_with_their_own_args() {
+    let spec = SpawnSpec::program("top", vec![String::from("-d"), String::from("5")]);
+    let cmd = spec.to_command();
+
+    assert!(!spec.login);
+    assert_eq!(cmd.get_argv(), &vec!["top", "-d", "5"]);
+}
+
+#[test]
+fn term_and_overrides_are_applied() {
+    let mut spec = SpawnSpec::program("sh", Vec::new());
+    spec.colorterm = Some(String::from("256color"));
+    spec.env.push((String::from("FI_TEST"), String::from("1")));
+    spec.env.push((String::from("TERM"), String::from("screen")));
+    spec.env_remove.push(String::from("PATH"));
+    spec.cwd = Some(PathBuf::from("/tmp"));
+    let cmd = spec.to_command();
+
+    assert_eq!(cmd.get_env("TERM"), Some(OsStr::new("screen")));
+    assert_eq!(cmd.get_env("COLORTERM"), Some(OsStr::new("256color")));
+    assert_eq!(cmd.get_env("FI_TEST"), Some(OsStr::new("1")));
+    assert_eq!(cmd.get_env("PATH"), None);
+    assert_eq!(cmd.get_cwd().map(|cwd| cwd.as_os_str()), Some(OsStr::new("/tmp")));
+}
+
+#[test]
+fn cleared_environment_keeps_only_what_the_spec_sets() {
+    let mut spec = SpawnSpec::program("/bin/sh", Vec::new());
+    spec.env_clear = true;
+    let cmd = spec.to_command();
+
+    assert_eq!(cmd.get_env("HOME"), None);
+    assert_eq!(cmd.get_env("TERM"), Some(OsStr::new("xterm-256color")));
+    assert_eq!(cmd.get_env("COLORTERM"), None);
+}
+
+#[test]
+fn osc_7_directory_is_decoded() {
+    let mut screen = Screen::new(20, 2);
+    for seq in parse_sequences(Cow::from("\x1b]7;file://host/tmp/a%20b%3B%27c\x07$ ")) {
+        screen.process(&seq);
+    }
+
+    assert_eq!(screen.working_dir(), Some("/tmp/a b;'c"));
+    assert_eq!(
+        Escape::CurrentDirectory(String::from("/tmp/a b;'c")).into_bytes(),
+        b"\x1b]7;file:///tmp/a%20b%3B%27c\x07"
+    );
+}
+
+#[test]
+fn respawn_prefers_the_reported_directory() {
+    let spec = SpawnSpec::program("sh", Vec::new());
+    let tmp = std::env::temp_dir();
+    let missing = PathBuf::from("/nonexistent/fi-test");
+
+    assert_eq!(
+        respawn_cwd(tmp.to_str(), Some(PathBuf::from("/")), &spec),
+        (Some(tmp.clone()), CwdSource::Osc7)
+    );
+    assert_eq!(
+        respawn_cwd(Some("/nonexistent/fi-test"), Some(tmp.clone()), &spec),
+        (Some(tmp), CwdSource::ProcFs)
+    );
+    assert_eq!(respawn_cwd(None, Some(missing), &spec), (None, CwdSource::Fallback));
+}
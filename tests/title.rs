@@ -0,0 +1,26 @@
+use std::borrow::Cow;
+
+use fi::ascii::escapes::{Escape, Sequence};
+use fi::ascii::parser::{parse_bytes, parse_sequences};
+use fi::screen::grid::Screen;
+
+fn screen_with(input: &str) -> Screen {
+    let mut screen = Screen::new(10, 3);
+    for seq in parse_sequences(Cow::from(input)) {
+        screen.process(&seq);
+    }
+    screen
+}
+
+#[test]
+fn titles_are_saved_and_restored() {
+    assert_eq!(parse_bytes(b"\x1b[22;0t\x1b[23t"), vec![
+        Sequence::Escape(vec![Escape::PushTitle]),
+        Sequence::Escape(vec![Escape::PopTitle]),
+    ]);
+    assert_eq!(screen_with("\x1b]2;shell\x07\x1b[22;0t\x1b]2;vim\x07").title(), Some("vim"));
+    assert_eq!(screen_with("\x1b]2;shell\x07\x1b[22;0t\x1b]2;vim\x07\x1b[23;0t").title(), Some("shell"));
+    // nothing saved, nothing to restore
+    assert_eq!(screen_with("\x1b]2;shell\x07\x1b[23;0t").title(), Some("shell"));
+    assert_eq!(screen_with("\x1b[22t\x1b]2;vim\x07\x1b[23t").title(), None);
+}
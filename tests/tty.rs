@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use fi::pty::raw_mode::*;
+
+/// A pty pair, the follower standing in for the host terminal.
+fn open_pty() -> (File, File) {
+    let (mut leader, mut follower) = (0, 0);
+    let size = libc::winsize { ws_row: 24, ws_col: 100, ws_xpixel: 0, ws_ypixel: 0 };
+    let opened = unsafe { libc::openpty(&mut leader, &mut follower, std::ptr::null_mut(), std::ptr::null(), &size) };
+    assert_eq!(opened, 0);
+    unsafe { (File::from_raw_fd(leader), File::from_raw_fd(follower)) }
+}
+
+fn attrs(fd: &impl AsRawFd) -> libc::termios {
+    let mut termios = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::tcgetattr(fd.as_raw_fd(), &mut termios) }, 0);
+    termios
+}
+
+#[test]
+fn raw_cbreak_and_restore() {
+    let (_leader, follower) = open_pty();
+    let before = attrs(&follower);
+    let mut tty = TtyGuard::new(follower.as_raw_fd()).unwrap();
+    assert_eq!(tty.window_size().unwrap(), (100, 24));
+
+    tty.enter_raw().unwrap();
+    let raw = attrs(&follower);
+    assert_eq!(raw.c_lflag & (libc::ICANON | libc::ECHO | libc::ISIG), 0);
+    assert_eq!(raw.c_oflag & libc::OPOST, 0);
+
+    // cbreak starts over from the original settings, not from raw
+    tty.enter_cbreak().unwrap();
+    let cbreak = attrs(&follower);
+    assert_eq!(cbreak.c_lflag & (libc::ICANON | libc::ECHO), 0);
+    assert_ne!(cbreak.c_lflag & libc::ISIG, 0);
+    assert_ne!(cbreak.c_oflag & libc::OPOST, 0);
+
+    tty.enter(TtyMode::Raw, TtyFlags { signals: true, ..TtyFlags::RAW }).unwrap();
+    let raw = attrs(&follower);
+    assert_ne!(raw.c_lflag & libc::ISIG, 0);
+    assert_eq!(raw.c_iflag & libc::IXON, 0);
+
+    tty.restore().unwrap();
+    assert!(!tty.is_changed());
+    let after = attrs(&follower);
+    assert_eq!((after.c_iflag, after.c_oflag, after.c_lflag), (before.c_iflag, before.c_oflag, before.c_lflag));
+
+    // and on drop
+    tty.enter_raw().unwrap();
+    drop(tty);
+    assert_eq!(attrs(&follower).c_lflag, before.c_lflag);
+}
+
+#[test]
+fn errors_are_reported() {
+    let null = File::open("/dev/null").unwrap();
+    let err = TtyGuard::new(null.as_raw_fd()).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ENOTTY));
+}
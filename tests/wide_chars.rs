@@ -0,0 +1,129 @@
+use std::borrow::Cow;
+
+use fi::ascii::parser::parse_sequences;
+use fi::screen::grid::Screen;
+
+// Expected cursor positions below were taken from xterm (patch 390) with
+// the same column counts. Positions are zero based (row, col).
+
+fn screen_with(cols: usize, rows: usize, input: &str) -> Screen {
+    let mut screen = Screen::new(cols, rows);
+    for seq in parse_sequences(Cow::from(input)) {
+        screen.process(&seq);
+    }
+    screen
+}
+
+fn row_text(screen: &Screen, row: usize) -> String {
+    screen.row(row).unwrap().text()
+}
+
+#[test]
+fn wide_char_takes_two_cells() {
+    let screen = screen_with(10, 3, "a世b");
+
+    assert_eq!(screen.cursor(), (0, 4));
+    assert_eq!(screen.cell(0, 1).unwrap().width, 2);
+    assert!(screen.cell(0, 2).unwrap().is_spacer());
+    assert_eq!(screen.cell(0, 3).unwrap().text, "b");
+    assert_eq!(row_text(&screen, 0), "a世b      ");
+}
+
+#[test]
+fn wide_char_in_last_column_wraps() {
+    let screen = screen_with(5, 3, "abcd世");
+
+    assert_eq!(row_text(&screen, 0), "abcd ");
+    assert!(screen.row(0).unwrap().wrapped);
+    assert_eq!(screen.cell(1, 0).unwrap().text, "世");
+    assert_eq!(screen.cursor(), (1, 2));
+}
+
+#[test]
+fn wide_char_filling_margin_defers_wrap() {
+    let mut screen = screen_with(4, 3, "ab世");
+
+    assert_eq!(screen.cursor(), (0, 3));
+    assert!(screen.pending_wrap());
+
+    screen.print('c');
+    assert_eq!(screen.cursor(), (1, 1));
+    assert_eq!(row_text(&screen, 1), "c   ");
+}
+
+#[test]
+fn combining_mark_joins_previous_cell() {
+    let screen = screen_with(10, 3, "e\u{301}x");
+
+    assert_eq!(screen.cell(0, 0).unwrap().text, "e\u{301}");
+    assert_eq!(screen.cell(0, 1).unwrap().text, "x");
+    assert_eq!(screen.cursor(), (0, 2));
+}
+
+#[test]
+fn combining_mark_joins_wide_char() {
+    let screen = screen_with(10, 3, "世\u{301}");
+
+    assert_eq!(screen.cell(0, 0).unwrap().text, "世\u{301}");
+    assert_eq!(screen.cursor(), (0, 2));
+}
+
+#[test]
+fn combining_mark_with_pending_wrap_stays_on_line() {
+    let screen = screen_with(3, 3, "abc\u{301}");
+
+    assert_eq!(screen.cell(0, 2).unwrap().text, "c\u{301}");
+    assert_eq!(screen.cursor(), (0, 2));
+    assert!(screen.pending_wrap());
+}
+
+#[test]
+fn leading_combining_mark_is_dropped() {
+    let screen = screen_with(10, 3, "\u{301}a");
+
+    assert_eq!(screen.cell(0, 0).unwrap().text, "a");
+    assert_eq!(screen.cursor(), (0, 1));
+}
+
+#[test]
+fn zwj_sequence_is_one_cluster() {
+    let family = "👨\u{200D}👩\u{200D}👧";
+    let screen = screen_with(10, 3, &format!("{}x", family));
+
+    assert_eq!(screen.cell(0, 0).unwrap().text, family);
+    assert!(screen.cell(0, 1).unwrap().is_spacer());
+    assert_eq!(screen.cell(0, 2).unwrap().text, "x");
+    assert_eq!(screen.cursor(), (0, 3));
+}
+
+#[test]
+fn skin_tone_modifier_joins_emoji() {
+    let screen = screen_with(10, 3, "👍🏽a");
+
+    assert_eq!(screen.cell(0, 0).unwrap().text, "👍🏽");
+    assert_eq!(screen.cursor(), (0, 3));
+}
+
+#[test]
+fn overwriting_right_half_erases_wide_char() {
+    let screen = screen_with(10, 3, "世\x08a");
+
+    assert_eq!(screen.cursor(), (0, 2));
+    assert_eq!(row_text(&screen, 0), " a        ");
+}
+
+#[test]
+fn overwriting_left_half_erases_wide_char() {
+    let screen = screen_with(10, 3, "世\ra");
+
+    assert_eq!(screen.cursor(), (0, 1));
+    assert_eq!(row_text(&screen, 0), "a         ");
+}
+
+#[test]
+fn backspace_clears_pending_wrap() {
+    let screen = screen_with(3, 3, "abc\x08");
+
+    assert_eq!(screen.cursor(), (0, 1));
+    assert!(!screen.pending_wrap());
+}
@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+
+use fi::pty::writer::PtyWriter;
+
+/// A pipe standing in for the pty, with the smallest buffer Linux allows.
+fn small_pipe() -> (File, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+    unsafe { libc::fcntl(fds[1], libc::F_SETPIPE_SZ, 4096) };
+    unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
+
+fn read_some(reader: &mut File, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn writes_what_fits_and_keeps_the_rest_in_order() {
+    let (mut reader, writer) = small_pipe();
+    let fd = std::os::unix::io::AsRawFd::as_raw_fd(&writer);
+    let mut pty = PtyWriter::new(Box::new(writer), fd, 1 << 20).unwrap();
+
+    let paste: Vec<u8> = (0..20_000).map(|i| b'a' + (i % 26) as u8).collect();
+    assert!(pty.queue(&paste));
+    assert!(pty.queue(b"\x03"));
+    pty.flush().unwrap();
+    let left = pty.pending();
+    assert!(left > 0 && left < paste.len() + 1);
+
+    let mut out = Vec::new();
+    while pty.pending() > 0 {
+        out.extend(read_some(&mut reader, paste.len() + 1 - out.len() - pty.pending()));
+        pty.flush().unwrap();
+    }
+    out.extend(read_some(&mut reader, paste.len() + 1 - out.len()));
+
+    // the key typed after the paste comes after all of it
+    assert_eq!(&out[..paste.len()], &paste[..]);
+    assert_eq!(out[paste.len()], 3);
+}
+
+#[test]
+fn refuses_input_when_full() {
+    let (_reader, writer) = small_pipe();
+    let fd = std::os::unix::io::AsRawFd::as_raw_fd(&writer);
+    let mut pty = PtyWriter::new(Box::new(writer), fd, 100).unwrap();
+
+    // a unit larger than the limit still goes in whole
+    assert!(pty.queue(&[b'x'; 10_000]));
+    pty.flush().unwrap();
+    assert!(pty.is_full());
+    assert!(!pty.queue(b"y"));
+
+    pty.clear();
+    assert_eq!(pty.pending(), 0);
+    assert!(pty.queue(b"y"));
+}
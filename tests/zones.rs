@@ -0,0 +1,67 @@
+use std::borrow::Cow;
+
+use fi::ascii::parser::parse_sequences;
+use fi::screen::grid::Screen;
+
+const PROMPT: &str = "\x1b]133;A\x07$ \x1b]133;B\x07";
+
+fn screen_with(cols: usize, rows: usize, input: &str) -> Screen {
+    let mut screen = Screen::new(cols, rows);
+    for seq in parse_sequences(Cow::from(input)) {
+        screen.process(&seq);
+    }
+    screen
+}
+
+fn session() -> Screen {
+    let input = format!(
+        "{p}ls\r\n\x1b]133;C\x07a\r\nb\r\n\x1b]133;D;0\x07{p}false\r\n\x1b]133;C\x07\x1b]133;D;1\x1b\\{p}",
+        p = PROMPT
+    );
+    screen_with(20, 3, &input)
+}
+
+#[test]
+fn osc_133_marks_become_command_zones() {
+    let screen = session();
+    let commands = screen.commands();
+
+    assert_eq!(commands.len(), 3);
+    assert_eq!(commands[0].prompt_line, 0);
+    assert_eq!(commands[0].output, Some(1..3));
+    assert_eq!(commands[0].exit_status, Some(0));
+    assert_eq!(commands[1].prompt_line, 3);
+    assert_eq!(commands[1].output, Some(4..4));
+    assert_eq!(commands[1].exit_status, Some(1));
+    assert!(!commands[2].finished);
+    assert_eq!(commands[2].output, None);
+}
+
+#[test]
+fn prompts_are_found_through_scrollback() {
+    let screen = session();
+
+    assert!(screen.scrollback_len() > 0);
+    assert_eq!(screen.previous_prompt(4), Some(3));
+    assert_eq!(screen.previous_prompt(3), Some(0));
+    assert_eq!(screen.previous_prompt(0), None);
+    assert_eq!(screen.next_prompt(0), Some(3));
+    assert_eq!(screen.next_prompt(4), None);
+}
+
+#[test]
+fn output_of_a_command_is_extracted() {
+    let screen = session();
+    let first = &screen.commands()[0];
+
+    assert_eq!(screen.command_output(first), Some(String::from("a\nb")));
+    assert_eq!(screen.last_command_output(), Some(String::new()));
+}
+
+#[test]
+fn running_command_output_reaches_the_cursor() {
+    let input = format!("{}make\r\n\x1b]133;C\x07compiling\r\nlinking", PROMPT);
+    let screen = screen_with(20, 5, &input);
+
+    assert_eq!(screen.last_command_output(), Some(String::from("compiling\nlinking")));
+}